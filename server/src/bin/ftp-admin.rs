@@ -0,0 +1,85 @@
+use std::io::{BufRead, BufReader, Error, ErrorKind, Result};
+use std::path::PathBuf;
+use std::process::ExitCode;
+use utils::server_utils::server_config::ServerConfig;
+
+const USAGE: &str = "Usage: ftp-admin [--socket <path>] [--json] [<command>...]";
+
+/// Sends administration commands to a running server through its admin socket.
+/// The command is taken from the arguments, or one per line from the standard input when none is given.
+/// The socket path defaults to the admin_socket_path of the server configuration.
+fn main() -> ExitCode {
+
+    match run() {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(error) => {
+            eprintln!("{}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Runs the commands, returning false if any of them failed.
+///
+fn run() -> Result<bool> {
+
+    let mut socket_path = None;
+    let mut json = false;
+    let mut command = Vec::new();
+
+    let mut arguments = std::env::args().skip(1);
+
+    while let Some(argument) = arguments.next() {
+        match argument.as_str() {
+            "--socket" => socket_path = Some(PathBuf::from(arguments.next().ok_or_else(|| Error::new(ErrorKind::InvalidInput, USAGE))?)),
+            "--json" => json = true,
+            "--help" => {
+                println!("{}", USAGE);
+                return Ok(true);
+            }
+            _ => command.push(argument),
+        }
+    }
+
+    // The configuration is only read without --socket, a missing or invalid one being reported as an error
+    let socket_path = match socket_path {
+        Some(socket_path) => socket_path,
+        None => {
+            ServerConfig::init()?;
+            ServerConfig::get_admin_socket_path()
+                .ok_or_else(|| Error::new(ErrorKind::NotFound, "The server configuration has no admin_socket_path, use --socket <path>"))?
+        }
+    };
+
+    let commands = match command.is_empty() {
+        true => BufReader::new(std::io::stdin()).lines().collect::<Result<Vec<String>>>()?,
+        false => vec![command.join(" ")],
+    };
+
+    let mut succeeded = true;
+
+    for command in commands.iter().filter(|command| !command.trim().is_empty()) {
+
+        let reply = send(&socket_path, command)?;
+
+        match json {
+            true => println!("{}", serde_json::to_string(&reply)?),
+            false => print!("{}", reply),
+        }
+
+        succeeded &= !reply.is_error();
+    }
+
+    Ok(succeeded)
+}
+
+#[cfg(unix)]
+fn send(socket_path: &PathBuf, command: &str) -> Result<utils::server_utils::admin::AdminReply> {
+    utils::server_utils::admin::socket::send_command(socket_path, command)
+}
+
+#[cfg(not(unix))]
+fn send(_socket_path: &PathBuf, _command: &str) -> Result<utils::server_utils::admin::AdminReply> {
+    Err(Error::new(ErrorKind::Unsupported, "The admin socket is not supported on this platform"))
+}
@@ -1,19 +1,156 @@
-use utils::server_utils::file_transfer_server::FileTransferServerBuilder;
-use utils::server_utils::server_config::ServerConfig;
+use std::net::SocketAddrV4;
+use std::path::PathBuf;
+use std::process::ExitCode;
+use clap::{Parser, ValueEnum};
+use utils::constants::CONFIG_PATH_ENV;
+use utils::logging;
+use utils::server_utils::file_transfer_server::{ActiveList, FileTransferServerBuilder};
+use utils::server_utils::server_config::{ConfigOverrides, ServerConfig, StorageConfig};
 
-const DEFAULT_CONFIG_PATH: &str = "./config.json";
+/// File transfer server.
+/// The options given replace the settings of the config file.
+#[derive(Debug, Parser)]
+#[command(version)]
+struct Cli {
+    /// Path of the config file, JSON or TOML [default: ./config.json, used if it exists]
+    #[arg(short, long, env = CONFIG_PATH_ENV)]
+    config: Option<PathBuf>,
 
-fn main() -> std::io::Result<()> {
+    /// Address the command server listens on, such as 0.0.0.0:7878
+    #[arg(short, long)]
+    bind: Option<SocketAddrV4>,
 
+    /// Directory of the served files
+    #[arg(short, long)]
+    data_dir: Option<PathBuf>,
 
-    let server = FileTransferServerBuilder::new()
+    /// First port of the data connections
+    #[arg(long)]
+    first_port: Option<u16>,
+
+    /// Last port of the data connections
+    #[arg(long)]
+    last_port: Option<u16>,
+
+    /// Number of threads handling the clients
+    #[arg(short, long)]
+    threads: Option<usize>,
+
+    /// List the server starts with, replacing the one saved when it last stopped
+    #[arg(short, long, value_enum)]
+    list: Option<ListMode>,
+
+    /// Runs in the foreground without reading commands from the terminal, as under a service manager
+    #[arg(long)]
+    no_console: bool,
+
+    /// Only checks the config file and reports its problems
+    #[arg(long)]
+    check_config: bool,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum ListMode {
+    Ban,
+    White,
+}
+
+impl From<ListMode> for ActiveList {
+    fn from(list_mode: ListMode) -> Self {
+        match list_mode {
+            ListMode::Ban => ActiveList::BanList,
+            ListMode::White => ActiveList::WhiteList,
+        }
+    }
+}
+
+/// Checks the config file, then runs the server until it shuts down.
+/// With --check-config the server only reports whether the config file is valid.
+fn main() -> ExitCode {
+
+    let cli = Cli::parse();
+
+    ServerConfig::set_overrides(ConfigOverrides {
+        config_path: cli.config.clone(),
+        command_address: cli.bind,
+        data_dir_path: cli.data_dir.clone(),
+        first_port: cli.first_port,
+        last_port: cli.last_port,
+        server_num_threads: cli.threads,
+        active_list: cli.list.map(ActiveList::from),
+        stdin_console: cli.no_console.then_some(false),
+    });
+
+    if let Err(error) = ServerConfig::init() {
+        eprintln!("{}", error);
+        return ExitCode::FAILURE;
+    }
+
+    if cli.check_config {
+        println!("Config file {} is valid", ServerConfig::config_path());
+        return ExitCode::SUCCESS;
+    }
+
+    match run(cli.list.is_some()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("{}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(list_given: bool) -> std::io::Result<()> {
+
+    // The log files are flushed once the server stops
+    let _log_guard = logging::init(&ServerConfig::get_logging())?;
+
+    let builder = FileTransferServerBuilder::new()
         .command_server_address(ServerConfig::get_command_address())
         .data_directory(ServerConfig::get_data_dir_path())
-        .activate_ban_list()
+        .active_list(ServerConfig::get_active_list())
         .serialized_lists_directory(ServerConfig::get_serialized_lists_path())
         .load_lists(ServerConfig::get_white_list_file_name().as_str(),ServerConfig::get_ban_list_file_name().as_str())
         .init_port_allocator(ServerConfig::get_first_port(),ServerConfig::get_last_port())
-        .build();
+        .init_audit_log(ServerConfig::get_audit())?
+        .versioning(ServerConfig::get_versioning())
+        .trash(ServerConfig::get_trash())
+        .shares_file_name(ServerConfig::get_shares_file_name().as_str())
+        .groups(ServerConfig::get_groups())
+        .roles(ServerConfig::get_roles())
+        .anonymous(ServerConfig::get_anonymous())
+        .auto_ban(ServerConfig::get_auto_ban())
+        .connection_limits(ServerConfig::get_connection_limits())
+        .admin_socket_path(ServerConfig::get_admin_socket_path())
+        .stdin_console(ServerConfig::get_stdin_console())
+        .maintenance(ServerConfig::get_maintenance());
+
+    // A list chosen on the command line wins over the one saved when the server last stopped
+    let builder = match list_given {
+        true => builder.active_list(ServerConfig::get_active_list()),
+        false => builder,
+    };
+
+    let builder = match ServerConfig::get_storage() {
+
+        StorageConfig::Local => builder,
+
+        #[cfg(feature = "s3")]
+        StorageConfig::S3(config) => builder.storage_backend(utils::storage::s3_backend::S3Backend::new(&config)?),
+
+        #[cfg(not(feature = "s3"))]
+        StorageConfig::S3(_) => {
+            let error = std::io::Error::new(std::io::ErrorKind::Unsupported, "S3 storage requires the server to be built with the s3 feature");
+            return Err(error);
+        }
+    };
+
+    let builder = match ServerConfig::get_deduplicate_storage() {
+        true => builder.deduplicate_storage(),
+        false => builder,
+    };
+
+    let server = builder.build();
 
     server.start()?;
 
@@ -0,0 +1,321 @@
+use std::collections::{BTreeMap, HashMap};
+use std::net::Ipv4Addr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use crate::cidr::Ipv4Cidr;
+
+/// Entry of an access list, listed until its expiry time if it has one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ListEntry {
+    pub cidr: Ipv4Cidr,
+    pub expires: Option<SystemTime>,
+}
+
+impl ListEntry {
+
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        self.expires.is_some_and(|expires| expires <= now)
+    }
+
+    /// Returns the time left before the entry expires, None for permanent entries.
+    ///
+    pub fn remaining(&self, now: SystemTime) -> Option<Duration> {
+        self.expires.map(|expires| expires.duration_since(now).unwrap_or_default())
+    }
+}
+
+/// Serialized form of an entry, permanent entries being saved as bare blocks like older versions did.
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum SavedEntry {
+    Permanent(Ipv4Cidr),
+    Temporary {
+        address: Ipv4Cidr,
+        expires: u64,
+    },
+}
+
+/// Set of addresses and address blocks, used for the white and ban lists.
+/// Blocks are grouped by prefix length, so that finding the most specific block holding an address
+/// takes one lookup per prefix length in use, whatever the number of entries.
+/// Temporary entries stop matching once they expire and are dropped by purge_expired.
+/// Serialized as a list of blocks, which also reads the lists of bare addresses saved by older versions.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(from = "Vec<SavedEntry>", into = "Vec<SavedEntry>")]
+pub struct AccessList {
+    networks: BTreeMap<u8, HashMap<Ipv4Addr, Option<SystemTime>>>,
+}
+
+impl AccessList {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a block to the list permanently.
+    /// Returns false if it was already listed, in which case it becomes permanent.
+    pub fn insert(&mut self, cidr: Ipv4Cidr) -> bool {
+        self.insert_entry(ListEntry { cidr, expires: None })
+    }
+
+    /// Adds a block to the list until the given time.
+    /// Returns false if it was already listed, in which case it keeps the later expiry, permanent entries staying permanent.
+    pub fn insert_until(&mut self, cidr: Ipv4Cidr, expires: SystemTime) -> bool {
+        self.insert_entry(ListEntry { cidr, expires: Some(expires) })
+    }
+
+    fn insert_entry(&mut self, entry: ListEntry) -> bool {
+
+        let networks = self.networks.entry(entry.cidr.prefix_len()).or_default();
+
+        match networks.get_mut(&entry.cidr.network()) {
+            Some(expires) => {
+                *expires = match (*expires, entry.expires) {
+                    (Some(current), Some(new)) => Some(current.max(new)),
+                    _ => None,
+                };
+                false
+            }
+            None => {
+                networks.insert(entry.cidr.network(), entry.expires);
+                true
+            }
+        }
+    }
+
+    /// Removes a block from the list, the blocks it overlaps being kept.
+    /// Returns false if it was not listed.
+    pub fn remove(&mut self, cidr: &Ipv4Cidr) -> bool {
+
+        let networks = match self.networks.get_mut(&cidr.prefix_len()) {
+            Some(networks) => networks,
+            None => return false,
+        };
+
+        let removed = networks.remove(&cidr.network()).is_some();
+
+        if networks.is_empty() {
+            self.networks.remove(&cidr.prefix_len());
+        }
+
+        removed
+    }
+
+    /// Returns the most specific unexpired entry holding the address at the given time.
+    ///
+    pub fn longest_match(&self, address: Ipv4Addr, now: SystemTime) -> Option<ListEntry> {
+        self.networks.iter()
+            .rev()
+            .filter_map(|(prefix_len, networks)| {
+                let cidr = Ipv4Cidr::new(address, *prefix_len).ok()?;
+                let expires = *networks.get(&cidr.network())?;
+                Some(ListEntry { cidr, expires })
+            })
+            .find(|entry| !entry.is_expired(now))
+    }
+
+    /// Checks if the address belongs to any unexpired entry.
+    ///
+    pub fn contains(&self, address: Ipv4Addr) -> bool {
+        self.longest_match(address, SystemTime::now()).is_some()
+    }
+
+    /// Removes the entries expired at the given time.
+    /// Returns the removed entries.
+    pub fn purge_expired(&mut self, now: SystemTime) -> Vec<ListEntry> {
+
+        let expired = self.entries()
+            .into_iter()
+            .filter(|entry| entry.is_expired(now))
+            .collect::<Vec<ListEntry>>();
+
+        for entry in &expired {
+            self.remove(&entry.cidr);
+        }
+
+        expired
+    }
+
+    /// Returns the entries sorted by address, the expired ones included until they are purged.
+    ///
+    pub fn entries(&self) -> Vec<ListEntry> {
+
+        let mut entries = self.networks.iter()
+            .flat_map(|(prefix_len, networks)| {
+                networks.iter().filter_map(|(network, expires)| Some(ListEntry {
+                    cidr: Ipv4Cidr::new(*network, *prefix_len).ok()?,
+                    expires: *expires,
+                }))
+            })
+            .collect::<Vec<ListEntry>>();
+
+        entries.sort_by_key(|entry| entry.cidr);
+
+        entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.networks.values().map(HashMap::len).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.networks.is_empty()
+    }
+}
+
+impl From<Vec<SavedEntry>> for AccessList {
+    fn from(entries: Vec<SavedEntry>) -> Self {
+
+        let mut list = Self::new();
+
+        for entry in entries {
+            match entry {
+                SavedEntry::Permanent(cidr) => list.insert(cidr),
+                // An expiry too far to represent never comes
+                SavedEntry::Temporary { address, expires } => match UNIX_EPOCH.checked_add(Duration::from_secs(expires)) {
+                    Some(expires) => list.insert_until(address, expires),
+                    None => list.insert(address),
+                },
+            };
+        }
+
+        list
+    }
+}
+
+impl From<AccessList> for Vec<SavedEntry> {
+    fn from(list: AccessList) -> Self {
+        list.entries()
+            .into_iter()
+            .map(|entry| match entry.expires {
+                None => SavedEntry::Permanent(entry.cidr),
+                Some(expires) => SavedEntry::Temporary {
+                    address: entry.cidr,
+                    expires: expires.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs(),
+                },
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cidr(cidr: &str) -> Ipv4Cidr {
+        cidr.parse().unwrap()
+    }
+
+    #[test]
+    pub fn test_access_list_1(){
+
+        let mut list = AccessList::new();
+
+        assert!(list.insert(cidr("10.0.0.0/8")));
+        assert!(list.insert(cidr("10.1.0.0/16")));
+        assert!(list.insert(cidr("10.1.2.3")));
+        assert!(!list.insert(cidr("10.0.0.0/8")));
+
+        let longest_match = |address| list.longest_match(address, SystemTime::now()).map(|entry| entry.cidr);
+
+        // The most specific block wins
+        assert_eq!(longest_match(Ipv4Addr::new(10, 1, 2, 3)), Some(cidr("10.1.2.3")));
+        assert_eq!(longest_match(Ipv4Addr::new(10, 1, 9, 9)), Some(cidr("10.1.0.0/16")));
+        assert_eq!(longest_match(Ipv4Addr::new(10, 2, 0, 1)), Some(cidr("10.0.0.0/8")));
+        assert!(!list.contains(Ipv4Addr::new(11, 0, 0, 1)));
+
+        // Removing a block keeps the blocks it overlaps
+        assert!(list.remove(&cidr("10.0.0.0/8")));
+        assert!(!list.remove(&cidr("10.0.0.0/8")));
+        assert!(!list.contains(Ipv4Addr::new(10, 2, 0, 1)));
+        assert!(list.contains(Ipv4Addr::new(10, 1, 0, 1)));
+        assert_eq!(list.len(), 2);
+
+    }
+
+    #[test]
+    pub fn test_access_list_2(){
+
+        // Lists saved before blocks were supported only hold bare addresses
+        let list: AccessList = serde_json::from_str(r#"["127.0.0.1","192.168.0.2"]"#).unwrap();
+
+        assert!(list.contains(Ipv4Addr::new(127, 0, 0, 1)));
+        assert!(!list.contains(Ipv4Addr::new(127, 0, 0, 2)));
+
+        let list: AccessList = serde_json::from_str(r#"["192.168.0.0/16","127.0.0.1","10.*.*.*"]"#).unwrap();
+
+        assert!(list.contains(Ipv4Addr::new(10, 9, 9, 9)));
+        assert_eq!(serde_json::to_string(&list).unwrap(), r#"["10.0.0.0/8","127.0.0.1","192.168.0.0/16"]"#);
+        assert!(AccessList::new().is_empty());
+
+    }
+
+    #[test]
+    pub fn test_access_list_3(){
+
+        let now = UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let client = Ipv4Addr::new(10, 1, 2, 3);
+        let mut list = AccessList::new();
+
+        list.insert(cidr("10.0.0.0/8"));
+        list.insert_until(cidr("10.1.2.3"), now + Duration::from_secs(60));
+        list.insert_until(cidr("192.168.0.1"), now - Duration::from_secs(1));
+
+        assert_eq!(list.longest_match(client, now).unwrap().remaining(now), Some(Duration::from_secs(60)));
+
+        // An expired entry no longer matches, the blocks holding it still do
+        let later = now + Duration::from_secs(60);
+
+        assert_eq!(list.longest_match(client, later).unwrap().cidr, cidr("10.0.0.0/8"));
+        assert!(list.longest_match(Ipv4Addr::new(192, 168, 0, 1), now).is_none());
+
+        let expired = list.purge_expired(later);
+
+        assert_eq!(expired.len(), 2);
+        assert_eq!(list.entries(), vec![ListEntry { cidr: cidr("10.0.0.0/8"), expires: None }]);
+
+    }
+
+    #[test]
+    pub fn test_access_list_4(){
+
+        let mut list = AccessList::new();
+
+        list.insert(cidr("127.0.0.1"));
+        list.insert_until(cidr("10.0.0.0/8"), UNIX_EPOCH + Duration::from_secs(1800));
+
+        let saved = serde_json::to_string(&list).unwrap();
+
+        // Permanent entries keep the format of older versions
+        assert_eq!(saved, r#"[{"address":"10.0.0.0/8","expires":1800},"127.0.0.1"]"#);
+        assert_eq!(serde_json::from_str::<AccessList>(&saved).unwrap(), list);
+
+        let tampered = serde_json::from_str::<AccessList>(r#"[{"address":"10.0.0.0/8","expires":18446744073709551615}]"#).unwrap();
+        assert_eq!(tampered.entries(), vec![ListEntry { cidr: cidr("10.0.0.0/8"), expires: None }]);
+
+    }
+
+    #[test]
+    pub fn test_access_list_5(){
+
+        let now = UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let mut list = AccessList::new();
+
+        // A temporary ban does not shorten a permanent one
+        list.insert(cidr("10.0.0.1"));
+        assert!(!list.insert_until(cidr("10.0.0.1"), now));
+
+        // Nor a longer temporary one, which a permanent ban replaces
+        list.insert_until(cidr("10.0.0.2"), now + Duration::from_secs(60));
+        list.insert_until(cidr("10.0.0.2"), now + Duration::from_secs(30));
+        list.insert_until(cidr("10.0.0.3"), now);
+        list.insert(cidr("10.0.0.3"));
+
+        assert_eq!(list.entries(), vec![
+            ListEntry { cidr: cidr("10.0.0.1"), expires: None },
+            ListEntry { cidr: cidr("10.0.0.2"), expires: Some(now + Duration::from_secs(60)) },
+            ListEntry { cidr: cidr("10.0.0.3"), expires: None },
+        ]);
+
+    }
+}
@@ -0,0 +1,292 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{Result, Write};
+use std::net::SocketAddrV4;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use crate::roles::Role;
+use crate::serialization::format_ipv4;
+use crate::server_utils::server_config::{AuditConfig, AuditFormat};
+use crate::session_registry::Session;
+
+/// What a transfer did to a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TransferDirection {
+    /// Downloaded by the client.
+    Outgoing,
+    /// Uploaded by the client.
+    Incoming,
+    Deleted,
+}
+
+/// Transfer of a file by a session, recorded once it ends.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TransferRecord {
+    /// End of the transfer.
+    pub time: DateTime<Utc>,
+    pub duration_ms: u64,
+    pub session_id: u64,
+    pub peer: SocketAddrV4,
+    pub role: Role,
+    pub direction: TransferDirection,
+    /// Path of the file in the storage.
+    pub file: PathBuf,
+    pub bytes: u64,
+    /// False if the transfer failed or was aborted.
+    pub completed: bool,
+}
+
+impl TransferRecord {
+
+    /// Describes the transfer of the current request of a session, ending now.
+    ///
+    pub fn of(session: &Session, direction: TransferDirection, file: &Path, completed: bool) -> Self {
+        Self {
+            time: Utc::now(),
+            duration_ms: session.request_elapsed().as_millis() as u64,
+            session_id: session.id(),
+            peer: session.peer(),
+            role: session.role(),
+            direction,
+            file: file.to_path_buf(),
+            bytes: session.bytes_transferred(),
+            completed,
+        }
+    }
+
+    /// Formats the record as a wu-ftpd xferlog line, timed in UTC.
+    /// The client directory stands as the user name and the session id as the authenticated user id.
+    /// Whitespace in the file name is replaced with underscores as wu-ftpd does, the fields being separated by spaces.
+    pub fn to_xferlog(&self) -> String {
+
+        let file = self.file.display().to_string().chars()
+            .map(|character| if character.is_whitespace() { '_' } else { character })
+            .collect::<String>();
+
+        let direction = match self.direction {
+            TransferDirection::Outgoing => 'o',
+            TransferDirection::Incoming => 'i',
+            TransferDirection::Deleted => 'd',
+        };
+
+        format!("{} {} {} {} /{} b _ {} {} {} ftp 0 {} {}",
+                self.time.format("%a %b %e %H:%M:%S %Y"),
+                (self.duration_ms + 500) / 1000,
+                self.peer.ip(),
+                self.bytes,
+                file,
+                direction,
+                if self.role.is_anonymous() { 'a' } else { 'r' },
+                format_ipv4(*self.peer.ip()),
+                self.session_id,
+                if self.completed { 'c' } else { 'i' })
+    }
+}
+
+/// Append-only file of transfer records, which records nothing when disabled.
+///
+#[derive(Debug, Default)]
+pub struct AuditLog {
+    config: AuditConfig,
+    file: Mutex<Option<File>>,
+}
+
+impl AuditLog {
+
+    /// Opens the audit file for appending, creating it and its directory if needed.
+    ///
+    pub fn open(config: AuditConfig) -> Result<Self> {
+
+        let file = match config.enabled {
+            true => Some(Self::open_file(&config.path)?),
+            false => None,
+        };
+
+        Ok(Self {
+            config,
+            file: Mutex::new(file),
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// Appends a record as a single line, rotating the file first if the line would make it too large.
+    ///
+    pub fn record(&self, record: &TransferRecord) -> Result<()> {
+
+        let mut file = self.file.lock().unwrap();
+
+        let Some(current) = file.as_mut() else {
+            return Ok(());
+        };
+
+        let mut line = match self.config.format {
+            AuditFormat::Xferlog => record.to_xferlog(),
+            AuditFormat::Json => serde_json::to_string(record)?,
+        };
+        line.push('\n');
+
+        if let Some(max_size_bytes) = self.config.max_size_bytes {
+
+            let size = current.metadata()?.len();
+
+            if size > 0 && size + line.len() as u64 > max_size_bytes {
+                self.rotate()?;
+                *current = Self::open_file(&self.config.path)?;
+            }
+        }
+
+        current.write_all(line.as_bytes())
+    }
+
+    /// Renames the file after the current time, then removes the oldest renamed files beyond max_files if delete_rotated is set.
+    ///
+    fn rotate(&self) -> Result<()> {
+
+        let path = &self.config.path;
+        let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+        let stamp = Utc::now().format("%Y%m%dT%H%M%S%.3f").to_string();
+
+        // Files rotated within the same millisecond are numbered
+        let mut rotated_path = path.with_file_name(format!("{}.{}", name, stamp));
+        let mut count = 0;
+
+        while rotated_path.exists() {
+            count += 1;
+            rotated_path = path.with_file_name(format!("{}.{}-{}", name, stamp, count));
+        }
+
+        fs::rename(path, rotated_path)?;
+
+        let Some(max_files) = self.config.max_files.filter(|_| self.config.delete_rotated) else {
+            return Ok(());
+        };
+
+        let prefix = format!("{}.", name);
+
+        let mut rotated_paths = fs::read_dir(Self::directory_of(path))?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|rotated_path| rotated_path.file_name().is_some_and(|file_name| file_name.to_string_lossy().starts_with(&prefix)))
+            .collect::<Vec<PathBuf>>();
+
+        // The time stamps sort the files oldest first
+        rotated_paths.sort();
+
+        for rotated_path in &rotated_paths[..rotated_paths.len().saturating_sub(max_files)] {
+            fs::remove_file(rotated_path)?;
+        }
+
+        Ok(())
+    }
+
+    fn open_file(path: &Path) -> Result<File> {
+        fs::create_dir_all(Self::directory_of(path))?;
+        OpenOptions::new().create(true).append(true).open(path)
+    }
+
+    fn directory_of(path: &Path) -> &Path {
+        match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use chrono::TimeZone;
+    use crate::session_registry::SessionRegistry;
+
+    fn record(direction: TransferDirection, completed: bool) -> TransferRecord {
+        TransferRecord {
+            time: Utc.with_ymd_and_hms(2024, 5, 1, 9, 5, 3).unwrap(),
+            duration_ms: 2600,
+            session_id: 12,
+            peer: "10.0.0.7:51234".parse().unwrap(),
+            role: Role::Full,
+            direction,
+            file: PathBuf::from("10-0-0-7/report.pdf"),
+            bytes: 48213,
+            completed,
+        }
+    }
+
+    #[test]
+    pub fn test_transfer_record_1(){
+
+        assert_eq!(record(TransferDirection::Outgoing, true).to_xferlog(),
+                   "Wed May  1 09:05:03 2024 3 10.0.0.7 48213 /10-0-0-7/report.pdf b _ o r 10-0-0-7 ftp 0 12 c");
+
+        let record = TransferRecord { role: Role::Anonymous, ..record(TransferDirection::Incoming, false) };
+
+        assert!(record.to_xferlog().ends_with(" i a 10-0-0-7 ftp 0 12 i"));
+
+        // Every field stays in place whatever the file name
+        let spaced = TransferRecord { file: PathBuf::from("10-0-0-7/q1 report\t.pdf"), ..record.clone() };
+        let fields = spaced.to_xferlog().split(' ').map(str::to_string).collect::<Vec<String>>();
+
+        assert_eq!(fields.len(), record.to_xferlog().split(' ').count());
+        assert_eq!(fields[9], "/10-0-0-7/q1_report_.pdf");
+
+        let json = serde_json::to_string(&record).unwrap();
+
+        assert!(json.starts_with(r#"{"time":"2024-05-01T09:05:03Z","duration_ms":2600,"session_id":12,"peer":"10.0.0.7:51234","role":"anonymous","direction":"incoming""#));
+        assert_eq!(serde_json::from_str::<TransferRecord>(&json).unwrap(), record);
+
+        // The session gives its identity and the bytes of its request
+        let sessions = Arc::new(SessionRegistry::new());
+        let session = sessions.register("10.0.0.7:51234".parse().unwrap(), Role::ReadOnly);
+
+        session.set_request("GET", Some("report.pdf"));
+        session.add_bytes(512);
+
+        let record = TransferRecord::of(&session, TransferDirection::Outgoing, Path::new("10-0-0-7/report.pdf"), true);
+
+        assert_eq!((record.session_id, record.role, record.bytes), (session.id(), Role::ReadOnly, 512));
+
+    }
+
+    #[test]
+    pub fn test_audit_log_1(){
+
+        let directory = std::env::temp_dir().join(format!("test_audit_log_{}", std::process::id()));
+        let path = directory.join("audit").join("transfers.jsonl");
+
+        let line_length = serde_json::to_string(&record(TransferDirection::Deleted, true)).unwrap().len() as u64 + 1;
+
+        // Two records per file, the current one and the two newest rotated files being kept
+        let audit_log = AuditLog::open(AuditConfig {
+            enabled: true,
+            path: path.clone(),
+            format: AuditFormat::Json,
+            max_size_bytes: Some(2 * line_length),
+            max_files: Some(2),
+            delete_rotated: true,
+        }).unwrap();
+
+        for _ in 0..7 {
+            audit_log.record(&record(TransferDirection::Deleted, true)).unwrap();
+        }
+
+        let file_count = fs::read_dir(path.parent().unwrap()).unwrap().count();
+        let contents = fs::read_to_string(&path).unwrap();
+
+        assert_eq!(file_count, 3);
+        assert_eq!(contents.lines().count(), 1);
+        assert_eq!(serde_json::from_str::<TransferRecord>(contents.trim_end()).unwrap(), record(TransferDirection::Deleted, true));
+
+        fs::remove_dir_all(directory).unwrap();
+
+        // Nothing is written when disabled
+        assert!(!AuditLog::default().is_enabled());
+        assert!(AuditLog::default().record(&record(TransferDirection::Deleted, true)).is_ok());
+
+    }
+}
@@ -0,0 +1,255 @@
+use std::collections::{HashMap, VecDeque};
+use std::fmt;
+use std::net::Ipv4Addr;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+use serde::{Deserialize, Serialize};
+use tracing::error;
+use crate::access_list::AccessList;
+use crate::cidr::Ipv4Cidr;
+use crate::list_store::ListStore;
+use crate::server_utils::server_config::AutoBanConfig;
+
+/// Client behaviour counted by the automatic ban rules.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Offence {
+    /// Accepted connection.
+    Connection,
+    /// Command which is not a known verb.
+    UnrecognizedCommand,
+    /// Request refused because of the role of the client or the access rules of a file.
+    AccessDenied,
+}
+
+impl fmt::Display for Offence {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Offence::Connection => formatter.write_str("connection"),
+            Offence::UnrecognizedCommand => formatter.write_str("unrecognized_command"),
+            Offence::AccessDenied => formatter.write_str("access_denied"),
+        }
+    }
+}
+
+/// Bans a client once it commits max_count offences of the same kind within window_secs,
+/// for ban_secs or permanently when no ban duration is set.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BanRule {
+    pub offence: Offence,
+    pub max_count: usize,
+    pub window_secs: u64,
+    #[serde(default)]
+    pub ban_secs: Option<u64>,
+}
+
+impl BanRule {
+    fn window(&self) -> Duration {
+        Duration::from_secs(self.window_secs)
+    }
+}
+
+/// Ban added to the ban list by a rule.
+#[derive(Debug, Clone, PartialEq)]
+pub struct AutoBan {
+    pub client_ip: Ipv4Addr,
+    pub rule: BanRule,
+}
+
+/// Keeps the recent offences of each client and adds the clients breaking a rule to the ban list.
+#[derive(Debug)]
+pub struct AutoBanner {
+    rules: RwLock<Vec<BanRule>>,
+    ban_list: Arc<RwLock<AccessList>>,
+    list_store: Arc<ListStore>,
+    offences: Mutex<HashMap<(Ipv4Addr, Offence), VecDeque<Instant>>>,
+}
+
+impl AutoBanner {
+
+    pub fn new(config: AutoBanConfig, ban_list: Arc<RwLock<AccessList>>, list_store: Arc<ListStore>) -> Self {
+        Self {
+            rules: RwLock::new(config.rules),
+            ban_list,
+            list_store,
+            offences: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Replaces the rules, the offences already recorded counting towards the new ones.
+    ///
+    pub fn set_rules(&self, config: AutoBanConfig) {
+        *self.rules.write().unwrap() = config.rules;
+    }
+
+    /// Records an offence of a client happening now.
+    /// Returns the ban if the client was banned because of it.
+    pub fn record(&self, client_ip: Ipv4Addr, offence: Offence) -> Option<AutoBan> {
+        self.record_at(client_ip, offence, Instant::now())
+    }
+
+    /// Records an offence of a client happening at the given time.
+    /// When several rules are broken at once the longest ban applies, permanent bans first.
+    /// The ban list is saved right away.
+    pub fn record_at(&self, client_ip: Ipv4Addr, offence: Offence, now: Instant) -> Option<AutoBan> {
+
+        let rules = self.rules.read().unwrap();
+        let rules = rules.iter().filter(|rule| rule.offence == offence).collect::<Vec<&BanRule>>();
+
+        if rules.is_empty() {
+            return None;
+        }
+
+        let longest_window = rules.iter().map(|rule| rule.window()).max().unwrap_or_default();
+
+        let mut offences = self.offences.lock().unwrap();
+        let times = offences.entry((client_ip, offence)).or_default();
+
+        times.push_back(now);
+        Self::forget_before(times, now, longest_window);
+
+        let rule = rules.into_iter()
+            .filter(|rule| times.iter().filter(|time| now.duration_since(**time) <= rule.window()).count() >= rule.max_count)
+            .max_by_key(|rule| rule.ban_secs.unwrap_or(u64::MAX))?;
+
+        // The client starts over with a clean record once its ban is lifted
+        offences.retain(|(ip, _), _| *ip != client_ip);
+        drop(offences);
+
+        let cidr = Ipv4Cidr::host(client_ip);
+        let mut ban_list = self.ban_list.write().unwrap();
+
+        // A ban too long to represent is never lifted
+        match rule.ban_secs.and_then(|ban_secs| SystemTime::now().checked_add(Duration::from_secs(ban_secs))) {
+            Some(expires) => ban_list.insert_until(cidr, expires),
+            None => ban_list.insert(cidr),
+        };
+
+        if let Err(error) = self.list_store.save_ban_list(&ban_list) {
+            error!("Failed to save the ban list: {}", error);
+        }
+
+        Some(AutoBan {
+            client_ip,
+            rule: rule.clone(),
+        })
+    }
+
+    /// Drops the offences which are too old to count towards any rule.
+    ///
+    pub fn forget_stale(&self, now: Instant) {
+
+        let rules = self.rules.read().unwrap();
+        let mut offences = self.offences.lock().unwrap();
+
+        for ((_, offence), times) in offences.iter_mut() {
+
+            let longest_window = rules.iter()
+                .filter(|rule| rule.offence == *offence)
+                .map(|rule| rule.window())
+                .max()
+                .unwrap_or_default();
+
+            Self::forget_before(times, now, longest_window);
+        }
+
+        offences.retain(|_, times| !times.is_empty());
+    }
+
+    fn forget_before(times: &mut VecDeque<Instant>, now: Instant, window: Duration) {
+        while times.front().is_some_and(|time| now.duration_since(*time) > window) {
+            times.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CLIENT: Ipv4Addr = Ipv4Addr::new(127, 0, 0, 2);
+
+    fn auto_banner(rules: Vec<BanRule>) -> (Arc<RwLock<AccessList>>, AutoBanner) {
+        let ban_list = Arc::new(RwLock::new(AccessList::new()));
+        (Arc::clone(&ban_list), AutoBanner::new(AutoBanConfig { rules }, ban_list, Arc::new(ListStore::default())))
+    }
+
+    fn rule(offence: Offence, max_count: usize, window_secs: u64, ban_secs: Option<u64>) -> BanRule {
+        BanRule { offence, max_count, window_secs, ban_secs }
+    }
+
+    #[test]
+    pub fn test_auto_banner_1(){
+
+        let (ban_list, banner) = auto_banner(vec![rule(Offence::UnrecognizedCommand, 3, 10, Some(60))]);
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        // Offences spread over more than the window are not enough
+        assert!(banner.record_at(CLIENT, Offence::UnrecognizedCommand, at(0)).is_none());
+        assert!(banner.record_at(CLIENT, Offence::UnrecognizedCommand, at(8)).is_none());
+        assert!(banner.record_at(CLIENT, Offence::UnrecognizedCommand, at(15)).is_none());
+        assert!(banner.record_at(CLIENT, Offence::AccessDenied, at(16)).is_none());
+        assert!(!ban_list.read().unwrap().contains(CLIENT));
+
+        let ban = banner.record_at(CLIENT, Offence::UnrecognizedCommand, at(17)).unwrap();
+
+        assert_eq!(ban.rule.ban_secs, Some(60));
+
+        let entry = ban_list.read().unwrap().longest_match(CLIENT, SystemTime::now()).unwrap();
+
+        assert!(entry.remaining(SystemTime::now()).unwrap() <= Duration::from_secs(60));
+        assert!(!ban_list.read().unwrap().contains(Ipv4Addr::new(127, 0, 0, 3)));
+
+    }
+
+    #[test]
+    pub fn test_auto_banner_2(){
+
+        let (ban_list, banner) = auto_banner(vec![
+            rule(Offence::Connection, 2, 1, Some(60)),
+            rule(Offence::Connection, 2, 5, None),
+        ]);
+        let start = Instant::now();
+
+        assert!(banner.record_at(CLIENT, Offence::Connection, start).is_none());
+
+        // Both rules are broken, the permanent ban wins
+        let ban = banner.record_at(CLIENT, Offence::Connection, start + Duration::from_millis(500)).unwrap();
+
+        assert_eq!(ban.rule.ban_secs, None);
+        assert_eq!(ban_list.read().unwrap().entries()[0].expires, None);
+
+        // The record of the client is cleared by the ban
+        ban_list.write().unwrap().remove(&Ipv4Cidr::host(CLIENT));
+        assert!(banner.record_at(CLIENT, Offence::Connection, start + Duration::from_secs(1)).is_none());
+
+    }
+
+    #[test]
+    pub fn test_auto_banner_3(){
+
+        let (_ban_list, banner) = auto_banner(vec![rule(Offence::AccessDenied, 5, 10, None)]);
+        let start = Instant::now();
+
+        banner.record_at(CLIENT, Offence::AccessDenied, start);
+        banner.record_at(CLIENT, Offence::Connection, start);
+
+        banner.forget_stale(start + Duration::from_secs(5));
+        assert_eq!(banner.offences.lock().unwrap().len(), 1);
+
+        banner.forget_stale(start + Duration::from_secs(11));
+        assert!(banner.offences.lock().unwrap().is_empty());
+
+    }
+
+    #[test]
+    pub fn test_auto_banner_4(){
+
+        let (ban_list, banner) = auto_banner(vec![rule(Offence::Connection, 1, 10, Some(u64::MAX))]);
+
+        assert!(banner.record_at(CLIENT, Offence::Connection, Instant::now()).is_some());
+        assert_eq!(ban_list.read().unwrap().entries()[0].expires, None);
+
+    }
+}
@@ -0,0 +1,180 @@
+use std::fmt;
+use std::io::{Error, ErrorKind, Result};
+use std::net::Ipv4Addr;
+use std::str::FromStr;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+/// Block of ipv4 addresses written as <network>/<prefix length>, a single address being a /32 block.
+/// The host bits of the network are cleared, so 10.1.2.3/8 and 10.0.0.0/8 are the same block.
+/// Blocks can also be written with trailing wildcard octets, 10.1.*.* being the same block as 10.1.0.0/16.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Ipv4Cidr {
+    network: Ipv4Addr,
+    prefix_len: u8,
+}
+
+impl Ipv4Cidr {
+
+    pub fn new(address: Ipv4Addr, prefix_len: u8) -> Result<Self> {
+
+        if prefix_len > 32 {
+            let error = Error::new(ErrorKind::InvalidInput, format!("Invalid prefix length {}", prefix_len));
+            return Err(error);
+        }
+
+        Ok(Self {
+            network: Ipv4Addr::from(u32::from(address) & Self::mask(prefix_len)),
+            prefix_len,
+        })
+    }
+
+    /// Block holding a single address.
+    ///
+    pub fn host(address: Ipv4Addr) -> Self {
+        Self {
+            network: address,
+            prefix_len: 32,
+        }
+    }
+
+    pub fn network(&self) -> Ipv4Addr {
+        self.network
+    }
+
+    pub fn prefix_len(&self) -> u8 {
+        self.prefix_len
+    }
+
+    /// Checks if the address belongs to the block.
+    ///
+    pub fn contains(&self, address: Ipv4Addr) -> bool {
+        u32::from(address) & Self::mask(self.prefix_len) == u32::from(self.network)
+    }
+
+    /// Parses an address whose last octets are wildcards, such as 192.168.*.*.
+    ///
+    fn from_wildcard(wildcard: &str) -> Option<Self> {
+
+        let octets = wildcard.split('.').collect::<Vec<&str>>();
+        let fixed_octets = octets.iter().take_while(|octet| **octet != "*").count();
+
+        if octets.len() != 4 || octets[fixed_octets..].iter().any(|octet| *octet != "*") {
+            return None;
+        }
+
+        let mut address = [0; 4];
+
+        for (index, octet) in octets[..fixed_octets].iter().enumerate() {
+            address[index] = octet.parse::<u8>().ok()?;
+        }
+
+        Self::new(Ipv4Addr::from(address), fixed_octets as u8 * 8).ok()
+    }
+
+    fn mask(prefix_len: u8) -> u32 {
+        match prefix_len {
+            0 => 0,
+            prefix_len => u32::MAX << (32 - prefix_len as u32),
+        }
+    }
+}
+
+impl FromStr for Ipv4Cidr {
+    type Err = Error;
+
+    /// Parses either <network>/<prefix length>, a wildcard address or a single address.
+    ///
+    fn from_str(cidr: &str) -> Result<Self> {
+
+        let invalid = || Error::new(ErrorKind::InvalidInput, format!("Invalid address block {}", cidr));
+
+        if cidr.contains('*') {
+            return Self::from_wildcard(cidr).ok_or_else(invalid);
+        }
+
+        match cidr.split_once('/') {
+            Some((address, prefix_len)) => {
+                let address = address.parse::<Ipv4Addr>().map_err(|_| invalid())?;
+                let prefix_len = prefix_len.parse::<u8>().map_err(|_| invalid())?;
+                Self::new(address, prefix_len)
+            }
+            None => cidr.parse::<Ipv4Addr>().map(Self::host).map_err(|_| invalid()),
+        }
+    }
+}
+
+impl fmt::Display for Ipv4Cidr {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.prefix_len {
+            32 => write!(formatter, "{}", self.network),
+            prefix_len => write!(formatter, "{}/{}", self.network, prefix_len),
+        }
+    }
+}
+
+impl Serialize for Ipv4Cidr {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Ipv4Cidr {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let cidr = String::deserialize(deserializer)?;
+        cidr.parse::<Ipv4Cidr>().map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_ipv4_cidr_1(){
+
+        let cidr = "10.1.2.3/8".parse::<Ipv4Cidr>().unwrap();
+
+        assert_eq!(cidr.network(), Ipv4Addr::new(10, 0, 0, 0));
+        assert_eq!(cidr.prefix_len(), 8);
+        assert!(cidr.contains(Ipv4Addr::new(10, 255, 0, 1)));
+        assert!(!cidr.contains(Ipv4Addr::new(11, 0, 0, 0)));
+        assert_eq!(cidr.to_string(), "10.0.0.0/8");
+
+    }
+
+    #[test]
+    pub fn test_ipv4_cidr_2(){
+
+        let host = "192.168.0.2".parse::<Ipv4Cidr>().unwrap();
+
+        assert_eq!(host, Ipv4Cidr::host(Ipv4Addr::new(192, 168, 0, 2)));
+        assert!(host.contains(Ipv4Addr::new(192, 168, 0, 2)));
+        assert!(!host.contains(Ipv4Addr::new(192, 168, 0, 3)));
+        assert_eq!(host.to_string(), "192.168.0.2");
+
+        let everything = "0.0.0.0/0".parse::<Ipv4Cidr>().unwrap();
+        assert!(everything.contains(Ipv4Addr::new(8, 8, 8, 8)));
+
+        assert_eq!("192.168.*.*".parse::<Ipv4Cidr>().unwrap().to_string(), "192.168.0.0/16");
+        assert_eq!("*.*.*.*".parse::<Ipv4Cidr>().unwrap(), everything);
+
+    }
+
+    #[test]
+    pub fn test_ipv4_cidr_3(){
+
+        assert!("10.0.0.0/33".parse::<Ipv4Cidr>().is_err());
+        assert!("10.0.0/8".parse::<Ipv4Cidr>().is_err());
+        assert!("10.0.0.0/".parse::<Ipv4Cidr>().is_err());
+        assert!("everyone".parse::<Ipv4Cidr>().is_err());
+
+        assert!("10.*.0.*".parse::<Ipv4Cidr>().is_err());
+        assert!("10.*.*".parse::<Ipv4Cidr>().is_err());
+
+        let cidrs: Vec<Ipv4Cidr> = serde_json::from_str(r#"["127.0.0.1","10.0.0.0/8"]"#).unwrap();
+
+        assert_eq!(cidrs[1].prefix_len(), 8);
+        assert_eq!(serde_json::to_string(&cidrs).unwrap(), r#"["127.0.0.1","10.0.0.0/8"]"#);
+
+    }
+}
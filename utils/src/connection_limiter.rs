@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+use std::net::Ipv4Addr;
+use std::sync::{Arc, Mutex, RwLock};
+use crate::server_utils::server_config::ConnectionLimitsConfig;
+
+#[derive(Debug, Default)]
+struct Sessions {
+    total: usize,
+    per_ip: HashMap<Ipv4Addr, usize>,
+}
+
+/// Counts the sessions being handled or waiting for a worker, overall and per client address.
+/// A session is counted from the moment its permit is granted until the permit is dropped.
+#[derive(Debug)]
+pub struct ConnectionLimiter {
+    config: RwLock<ConnectionLimitsConfig>,
+    sessions: Mutex<Sessions>,
+}
+
+/// Permit of a session, releasing its slot when dropped.
+#[derive(Debug)]
+pub struct SessionPermit {
+    limiter: Arc<ConnectionLimiter>,
+    client_ip: Ipv4Addr,
+}
+
+impl ConnectionLimiter {
+
+    pub fn new(config: ConnectionLimitsConfig) -> Self {
+        Self {
+            config: RwLock::new(config),
+            sessions: Mutex::new(Sessions::default()),
+        }
+    }
+
+    /// Replaces the limits, the sessions above the new ones being kept until they end.
+    ///
+    pub fn set_config(&self, config: ConnectionLimitsConfig) {
+        *self.config.write().unwrap() = config;
+    }
+
+    /// Grants a session to the client unless it would exceed one of the limits.
+    ///
+    pub fn try_acquire(self: &Arc<Self>, client_ip: Ipv4Addr) -> Option<SessionPermit> {
+
+        let config = self.config.read().unwrap();
+        let mut sessions = self.sessions.lock().unwrap();
+        let client_sessions = sessions.per_ip.get(&client_ip).copied().unwrap_or(0);
+
+        let total_reached = config.max_sessions.is_some_and(|max| sessions.total >= max);
+        let client_reached = config.max_sessions_per_ip.is_some_and(|max| client_sessions >= max);
+
+        if total_reached || client_reached {
+            return None;
+        }
+
+        sessions.total += 1;
+        sessions.per_ip.insert(client_ip, client_sessions + 1);
+
+        Some(SessionPermit {
+            limiter: Arc::clone(self),
+            client_ip,
+        })
+    }
+
+    /// Returns the number of sessions, overall and of the given client.
+    ///
+    pub fn sessions(&self, client_ip: Ipv4Addr) -> (usize, usize) {
+        let sessions = self.sessions.lock().unwrap();
+        (sessions.total, sessions.per_ip.get(&client_ip).copied().unwrap_or(0))
+    }
+
+    /// Returns the number of sessions being handled or waiting for a worker.
+    ///
+    pub fn total_sessions(&self) -> usize {
+        self.sessions.lock().unwrap().total
+    }
+
+    fn release(&self, client_ip: Ipv4Addr) {
+
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.total -= 1;
+
+        if let Some(client_sessions) = sessions.per_ip.get_mut(&client_ip) {
+            *client_sessions -= 1;
+            if *client_sessions == 0 {
+                sessions.per_ip.remove(&client_ip);
+            }
+        }
+    }
+}
+
+impl Drop for SessionPermit {
+    fn drop(&mut self) {
+        self.limiter.release(self.client_ip);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIRST: Ipv4Addr = Ipv4Addr::new(127, 0, 0, 1);
+    const SECOND: Ipv4Addr = Ipv4Addr::new(127, 0, 0, 2);
+
+    fn limiter(max_sessions: Option<usize>, max_sessions_per_ip: Option<usize>) -> Arc<ConnectionLimiter> {
+        Arc::new(ConnectionLimiter::new(ConnectionLimitsConfig { max_sessions, max_sessions_per_ip }))
+    }
+
+    #[test]
+    pub fn test_connection_limiter_1(){
+
+        let limiter = limiter(Some(3), Some(2));
+
+        let first = limiter.try_acquire(FIRST).unwrap();
+        let _second = limiter.try_acquire(FIRST).unwrap();
+
+        // A client can not take more than its share
+        assert!(limiter.try_acquire(FIRST).is_none());
+
+        let _third = limiter.try_acquire(SECOND).unwrap();
+
+        // Nor can anyone once the server is full
+        assert!(limiter.try_acquire(SECOND).is_none());
+        assert_eq!(limiter.sessions(FIRST), (3, 2));
+
+        drop(first);
+
+        assert_eq!(limiter.sessions(FIRST), (2, 1));
+        assert!(limiter.try_acquire(SECOND).is_some());
+
+    }
+
+    #[test]
+    pub fn test_connection_limiter_2(){
+
+        let limiter = limiter(None, None);
+
+        let permits = (0..100).map(|_| limiter.try_acquire(FIRST).unwrap()).collect::<Vec<SessionPermit>>();
+
+        assert_eq!(limiter.sessions(FIRST), (100, 100));
+        assert_eq!(limiter.total_sessions(), 100);
+
+        drop(permits);
+
+        assert_eq!(limiter.sessions(FIRST), (0, 0));
+
+    }
+
+    #[test]
+    pub fn test_connection_limiter_3(){
+
+        let limiter = limiter(None, None);
+        let _permits = (0..3).map(|_| limiter.try_acquire(FIRST).unwrap()).collect::<Vec<SessionPermit>>();
+
+        limiter.set_config(ConnectionLimitsConfig { max_sessions: None, max_sessions_per_ip: Some(2) });
+
+        // Ongoing sessions are kept, new ones wait for the client to go under the limit
+        assert!(limiter.try_acquire(FIRST).is_none());
+        assert!(limiter.try_acquire(SECOND).is_some());
+        assert_eq!(limiter.sessions(FIRST), (3, 3));
+
+    }
+}
@@ -1,6 +1,7 @@
 use std::net::{Ipv4Addr, SocketAddrV4};
+use std::time::Duration;
 
-/// File codes
+// File codes
 
 pub const FILE_NOT_FOUND: &str = "File not found\n";
 pub const DELETE_SUCCESSFUL: &str = "Deleted file successfully\n";
@@ -9,22 +10,72 @@ pub const ALREADY_EXISTS: &str = "File already exists\n";
 pub const READY_TO_RECEIVE: &str = "File ready to receive\n";
 pub const QUIT_MESSAGE: &str = "Bye!\n";
 pub const UNRECOGNIZED_MESSAGE: &str = "Unrecognized command. Use HELP command for info.\n";
+pub const NO_MATCHING_FILES: &str = "No matching files\n";
+pub const INVALID_FILE_NAME: &str = "Invalid file name\n";
+pub const NO_VERSIONS_FOUND: &str = "No versions found\n";
+pub const VERSION_NOT_FOUND: &str = "Version not found\n";
+pub const VERSION_RESTORED: &str = "Version restored\n";
+pub const TRASH_EMPTY: &str = "Trash is empty\n";
+pub const FILE_RESTORED: &str = "File restored\n";
+pub const TRASH_EMPTIED: &str = "Emptied trash\n";
+pub const ACCESS_DENIED: &str = "Access denied\n";
+pub const SHARE_SUCCESSFUL: &str = "Shared file successfully\n";
+pub const UNSHARE_SUCCESSFUL: &str = "Unshared file successfully\n";
+pub const UNKNOWN_GRANTEE: &str = "Unknown user or group\n";
+pub const GRANT_NOT_FOUND: &str = "File is not shared with this user or group\n";
+pub const NO_SHARED_FILES: &str = "No files shared with you\n";
+pub const COMMAND_NOT_PERMITTED: &str = "Command not permitted for your role\n";
+pub const PUBLIC_AREA_DISABLED: &str = "Public area is disabled\n";
+pub const TOO_MANY_CONNECTIONS: &str = "Too many connections\n";
+pub const SERVER_SHUTTING_DOWN: &str = "Server is shutting down\n";
+pub const TRANSFER_ABORTED: &str = "Transfer aborted, the server is shutting down\n";
+pub const SESSION_KICKED: &str = "Session closed by an administrator\n";
+pub const MAINTENANCE_MODE: &str = "Server is in read-only maintenance mode\n";
 
-/// Miscellaneous
+// Miscellaneous
 
 pub const EPHEMERAL_ADDRESS: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 0);
+/// Data port sent to a rejected client, the reason following on the command connection
+pub const REJECTED_PORT: u16 = 0;
 pub const FILE_TYPE_DIRECTORY: &str = "Directory";
 pub const FILE_TYPE_FILE: &str = "File";
 pub const FILE_TYPE_OTHER: &str = "Other";
 pub const EMPTY: &str = "EMPTY";
 pub const WRONG_INPUT: &str = "Wrong input!";
+pub const UNRECOGNIZED_INPUT: &str = "Unrecognized input";
+pub const SAVE_LISTS_ERROR: &str = "Failed to save the lists:";
+pub const RELOAD_ERROR: &str = "Failed to reload:";
+pub const SERVER_NOT_RUNNING: &str = "the server is not running";
+pub const RELOADED_DESC: &str = "Reloaded";
+pub const APPLIED_SETTINGS_DESC: &str = "Applied:";
+pub const RESTART_REQUIRED_DESC: &str = "Restart required:";
 pub const BAN_LIST_DESC: &str = "Banned ips:";
+pub const ROLES_DESC: &str = "Roles:";
+pub const DEFAULT_ROLE_DESC: &str = "Default role:";
 pub const WHITE_LIST_DESC: &str = "Allowed ips:";
 pub const CONFIG_LOAD_ERROR: &str = "Failed to load config file";
+pub const INVALID_CONFIG: &str = "Invalid config:";
+pub const SESSION_NOT_FOUND: &str = "No session with this id";
+pub const NO_SESSIONS_DESC: &str = "No active sessions";
+pub const KICKED_DESC: &str = "Kicked session";
+pub const BANNED_DESC: &str = "and banned";
+pub const SAVE_MAINTENANCE_ERROR: &str = "Failed to save the maintenance mode:";
+pub const MAINTENANCE_DESC: &str = "Maintenance:";
+pub const ACTIVE_LIST_DESC: &str = "Active list:";
+pub const SESSION_COUNT_DESC: &str = "Sessions:";
+pub const UPTIME_DESC: &str = "Uptime:";
 
-/// Verbs
+// Reserved storage directories
 
-pub const VERBS: [&str;8] = [GET,DELETE,LIST,CREATE,UPDATE,QUIT,HELP,LIST_OWNED];
+pub const STAGING_DIR: &str = ".staging";
+pub const BLOBS_DIR: &str = ".blobs";
+pub const VERSIONS_DIR: &str = ".versions";
+pub const TRASH_DIR: &str = ".trash";
+pub const KEYS_DIR: &str = ".keys";
+
+// Verbs
+
+pub const VERBS: [&str;20] = [GET,DELETE,LIST,CREATE,UPDATE,QUIT,HELP,LIST_OWNED,SEARCH,VERSIONS,GET_VERSION,RESTORE,TRASH,UNDELETE,EMPTY_TRASH,SHARE,UNSHARE,LIST_SHARED,STAT,PUBLISH];
 pub const GET: &str = "GET";
 pub const DELETE: &str = "DELETE";
 pub const LIST: &str = "LIST";
@@ -33,9 +84,21 @@ pub const CREATE: &str = "CREATE";
 pub const UPDATE: &str = "UPDATE";
 pub const QUIT: &str = "QUIT";
 pub const HELP: &str = "HELP";
+pub const SEARCH: &str = "SEARCH";
+pub const VERSIONS: &str = "VERSIONS";
+pub const GET_VERSION: &str = "GET_VERSION";
+pub const RESTORE: &str = "RESTORE";
+pub const TRASH: &str = "TRASH";
+pub const UNDELETE: &str = "UNDELETE";
+pub const EMPTY_TRASH: &str = "EMPTY_TRASH";
+pub const SHARE: &str = "SHARE";
+pub const UNSHARE: &str = "UNSHARE";
+pub const LIST_SHARED: &str = "LIST_SHARED";
+pub const STAT: &str = "STAT";
+pub const PUBLISH: &str = "PUBLISH";
 
 /// Verb descriptions
-pub const VERB_DESCRIPTIONS: [&str;7] = [GET_DESC,DELETE_DESC,LIST_DESC,CREATE_DESC,UPDATE_DESC,QUIT_DESC,LIST_OWNED_DESC];
+pub const VERB_DESCRIPTIONS: [&str;19] = [GET_DESC,DELETE_DESC,LIST_DESC,CREATE_DESC,UPDATE_DESC,QUIT_DESC,LIST_OWNED_DESC,SEARCH_DESC,VERSIONS_DESC,GET_VERSION_DESC,RESTORE_DESC,TRASH_DESC,UNDELETE_DESC,EMPTY_TRASH_DESC,SHARE_DESC,UNSHARE_DESC,LIST_SHARED_DESC,STAT_DESC,PUBLISH_DESC];
 pub const GET_DESC: &str = "Usage: GET <filename>";
 pub const DELETE_DESC: &str = "Usage: DELETE <filename>";
 pub const LIST_DESC: &str = "Usage: LIST";
@@ -43,39 +106,147 @@ pub const LIST_OWNED_DESC: &str = "Usage: LIST_OWNED";
 pub const CREATE_DESC: &str = "Usage: CREATE <filename>";
 pub const UPDATE_DESC: &str = "Usage: UPDATE <filename>";
 pub const QUIT_DESC: &str = "Usage: QUIT";
+pub const SEARCH_DESC: &str = "Usage: SEARCH <pattern> [REGEX] [CONTENT] [MIN_SIZE=<bytes>] [MAX_SIZE=<bytes>] [AFTER=<yyyy-mm-dd>] [BEFORE=<yyyy-mm-dd>]";
+pub const VERSIONS_DESC: &str = "Usage: VERSIONS <filename>";
+pub const GET_VERSION_DESC: &str = "Usage: GET_VERSION <filename> <version>";
+pub const RESTORE_DESC: &str = "Usage: RESTORE <filename> <version>";
+pub const TRASH_DESC: &str = "Usage: TRASH";
+pub const UNDELETE_DESC: &str = "Usage: UNDELETE <filename>";
+pub const EMPTY_TRASH_DESC: &str = "Usage: EMPTY_TRASH";
+pub const SHARE_DESC: &str = "Usage: SHARE <filename> <ipv4 address|group> [READ|WRITE]";
+pub const UNSHARE_DESC: &str = "Usage: UNSHARE <filename> <ipv4 address|group>";
+pub const LIST_SHARED_DESC: &str = "Usage: LIST_SHARED";
+pub const STAT_DESC: &str = "Usage: STAT <filename>";
+pub const PUBLISH_DESC: &str = "Usage: PUBLISH <filename>";
+
+// Share access levels
+
+pub const SHARE_READ: &str = "READ";
+pub const SHARE_WRITE: &str = "WRITE";
+
+// Roles
+
+pub const ROLE_READ_ONLY: &str = "read_only";
+pub const ROLE_UPLOAD_ONLY: &str = "upload_only";
+pub const ROLE_FULL: &str = "full";
+pub const ROLE_ADMIN: &str = "admin";
+pub const ROLE_ANONYMOUS: &str = "anonymous";
 
-/// Server input commands
+// Search options
 
-pub const INPUTS: [&str;7] = [SHUTDOWN,ADD_IP,REMOVE_IP,LIST_IP,HELP,SWITCH,SHOW_CONFIG];
+pub const SEARCH_REGEX: &str = "REGEX";
+pub const SEARCH_CONTENT: &str = "CONTENT";
+pub const SEARCH_MIN_SIZE: &str = "MIN_SIZE";
+pub const SEARCH_MAX_SIZE: &str = "MAX_SIZE";
+pub const SEARCH_AFTER: &str = "AFTER";
+pub const SEARCH_BEFORE: &str = "BEFORE";
+
+// Server input commands
+
+pub const INPUTS: [&str;15] = [SHUTDOWN,ADD_IP,REMOVE_IP,LIST_IP,HELP,SWITCH,SHOW_CONFIG,SET_ROLE,REMOVE_ROLE,LIST_ROLES,RELOAD,SESSIONS,KICK,MAINTENANCE,STATUS];
 pub const SHUTDOWN: &str = "SHUTDOWN";
 pub const ADD_IP: &str = "ADD";
 pub const REMOVE_IP: &str = "REMOVE";
 pub const LIST_IP: &str = "LIST";
 pub const SWITCH: &str = "SWITCH";
 pub const SHOW_CONFIG: &str = "SHOW_CONFIG";
+pub const SET_ROLE: &str = "SET_ROLE";
+pub const REMOVE_ROLE: &str = "REMOVE_ROLE";
+pub const LIST_ROLES: &str = "LIST_ROLES";
+pub const RELOAD: &str = "RELOAD";
+pub const SESSIONS: &str = "SESSIONS";
+pub const KICK: &str = "KICK";
+pub const KICK_BAN: &str = "BAN";
+pub const MAINTENANCE: &str = "MAINTENANCE";
+pub const MAINTENANCE_ON: &str = "ON";
+pub const MAINTENANCE_OFF: &str = "OFF";
+pub const MAINTENANCE_PERSIST: &str = "PERSIST";
+pub const STATUS: &str = "STATUS";
 
-/// Server input descriptions
+// Server input descriptions
 
-pub const INPUT_DESCRIPTIONS: [&str;6] = [SHUTDOWN_DESC,ADD_IP_DESC,REMOVE_IP_DESC,LIST_IP_DESC,SWITCH_DESC,SHOW_CONFIG_DESC];
+pub const INPUT_DESCRIPTIONS: [&str;14] = [SHUTDOWN_DESC,ADD_IP_DESC,REMOVE_IP_DESC,LIST_IP_DESC,SWITCH_DESC,SHOW_CONFIG_DESC,SET_ROLE_DESC,REMOVE_ROLE_DESC,LIST_ROLES_DESC,RELOAD_DESC,SESSIONS_DESC,KICK_DESC,MAINTENANCE_INPUT_DESC,STATUS_DESC];
 pub const SHUTDOWN_DESC: &str = "Usage: SHUTDOWN --- Shuts down the server and all active connections.";
-pub const ADD_IP_DESC: &str = "Usage: ADD <ipv4 address> --- Adds a new IP to the white/ban list";
-pub const REMOVE_IP_DESC: &str = "Usage: REMOVE<ipv4 address> --- Removes an IP from the white/ban list";
+pub const ADD_IP_DESC: &str = "Usage: ADD <ipv4 address|cidr|wildcard> [<duration>] --- Adds a new IP or block of addresses, such as 10.0.0.0/8 or 10.*.*.*, to the white/ban list, for a duration such as 30m, 2h or 7d if given";
+pub const REMOVE_IP_DESC: &str = "Usage: REMOVE <ipv4 address|cidr|wildcard> --- Removes an IP or block of addresses from the white/ban list";
 pub const LIST_IP_DESC: &str = "Usage: LIST --- Lists the white/ban list";
 pub const SWITCH_DESC: &str = "Usage: SWITCH --- Switches from the current list to the opposite";
 
-pub const SHOW_CONFIG_DESC: &str = "Usage: SHOW_CONFIG --- Shows current server configuration";
+pub const SHOW_CONFIG_DESC: &str = "Usage: SHOW_CONFIG --- Shows current server configuration and where each setting comes from";
+pub const SET_ROLE_DESC: &str = "Usage: SET_ROLE <ipv4 address|cidr> <anonymous|read_only|upload_only|full|admin> --- Gives a role to a client or a block of addresses";
+pub const REMOVE_ROLE_DESC: &str = "Usage: REMOVE_ROLE <ipv4 address|cidr> --- Removes the role of a client or a block of addresses";
+pub const LIST_ROLES_DESC: &str = "Usage: LIST_ROLES --- Lists the assigned roles";
+pub const RELOAD_DESC: &str = "Usage: RELOAD --- Reads the config file and the saved lists again, applying what does not require a restart";
+pub const SESSIONS_DESC: &str = "Usage: SESSIONS --- Lists the active sessions with their current request, bytes transferred and rate";
+pub const KICK_DESC: &str = "Usage: KICK <session id> [BAN [<duration>]] --- Closes a session, banning its address if asked, for a duration such as 30m, 2h or 7d if given";
+pub const MAINTENANCE_INPUT_DESC: &str = "Usage: MAINTENANCE <ON|OFF> [PERSIST] --- Switches the read-only maintenance mode, keeping it after a restart if PERSIST is given";
+pub const STATUS_DESC: &str = "Usage: STATUS --- Shows the maintenance mode, the active list, the number of sessions and the uptime";
 
-/// Server environment variables
+// Server environment variables
 
 pub const CONFIG_PATH_ENV: &str = "CONFIG_PATH";
+pub const DEFAULT_CONFIG_PATH: &str = "./config.json";
+/// Prefix of the environment variables replacing the config settings, such as FTP_BUFFER_SIZE
+pub const CONFIG_ENV_PREFIX: &str = "FTP_";
+
+// Default server configurations
+
+pub const DEFAULT_COMMAND_ADDRESS: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 7878);
+pub const DEFAULT_DATA_DIR_PATH: &str = "./data";
+pub const DEFAULT_SERIALIZED_LISTS_PATH: &str = "./serialized_lists";
+pub const DEFAULT_WHITE_LIST_FILE_NAME: &str = "white_list.json";
+pub const DEFAULT_BAN_LIST_FILE_NAME: &str = "ban_list.json";
+pub const DEFAULT_SERVER_NUM_THREADS: usize = 10;
+pub const DEFAULT_BUFFER_SIZE: usize = 8192;
+pub const DEFAULT_FIRST_PORT: u16 = 50000;
+pub const DEFAULT_LAST_PORT: u16 = 50100;
+pub const DEFAULT_SHUTDOWN_TIMEOUT_SECS: u64 = 30;
+pub const DEFAULT_LOG_LEVEL: &str = "info";
+pub const DEFAULT_LOG_FILE_PREFIX: &str = "server";
+pub const DEFAULT_AUDIT_LOG_PATH: &str = "./audit/xferlog";
+
+// Serialized files
+
+pub const TEMPORARY_EXTENSION: &str = ".tmp";
+pub const ACTIVE_LIST_FILE_NAME: &str = "active_list.json";
+pub const MAINTENANCE_FILE_NAME: &str = "maintenance.json";
+pub const LOG_FILE_SUFFIX: &str = "log";
 
 /// Memory representation
 pub const BYTE: usize = 1;
 pub const KILOBYTE: usize = 1024 * BYTE;
 pub const MEGABYTE: usize = 1024 * KILOBYTE ;
+pub const GIGABYTE: usize = 1024 * MEGABYTE;
 
-#[cfg(test)]
+/// Largest file whose content is scanned by a SEARCH request
+pub const SEARCH_CONTENT_LIMIT: usize = 16 * MEGABYTE;
+
+/// Smallest part accepted by S3 for every part of a multipart upload except the last one
+pub const S3_MIN_PART_SIZE: usize = 5 * MEGABYTE;
+pub const S3_DEFAULT_PART_SIZE: usize = 8 * MEGABYTE;
+
+/// Time representation
+pub const SECONDS_PER_DAY: u64 = 24 * 60 * 60;
+
+/// Longest temporary ban a rule may set, longer bans being meant as permanent
+pub const MAX_BAN_SECS: u64 = 100 * 365 * SECONDS_PER_DAY;
+
+/// Longest max age a retention setting may set, leaving it out keeping the files forever
+pub const MAX_AGE_DAYS: u64 = 100 * 365;
 
+/// Time between two purges of the expired trashed files and versions
+pub const SWEEP_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// Time between two purges of the expired white and ban list entries
+pub const LIST_SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Longest wait of a data transfer for the client before checking whether it was aborted
+pub const TRANSFER_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Time between two checks of the data connection being accepted
+pub const DATA_ACCEPT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+#[cfg(test)]
 mod tests{
     use super::*;
 
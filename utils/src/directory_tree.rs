@@ -1,17 +1,22 @@
 use std::fs;
-use std::fs::File;
+use std::fs::{File, OpenOptions};
 use std::io::{Error, ErrorKind, Result};
 use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tracing::debug;
+use crate::file_index::FileIndex;
 
 
 
 /// Data structure used to manage files in a directory hierarchy.
 /// Each file will have a unique name.
+/// When an index of the local storage holding the tree is attached, lookups and uniqueness checks are answered by the index instead of walking the tree.
 #[derive(Debug,Clone)]
 pub struct DirectoryTree<T>
     where T: AsRef<Path>
 {
     root_dir: T,
+    index: Option<Arc<FileIndex>>,
 }
 
 impl<P> DirectoryTree<P>
@@ -25,6 +30,7 @@ where P: AsRef<Path>{
 
         Ok(Self {
             root_dir,
+            index: None,
         })
     }
 
@@ -39,9 +45,37 @@ where P: AsRef<Path>{
 
         Ok(Self {
             root_dir,
+            index: None,
         })
     }
 
+    /// Attaches an index that will be queried and kept up to date by this tree.
+    /// The index is only used if its storage is a local directory holding the tree.
+    pub fn with_index(mut self, index: Arc<FileIndex>) -> Self {
+        self.index = Some(index);
+        self
+    }
+
+    /// Getter for the tree root.
+    pub fn root_dir(&self) -> &Path {
+        self.root_dir.as_ref()
+    }
+
+    /// Getter for the attached index.
+    pub fn index(&self) -> Option<&Arc<FileIndex>> {
+        self.index.as_ref()
+    }
+
+    /// Returns the attached index with the root of its storage, if the index covers the given path.
+    ///
+    fn index_of(&self, path: &Path) -> Option<(&FileIndex, &Path)> {
+
+        let index = self.index.as_ref()?;
+        let storage_root = index.storage().local_root()?;
+
+        path.starts_with(storage_root).then_some((index.as_ref(), storage_root))
+    }
+
     /// Create a directory relative to the tree root.
     ///
     pub fn create_dir<T:AsRef<Path>>(&self, new_dir: T) -> Result<()> {
@@ -67,28 +101,57 @@ where P: AsRef<Path>{
         dir_path.exists()
     }
 
-    /// Creates a new file into the selected directory if there is no file named the same.
+    /// Creates a new file into the selected directory if there is no file named the same and returns it opened for reading and writing.
     /// The selected directory is considered to be relative to the tree root.
-    pub fn create_file<T:AsRef<Path>>(&self,file_dir: T,file_name: &str) -> Result<()> {
+    /// With an index attached the name is claimed atomically, so concurrent creates of the same name cannot both succeed.
+    pub fn create_file<T:AsRef<Path>>(&self,file_dir: T,file_name: &str) -> Result<File> {
 
-        if let Ok(Some(_)) = self.find_file(file_name){
-            let error = Error::new(ErrorKind::AlreadyExists, format!("A file named {} already exists", file_name));
-            return Err(error);
+        let file_dir_path = self.root_dir.as_ref().join(file_dir.as_ref());
+        let file_path = file_dir_path.join(file_name);
+
+        let claimed = match self.index_of(&file_path) {
+
+            Some((index, storage_root)) => {
+                let index_path = file_path.strip_prefix(storage_root).unwrap();
+                index.reserve(file_name, index_path)?;
+                Some((index, index_path))
+            }
+
+            None => {
+                if let Ok(Some(_)) = self.find_file(file_name){
+                    let error = Error::new(ErrorKind::AlreadyExists, format!("A file named {} already exists", file_name));
+                    return Err(error);
+                };
+                None
+            }
         };
 
-        let file_dir_path = self.root_dir.as_ref().join(file_dir.as_ref());
+        let file = Self::create_new_file(&file_dir_path, &file_path);
 
-        if !file_dir_path.exists() {
-            self.create_dir_all(file_dir.as_ref())?;
+        // Release the claimed name if the file could not be created
+        if let (Err(_), Some((index, index_path))) = (&file, claimed) {
+            index.remove_path(index_path);
         }
 
-        let file_path = file_dir_path.join(&file_name);
-        println!("Creating file {:?}", file_path);
-        File::create(&file_path)?;
+        file
 
+    }
 
-        Ok(())
+    /// Creates the missing directories and a file that must not already exist.
+    ///
+    fn create_new_file(file_dir_path: &Path, file_path: &Path) -> Result<File> {
+
+        if !file_dir_path.exists() {
+            fs::create_dir_all(file_dir_path)?;
+        }
 
+        debug!("Creating file {:?}", file_path);
+
+        OpenOptions::new()
+            .create_new(true)
+            .read(true)
+            .write(true)
+            .open(file_path)
     }
 
     /// Recursively searches each child directory of the parent directory and appends to a vec each file path.
@@ -97,6 +160,11 @@ where P: AsRef<Path>{
 
         let dir = self.root_dir.as_ref();
 
+        if let Some((index, storage_root)) = self.index_of(dir) {
+            let entries = index.entries_under(dir.strip_prefix(storage_root).unwrap());
+            return Ok(entries.into_iter().map(|entry| storage_root.join(entry.path)).collect());
+        }
+
         let mut files: Vec<PathBuf> = Vec::new();
 
         // Search each child of this directory
@@ -123,10 +191,15 @@ where P: AsRef<Path>{
     }
 
     /// Attempts to find a file and return its path in the directory tree.
-    ///
+    /// Directories whose name starts with a dot are reserved for the server, such as the trash, and are not searched.
     pub fn find_file(&self, file_name: &str) -> Result<Option<PathBuf>> {
         let dir = self.root_dir.as_ref();
 
+        if let Some((index, storage_root)) = self.index_of(dir) {
+            let entry = index.get(file_name).filter(|entry| entry.path.starts_with(dir.strip_prefix(storage_root).unwrap()));
+            return Ok(entry.map(|entry| storage_root.join(entry.path)));
+        }
+
         let result = None;
 
         for entry in fs::read_dir(dir)? {
@@ -136,8 +209,12 @@ where P: AsRef<Path>{
 
             if path.is_dir(){
 
+                if entry.file_name().to_string_lossy().starts_with('.') {
+                    continue;
+                }
+
                 let dir_node = DirectoryTree::new_from_existing(path)?;
-                let result = dir_node.find_file(&file_name)?;
+                let result = dir_node.find_file(file_name)?;
 
                 if result.is_some() {
                     return Ok(result);
@@ -174,7 +251,11 @@ where P: AsRef<Path>{
             },
         };
 
-        fs::remove_file(file_path)?;
+        fs::remove_file(&file_path)?;
+
+        if let Some((index, storage_root)) = self.index_of(&file_path) {
+            index.remove_path(file_path.strip_prefix(storage_root).unwrap());
+        }
 
         Ok(())
 
@@ -185,6 +266,7 @@ where P: AsRef<Path>{
 #[cfg(test)]
 mod tests {
     use std::fs::remove_file;
+    use crate::storage::local_backend::LocalBackend;
     use super::*;
     #[test]
     pub fn test_list_files_in_dir_1(){
@@ -298,4 +380,67 @@ mod tests {
 
     }
 
-}
\ No newline at end of file
+    #[test]
+    pub fn test_list_files_in_dir_9(){
+        let dir_tree = DirectoryTree::new(PathBuf::from("./tests/files7")).unwrap();
+
+        let file_name = "unique_file.txt";
+
+        assert!(dir_tree.create_file("dir1", file_name).is_ok());
+        assert!(dir_tree.find_file(file_name).unwrap().is_some());
+
+        // The name is already used even if it is requested in another directory
+        let result = dir_tree.create_file("dir2", file_name);
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::AlreadyExists);
+
+        dir_tree.remove_file(file_name).unwrap();
+        assert!(dir_tree.find_file(file_name).unwrap().is_none());
+
+    }
+
+    #[test]
+    pub fn test_list_files_in_dir_10(){
+        let dir_tree = DirectoryTree::new(PathBuf::from("./tests/files11")).unwrap();
+
+        let file_name = "trashed_file.txt";
+
+        dir_tree.create_file(".trash/dir1", file_name).unwrap();
+
+        // Files kept in reserved directories do not take the name
+        assert!(dir_tree.find_file(file_name).unwrap().is_none());
+        assert!(dir_tree.create_file("dir1", file_name).is_ok());
+
+        dir_tree.remove_file(file_name).unwrap();
+        fs::remove_dir_all(dir_tree.root_dir()).unwrap();
+
+    }
+
+    #[test]
+    pub fn test_list_files_in_dir_11(){
+        let directory = std::env::temp_dir().join(format!("test_list_files_in_dir_{}", std::process::id()));
+
+        let index = Arc::new(FileIndex::build(Arc::new(LocalBackend::new(&directory).unwrap())).unwrap());
+        let dir_tree = DirectoryTree::new_from_existing(directory.clone()).unwrap().with_index(Arc::clone(&index));
+
+        let file_name = "indexed_file.txt";
+
+        assert!(dir_tree.create_file("dir1", file_name).is_ok());
+        assert_eq!(dir_tree.find_file(file_name).unwrap(), Some(directory.join("dir1").join(file_name)));
+        assert_eq!(dir_tree.list_files_in_tree().unwrap(), vec![directory.join("dir1").join(file_name)]);
+
+        // The name is already claimed in the index even if it is requested in another directory
+        let result = dir_tree.create_file("dir2", file_name);
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::AlreadyExists);
+
+        // A tree of a sub directory only sees its own files
+        let sub_tree = DirectoryTree::new(directory.join("dir2")).unwrap().with_index(Arc::clone(&index));
+        assert!(sub_tree.find_file(file_name).unwrap().is_none());
+
+        dir_tree.remove_file(file_name).unwrap();
+        assert!(!index.contains(file_name));
+
+        fs::remove_dir_all(directory).unwrap();
+
+    }
+
+}
@@ -0,0 +1,378 @@
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+use tracing::warn;
+use crate::storage::storage_backend::{is_internal_path, FileStat, StorageBackend};
+
+/// In memory index from file names to their metadata.
+/// It is built once from the storage and must be updated on every mutation,
+/// so that lookups and uniqueness checks do not have to walk the whole storage.
+#[derive(Debug)]
+pub struct FileIndex {
+    storage: Arc<dyn StorageBackend>,
+    entries: RwLock<HashMap<String, FileStat>>,
+}
+
+impl FileIndex {
+
+    /// Builds the index by listing every file of the storage.
+    /// Files sharing a name are indexed by the first path in order, the other ones being shadowed with a warning.
+    pub fn build(storage: Arc<dyn StorageBackend>) -> Result<Self> {
+
+        let mut entries: HashMap<String, FileStat> = HashMap::new();
+
+        for file_stat in storage.list(Path::new(""))? {
+
+            let file_name = match Self::file_name_of(&file_stat.path) {
+                Some(file_name) => file_name,
+                None => continue,
+            };
+
+            let shadowed = match entries.get(&file_name) {
+                Some(indexed) if indexed.path < file_stat.path => file_stat,
+                _ => match entries.insert(file_name, file_stat) {
+                    Some(shadowed) => shadowed,
+                    None => continue,
+                },
+            };
+
+            warn!("{} is shadowed by a file with the same name and cannot be reached", shadowed.path.display());
+        }
+
+        Ok(Self {
+            storage,
+            entries: RwLock::new(entries),
+        })
+    }
+
+    /// Getter for the indexed storage.
+    pub fn storage(&self) -> &Arc<dyn StorageBackend> {
+        &self.storage
+    }
+
+    /// Returns the metadata of the file with the given name.
+    ///
+    pub fn get(&self, file_name: &str) -> Option<FileStat> {
+        self.entries.read().unwrap().get(file_name).cloned()
+    }
+
+    /// Checks if a file with the given name is indexed.
+    ///
+    pub fn contains(&self, file_name: &str) -> bool {
+        self.entries.read().unwrap().contains_key(file_name)
+    }
+
+    /// Atomically checks that the name is free and claims it for the given path.
+    /// Fails with AlreadyExists if another file already uses this name.
+    pub fn reserve<P: AsRef<Path>>(&self, file_name: &str, path: P) -> Result<()> {
+
+        let mut entries = self.entries.write().unwrap();
+
+        if entries.contains_key(file_name) {
+            let error = Error::new(ErrorKind::AlreadyExists, format!("A file named {} already exists", file_name));
+            return Err(error);
+        }
+
+        entries.insert(file_name.to_string(), FileStat {
+            path: path.as_ref().to_path_buf(),
+            size: 0,
+            modified: SystemTime::now(),
+        });
+
+        Ok(())
+    }
+
+    /// Re-reads the metadata of the file at the given path and stores it in the index.
+    /// If the file no longer exists its entry is dropped. Paths reserved for the server are ignored.
+    pub fn refresh<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+
+        let path = path.as_ref();
+
+        if is_internal_path(path) {
+            return Ok(());
+        }
+
+        match self.storage.stat(path) {
+
+            Ok(file_stat) => {
+                if let Some(file_name) = Self::file_name_of(path) {
+                    self.entries.write().unwrap().insert(file_name, file_stat);
+                }
+                Ok(())
+            }
+
+            Err(error) if error.kind() == ErrorKind::NotFound => {
+                self.remove_path(path);
+                Ok(())
+            }
+
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Removes the entry with the given name.
+    ///
+    pub fn remove(&self, file_name: &str) -> Option<FileStat> {
+        self.entries.write().unwrap().remove(file_name)
+    }
+
+    /// Removes the entry of the given path, if the name is indexed for that exact path.
+    ///
+    pub fn remove_path<P: AsRef<Path>>(&self, path: P) -> Option<FileStat> {
+
+        let path = path.as_ref();
+        let file_name = Self::file_name_of(path)?;
+
+        let mut entries = self.entries.write().unwrap();
+
+        match entries.get(&file_name) {
+            Some(entry) if entry.path == path => entries.remove(&file_name),
+            _ => None,
+        }
+    }
+
+    /// Returns all the indexed entries sorted by path.
+    ///
+    pub fn entries(&self) -> Vec<FileStat> {
+
+        let mut entries = self.entries.read().unwrap().values().cloned().collect::<Vec<FileStat>>();
+        entries.sort_by(|first, second| first.path.cmp(&second.path));
+
+        entries
+    }
+
+    /// Returns the entries found in the hierarchy of the given directory sorted by path.
+    ///
+    pub fn entries_under<P: AsRef<Path>>(&self, dir: P) -> Vec<FileStat> {
+
+        self.entries()
+            .into_iter()
+            .filter(|entry| entry.path.starts_with(dir.as_ref()))
+            .collect()
+    }
+
+    /// Returns the number of indexed files.
+    pub fn len(&self) -> usize {
+        self.entries.read().unwrap().len()
+    }
+
+    /// Checks if the index is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Extracts the file name of a path as an owned string.
+    ///
+    fn file_name_of(path: &Path) -> Option<String> {
+        path.file_name()
+            .and_then(|file_name| file_name.to_str())
+            .map(|file_name| file_name.to_string())
+    }
+
+}
+
+/// Keeps the index consistent with changes made to a local storage outside of the server.
+/// Relies on inotify on linux and on the native file system notifications on other platforms.
+#[cfg(feature = "watch")]
+pub mod watcher {
+    use std::fs;
+    use std::io::{Error, ErrorKind, Result};
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+    use notify::{Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+    use notify::event::{ModifyKind, RenameMode};
+    use super::FileIndex;
+
+    /// Starts watching the directory backing the indexed storage recursively.
+    /// The index is kept up to date for as long as the returned watcher is alive.
+    pub fn watch(index: Arc<FileIndex>) -> Result<RecommendedWatcher> {
+
+        let root_dir = match index.storage().local_root() {
+            Some(root_dir) => root_dir.to_path_buf(),
+            None => return Err(Error::new(ErrorKind::Unsupported, "Only local storage can be watched")),
+        };
+
+        let handler_index = Arc::clone(&index);
+
+        // Events are reported with canonical paths, while the index keeps them relative to the storage root
+        let canonical_root_dir = fs::canonicalize(&root_dir)?;
+        let to_index_path = move |path: &Path| -> Option<PathBuf> {
+            path.strip_prefix(&canonical_root_dir).ok().map(|relative_path| relative_path.to_path_buf())
+        };
+
+        let mut watcher = notify::recommended_watcher(move |event: notify::Result<Event>| {
+
+            let event = match event {
+                Ok(event) => event,
+                Err(_) => return,
+            };
+
+            match event.kind {
+
+                EventKind::Modify(ModifyKind::Name(RenameMode::Both)) if event.paths.len() == 2 => {
+                    if let Some(path) = to_index_path(&event.paths[0]) {
+                        handler_index.remove_path(path);
+                    }
+                    if let Some(path) = to_index_path(&event.paths[1]) {
+                        let _ = handler_index.refresh(path);
+                    }
+                }
+
+                EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_) => {
+                    for path in event.paths.iter().filter_map(|path| to_index_path(path)) {
+                        let _ = handler_index.refresh(path);
+                    }
+                }
+
+                _ => (),
+            }
+
+        }).map_err(Error::other)?;
+
+        watcher.watch(&root_dir, RecursiveMode::Recursive).map_err(Error::other)?;
+
+        Ok(watcher)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use crate::storage::memory_backend::MemoryBackend;
+    use super::*;
+
+    fn memory_storage() -> MemoryBackend {
+
+        let storage = MemoryBackend::new();
+        storage.insert("1", b"").unwrap();
+        storage.insert("2", b"").unwrap();
+        storage.insert("3", b"").unwrap();
+        storage.insert("files/1", b"").unwrap();
+        storage.insert("files/2", b"").unwrap();
+
+        storage
+    }
+
+    #[test]
+    pub fn test_file_index_1(){
+
+        let index = FileIndex::build(Arc::new(memory_storage())).unwrap();
+
+        // 1 and files/1 share a name, the first path in order is kept
+        assert_eq!(index.len(),3);
+        assert_eq!(index.get("1").unwrap().path,PathBuf::from("1"));
+        assert_eq!(index.get("2").unwrap().path,PathBuf::from("2"));
+        assert!(index.contains("1"));
+        assert!(index.contains("2"));
+        assert!(index.contains("3"));
+        assert!(!index.contains("4"));
+
+    }
+
+    #[test]
+    pub fn test_file_index_2(){
+
+        let storage = MemoryBackend::new();
+        storage.insert("dir/1", b"content").unwrap();
+
+        let index = FileIndex::build(Arc::new(storage)).unwrap();
+        let entry = index.get("1").unwrap();
+
+        assert_eq!(entry.path,PathBuf::from("dir/1"));
+        assert_eq!(entry.size,7);
+
+    }
+
+    #[test]
+    pub fn test_file_index_3(){
+
+        let index = FileIndex::build(Arc::new(memory_storage())).unwrap();
+
+        assert!(index.reserve("4","4").is_ok());
+
+        let result = index.reserve("4","dir/4");
+
+        assert!(result.is_err());
+        assert_eq!(result.unwrap_err().kind(),ErrorKind::AlreadyExists);
+        assert_eq!(index.get("4").unwrap().path,PathBuf::from("4"));
+
+    }
+
+    #[test]
+    pub fn test_file_index_4(){
+
+        let storage = MemoryBackend::new();
+        storage.insert("3", b"").unwrap();
+
+        let index = FileIndex::build(Arc::new(storage)).unwrap();
+
+        // Only the entry pointing to the exact path is removed
+        assert!(index.remove_path("dir/3").is_none());
+        assert!(index.remove_path("3").is_some());
+        assert!(!index.contains("3"));
+
+    }
+
+    #[test]
+    pub fn test_file_index_5(){
+
+        let storage = Arc::new(MemoryBackend::new());
+        let index = FileIndex::build(storage.clone()).unwrap();
+
+        storage.insert("dir/new_file", b"").unwrap();
+        index.refresh("dir/new_file").unwrap();
+        assert!(index.contains("new_file"));
+
+        storage.delete(Path::new("dir/new_file")).unwrap();
+        index.refresh("dir/new_file").unwrap();
+        assert!(!index.contains("new_file"));
+
+        // Staged files are never indexed
+        storage.insert(".staging/staged_file", b"").unwrap();
+        index.refresh(".staging/staged_file").unwrap();
+        assert!(!index.contains("staged_file"));
+
+    }
+
+    #[cfg(feature = "watch")]
+    #[test]
+    pub fn test_file_index_6(){
+        use std::fs;
+        use std::thread;
+        use std::time::{Duration, Instant};
+        use crate::storage::local_backend::LocalBackend;
+
+        // Waits for the watcher to apply the events
+        fn wait_until(condition: impl Fn() -> bool) -> bool {
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while !condition() && Instant::now() < deadline {
+                thread::sleep(Duration::from_millis(20));
+            }
+            condition()
+        }
+
+        let directory = std::env::temp_dir().join(format!("test_file_index_{}", std::process::id()));
+
+        // A directory created just before a file may not be watched yet when the file appears
+        fs::create_dir_all(directory.join("dir")).unwrap();
+
+        let index = Arc::new(FileIndex::build(Arc::new(LocalBackend::new(&directory).unwrap())).unwrap());
+        let _watcher = watcher::watch(Arc::clone(&index)).unwrap();
+
+        // Files created and removed outside the server
+        fs::write(directory.join("dir").join("out_of_band.txt"), b"content").unwrap();
+
+        assert!(wait_until(|| index.get("out_of_band.txt").is_some_and(|entry| entry.size == 7)));
+        assert_eq!(index.get("out_of_band.txt").unwrap().path, PathBuf::from("dir/out_of_band.txt"));
+
+        fs::remove_file(directory.join("dir").join("out_of_band.txt")).unwrap();
+
+        assert!(wait_until(|| !index.contains("out_of_band.txt")));
+
+        fs::remove_dir_all(directory).unwrap();
+
+    }
+
+}
@@ -2,10 +2,36 @@ pub mod server_utils{
     pub mod file_transfer_server;
     pub mod file_transfer_client;
     pub mod server_config;
+    pub mod config_source;
     pub mod port_allocator;
+    pub mod admin;
+}
+pub mod storage{
+    pub mod storage_backend;
+    pub mod local_backend;
+    pub mod memory_backend;
+    pub mod dedup_backend;
+    #[cfg(feature = "s3")]
+    pub mod s3_backend;
 }
 pub mod mapped_file;
 pub mod constants;
 pub mod thread_pool;
 pub mod serialization;
 pub mod directory_tree;
+pub mod file_index;
+pub mod search;
+pub mod version_store;
+pub mod trash_bin;
+pub mod sharing;
+pub mod cidr;
+pub mod access_list;
+pub mod list_store;
+pub mod auto_ban;
+pub mod connection_limiter;
+pub mod session_registry;
+pub mod maintenance;
+pub mod logging;
+pub mod audit_log;
+pub mod roles;
+pub mod public_area;
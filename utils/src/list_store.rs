@@ -0,0 +1,159 @@
+use std::fs;
+use std::io::{ErrorKind, Result};
+use std::path::PathBuf;
+use std::time::SystemTime;
+use crate::access_list::AccessList;
+use crate::constants::{ACTIVE_LIST_FILE_NAME, MAINTENANCE_FILE_NAME};
+use crate::serialization::{load, save};
+use crate::server_utils::file_transfer_server::ActiveList;
+
+/// Files of the serialized lists directory holding the white list, the ban list, the active list and the saved maintenance mode.
+/// Each file is saved as soon as what it holds changes. Without a directory nothing is saved.
+#[derive(Debug, Clone, Default)]
+pub struct ListStore {
+    directory: Option<PathBuf>,
+    white_list_name: String,
+    ban_list_name: String,
+}
+
+impl ListStore {
+
+    pub fn new(directory: Option<PathBuf>, white_list_name: &str, ban_list_name: &str) -> Self {
+        Self {
+            directory,
+            white_list_name: white_list_name.to_string(),
+            ban_list_name: ban_list_name.to_string(),
+        }
+    }
+
+    pub fn save_white_list(&self, white_list: &AccessList) -> Result<()> {
+        self.save_list(&self.white_list_name, white_list)
+    }
+
+    pub fn save_ban_list(&self, ban_list: &AccessList) -> Result<()> {
+        self.save_list(&self.ban_list_name, ban_list)
+    }
+
+    pub fn save_active_list(&self, active_list: &ActiveList) -> Result<()> {
+        match &self.directory {
+            Some(directory) => save(active_list, directory.join(ACTIVE_LIST_FILE_NAME)),
+            None => Ok(()),
+        }
+    }
+
+    /// Saves the maintenance mode to restore after a restart, or forgets the saved one.
+    ///
+    pub fn save_maintenance(&self, maintenance: Option<bool>) -> Result<()> {
+
+        let Some(directory) = &self.directory else {
+            return Ok(());
+        };
+
+        match maintenance {
+            Some(enabled) => save(enabled, directory.join(MAINTENANCE_FILE_NAME)),
+            None => match fs::remove_file(directory.join(MAINTENANCE_FILE_NAME)) {
+                Err(error) if error.kind() != ErrorKind::NotFound => Err(error),
+                _ => Ok(()),
+            },
+        }
+    }
+
+    /// Reads the saved white list again, a missing file giving an empty list.
+    /// Returns None without a directory.
+    pub fn load_white_list(&self) -> Result<Option<AccessList>> {
+        self.load_list(&self.white_list_name)
+    }
+
+    /// Reads the saved ban list again, a missing file giving an empty list.
+    /// Returns None without a directory.
+    pub fn load_ban_list(&self) -> Result<Option<AccessList>> {
+        self.load_list(&self.ban_list_name)
+    }
+
+    /// Returns the list saved as the active one, if any.
+    ///
+    pub fn load_active_list(&self) -> Option<ActiveList> {
+        load(self.directory.as_ref()?.join(ACTIVE_LIST_FILE_NAME)).ok()
+    }
+
+    /// Returns the saved maintenance mode, if any.
+    ///
+    pub fn load_maintenance(&self) -> Option<bool> {
+        load(self.directory.as_ref()?.join(MAINTENANCE_FILE_NAME)).ok()
+    }
+
+    fn load_list(&self, file_name: &str) -> Result<Option<AccessList>> {
+
+        let directory = match &self.directory {
+            Some(directory) => directory,
+            None => return Ok(None),
+        };
+
+        match load(directory.join(file_name)) {
+            Ok(list) => Ok(Some(list)),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(Some(AccessList::new())),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Saves a list without the entries which already expired.
+    ///
+    fn save_list(&self, file_name: &str, list: &AccessList) -> Result<()> {
+
+        let directory = match &self.directory {
+            Some(directory) => directory,
+            None => return Ok(()),
+        };
+
+        let mut list = list.clone();
+        list.purge_expired(SystemTime::now());
+
+        save(list, directory.join(file_name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use super::*;
+
+    #[test]
+    pub fn test_list_store_1(){
+
+        let directory = std::env::temp_dir().join(format!("test_list_store_{}", std::process::id()));
+        let store = ListStore::new(Some(directory.clone()), "white_list.json", "ban_list.json");
+
+        assert!(store.load_active_list().is_none());
+        assert!(store.load_maintenance().is_none());
+
+        let mut ban_list = AccessList::new();
+        ban_list.insert("10.0.0.0/8".parse().unwrap());
+        ban_list.insert_until("127.0.0.2".parse().unwrap(), SystemTime::now());
+
+        store.save_ban_list(&ban_list).unwrap();
+        store.save_active_list(&ActiveList::WhiteList).unwrap();
+
+        // Expired entries are left out
+        let saved: AccessList = load(directory.join("ban_list.json")).unwrap();
+
+        assert_eq!(saved.len(), 1);
+        assert_eq!(store.load_ban_list().unwrap(), Some(saved));
+        assert_eq!(store.load_white_list().unwrap(), Some(AccessList::new()));
+        assert_eq!(store.load_active_list(), Some(ActiveList::WhiteList));
+
+        store.save_maintenance(Some(true)).unwrap();
+        assert_eq!(store.load_maintenance(), Some(true));
+
+        // Forgetting the mode twice is not an error
+        store.save_maintenance(None).unwrap();
+        store.save_maintenance(None).unwrap();
+        assert!(store.load_maintenance().is_none());
+
+        fs::remove_dir_all(directory).unwrap();
+
+        // Nothing is written nor read without a directory
+        assert!(ListStore::default().save_ban_list(&ban_list).is_ok());
+        assert!(ListStore::default().load_ban_list().unwrap().is_none());
+
+    }
+}
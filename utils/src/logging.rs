@@ -0,0 +1,127 @@
+use std::fs;
+use std::io::{self, Error, Result};
+use tracing::Subscriber;
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, EnvFilter, Layer};
+use crate::constants::LOG_FILE_SUFFIX;
+use crate::server_utils::server_config::{LogFormat, LogRotation, LoggingConfig};
+
+/// Keeps the log files written while alive, the pending events being flushed when dropped.
+#[must_use]
+#[derive(Debug)]
+pub struct LogGuard {
+    _worker: Option<WorkerGuard>,
+}
+
+/// Sends the log events of the whole process to the output set by the config.
+/// Without log files the events go to the standard error, apart from the console replies printed on the standard output.
+pub fn init(config: &LoggingConfig) -> Result<LogGuard> {
+
+    let (subscriber, guard) = build_subscriber(config)?;
+    subscriber.try_init().map_err(Error::other)?;
+
+    Ok(guard)
+}
+
+/// Builds the subscriber writing the events of the config, along with the guard of its log files.
+///
+fn build_subscriber(config: &LoggingConfig) -> Result<(Box<dyn Subscriber + Send + Sync>, LogGuard)> {
+
+    let filter = EnvFilter::try_new(&config.level).map_err(|error| Error::new(io::ErrorKind::InvalidInput, error))?;
+
+    let (writer, worker) = match &config.file {
+        Some(file) => {
+
+            // Created before the old files are looked up
+            fs::create_dir_all(&file.directory)?;
+
+            let mut builder = RollingFileAppender::builder()
+                .rotation(rotation(file.rotation))
+                .filename_prefix(&file.prefix)
+                .filename_suffix(LOG_FILE_SUFFIX);
+
+            if let Some(max_files) = file.max_files {
+                builder = builder.max_log_files(max_files);
+            }
+
+            let appender = builder.build(&file.directory).map_err(Error::other)?;
+            let (writer, worker) = tracing_appender::non_blocking(appender);
+
+            (BoxMakeWriter::new(writer), Some(worker))
+        }
+        None => (BoxMakeWriter::new(io::stderr), None),
+    };
+
+    // Colors are only meant for a terminal
+    let ansi = config.file.is_none() && io::IsTerminal::is_terminal(&io::stderr());
+
+    let layer = match config.format {
+        LogFormat::Pretty => fmt::layer().with_writer(writer).with_ansi(ansi).boxed(),
+        LogFormat::Json => fmt::layer().json().with_current_span(true).with_span_list(false).with_writer(writer).boxed(),
+    };
+
+    let subscriber = tracing_subscriber::registry().with(layer).with(filter);
+
+    Ok((Box::new(subscriber), LogGuard { _worker: worker }))
+}
+
+fn rotation(rotation: LogRotation) -> Rotation {
+    match rotation {
+        LogRotation::Minutely => Rotation::MINUTELY,
+        LogRotation::Hourly => Rotation::HOURLY,
+        LogRotation::Daily => Rotation::DAILY,
+        LogRotation::Never => Rotation::NEVER,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server_utils::server_config::LogFileConfig;
+
+    #[test]
+    pub fn test_logging_1(){
+
+        let directory = std::env::temp_dir().join(format!("test_logging_{}", std::process::id()));
+
+        let config = LoggingConfig {
+            level: "debug".to_string(),
+            format: LogFormat::Json,
+            file: Some(LogFileConfig { directory: directory.clone(), prefix: "server".to_string(), rotation: LogRotation::Never, max_files: None }),
+        };
+
+        let (subscriber, guard) = build_subscriber(&config).unwrap();
+
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("session", id = 7, peer = "127.0.0.2:40000", verb = tracing::field::Empty);
+            let _entered = span.enter();
+
+            span.record("verb", "GET");
+            tracing::debug!(file = "report.pdf", "Sending the file");
+            tracing::trace!("Left out by the level");
+        });
+
+        // The events are written once flushed
+        drop(guard);
+
+        let contents = fs::read_to_string(directory.join("server.log")).unwrap();
+        let event: serde_json::Value = serde_json::from_str(contents.trim_end()).unwrap();
+
+        assert_eq!(contents.lines().count(), 1);
+        assert_eq!(event["level"], "DEBUG");
+        assert_eq!(event["fields"]["message"], "Sending the file");
+        assert_eq!(event["fields"]["file"], "report.pdf");
+        assert_eq!(event["span"]["id"], 7);
+        assert_eq!(event["span"]["peer"], "127.0.0.2:40000");
+        assert_eq!(event["span"]["verb"], "GET");
+
+        fs::remove_dir_all(directory).unwrap();
+
+        assert!(build_subscriber(&LoggingConfig { level: "info,=verbose".to_string(), ..LoggingConfig::default() }).is_err());
+
+    }
+}
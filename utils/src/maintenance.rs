@@ -0,0 +1,79 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use crate::roles::is_read_verb;
+
+/// Read-only mode of the server, during which the clients may only run the verbs reading the storage.
+/// Starts as set by the config, unless an administrator switched it since.
+#[derive(Debug, Default)]
+pub struct MaintenanceMode {
+    enabled: AtomicBool,
+    set_by_admin: AtomicBool,
+}
+
+impl MaintenanceMode {
+
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled: AtomicBool::new(enabled),
+            set_by_admin: AtomicBool::new(false),
+        }
+    }
+
+    /// Restores the mode an administrator saved before the server last stopped.
+    ///
+    pub fn restored(enabled: bool) -> Self {
+        Self {
+            enabled: AtomicBool::new(enabled),
+            set_by_admin: AtomicBool::new(true),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Switches the mode on behalf of an administrator.
+    ///
+    pub fn set(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+        self.set_by_admin.store(true, Ordering::Relaxed);
+    }
+
+    /// Checks if the mode was set by an administrator rather than by the config.
+    ///
+    pub fn is_set_by_admin(&self) -> bool {
+        self.set_by_admin.load(Ordering::Relaxed)
+    }
+
+    /// Checks if a client may run the verb in the current mode.
+    ///
+    pub fn allows(&self, verb: &str) -> bool {
+        !self.is_enabled() || is_read_verb(verb)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::*;
+
+    #[test]
+    pub fn test_maintenance_mode_1(){
+
+        let maintenance = MaintenanceMode::new(false);
+
+        assert!(maintenance.allows(CREATE));
+        assert!(!maintenance.is_set_by_admin());
+
+        maintenance.set(true);
+
+        // Only the verbs reading the storage are left
+        assert!(maintenance.allows(GET));
+        assert!(maintenance.allows(LIST));
+        assert!(maintenance.allows(QUIT));
+        assert!([CREATE, UPDATE, DELETE, PUBLISH, RESTORE, UNDELETE, EMPTY_TRASH, SHARE].iter().all(|verb| !maintenance.allows(verb)));
+        assert!(maintenance.is_set_by_admin());
+
+        assert!(MaintenanceMode::restored(false).is_set_by_admin());
+
+    }
+}
@@ -83,7 +83,6 @@ impl Ord for MappedFile{
 
 
 #[cfg(test)]
-
 mod tests{
     use std::fs::OpenOptions;
     use std::io::Write;
@@ -0,0 +1,81 @@
+use std::net::Ipv4Addr;
+use std::path::{Path, PathBuf};
+use crate::roles::Role;
+use crate::server_utils::server_config::AnonymousConfig;
+
+/// Directory of the storage whose files anyone may download, anonymous clients included.
+/// Files are published to it by admins and by the configured publishers.
+#[derive(Debug)]
+pub struct PublicArea {
+    config: AnonymousConfig,
+}
+
+impl PublicArea {
+
+    pub fn new(config: AnonymousConfig) -> Self {
+        Self {
+            config,
+        }
+    }
+
+    /// Checks if anonymous access and the public area are enabled.
+    ///
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// Returns the path of the given file in the public area.
+    ///
+    pub fn path_of(&self, file_name: &str) -> PathBuf {
+        Path::new(&self.config.public_dir).join(file_name)
+    }
+
+    /// Checks if a file is stored in the public area.
+    ///
+    pub fn contains(&self, path: &Path) -> bool {
+        self.is_enabled() && path.parent() == Some(Path::new(&self.config.public_dir))
+    }
+
+    /// Checks if a client may upload, update and delete public files.
+    ///
+    pub fn can_publish(&self, client_ip: Ipv4Addr, role: Role) -> bool {
+
+        if !self.is_enabled() || role.is_anonymous() {
+            return false;
+        }
+
+        role.is_admin() || self.config.publishers.iter().any(|publishers| publishers.contains(client_ip))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_public_area_1(){
+
+        let config = AnonymousConfig {
+            enabled: true,
+            public_dir: "releases".to_string(),
+            publishers: vec!["10.0.0.0/24".parse().unwrap()],
+        };
+
+        let public = PublicArea::new(config.clone());
+
+        assert_eq!(public.path_of("app.zip"), PathBuf::from("releases/app.zip"));
+        assert!(public.contains(Path::new("releases/app.zip")));
+        assert!(!public.contains(Path::new("10-0-0-1/app.zip")));
+
+        assert!(public.can_publish(Ipv4Addr::new(10, 0, 0, 9), Role::Full));
+        assert!(public.can_publish(Ipv4Addr::new(192, 168, 0, 1), Role::Admin));
+        assert!(!public.can_publish(Ipv4Addr::new(192, 168, 0, 1), Role::Full));
+
+        // Nothing is public once anonymous access is disabled
+        let public = PublicArea::new(AnonymousConfig { enabled: false, ..config });
+
+        assert!(!public.contains(Path::new("releases/app.zip")));
+        assert!(!public.can_publish(Ipv4Addr::new(10, 0, 0, 9), Role::Admin));
+
+    }
+}
@@ -0,0 +1,248 @@
+use std::fmt;
+use std::net::Ipv4Addr;
+use std::sync::RwLock;
+use serde::{Deserialize, Serialize};
+use crate::cidr::Ipv4Cidr;
+use crate::constants::*;
+use crate::server_utils::server_config::RolesConfig;
+
+/// Verbs every role can run.
+const SESSION_VERBS: [&str; 2] = [HELP, QUIT];
+
+/// Verbs which only read the storage.
+const READ_VERBS: [&str; 9] = [GET, LIST, LIST_OWNED, SEARCH, VERSIONS, GET_VERSION, TRASH, LIST_SHARED, STAT];
+
+/// Verbs of the clients browsing the public files.
+const ANONYMOUS_VERBS: [&str; 3] = [GET, LIST, STAT];
+
+/// Checks if the verb leaves the storage untouched, which is all the read-only clients may run.
+///
+pub fn is_read_verb(verb: &str) -> bool {
+    SESSION_VERBS.contains(&verb) || READ_VERBS.contains(&verb)
+}
+
+/// Set of verbs a client may run.
+/// Admins may also read and update every file, whoever owns it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// Client without an assigned role when anonymous access is enabled, which only sees the public files.
+    Anonymous,
+    /// Can only download and list files.
+    ReadOnly,
+    /// Drop box client, which can only create new files without seeing any.
+    UploadOnly,
+    #[default]
+    Full,
+    Admin,
+}
+
+impl Role {
+
+    /// Parses a role name, case-insensitively.
+    ///
+    pub fn parse(role: &str) -> Option<Self> {
+        match role.to_lowercase().as_str() {
+            ROLE_ANONYMOUS => Some(Role::Anonymous),
+            ROLE_READ_ONLY => Some(Role::ReadOnly),
+            ROLE_UPLOAD_ONLY => Some(Role::UploadOnly),
+            ROLE_FULL => Some(Role::Full),
+            ROLE_ADMIN => Some(Role::Admin),
+            _ => None,
+        }
+    }
+
+    /// Checks if the role may run the given verb.
+    ///
+    pub fn allows(&self, verb: &str) -> bool {
+        match self {
+            Role::Anonymous => SESSION_VERBS.contains(&verb) || ANONYMOUS_VERBS.contains(&verb),
+            Role::ReadOnly => is_read_verb(verb),
+            Role::UploadOnly => SESSION_VERBS.contains(&verb) || verb == CREATE,
+            Role::Full | Role::Admin => true,
+        }
+    }
+
+    pub fn is_admin(&self) -> bool {
+        *self == Role::Admin
+    }
+
+    pub fn is_anonymous(&self) -> bool {
+        *self == Role::Anonymous
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Role::Anonymous => formatter.write_str(ROLE_ANONYMOUS),
+            Role::ReadOnly => formatter.write_str(ROLE_READ_ONLY),
+            Role::UploadOnly => formatter.write_str(ROLE_UPLOAD_ONLY),
+            Role::Full => formatter.write_str(ROLE_FULL),
+            Role::Admin => formatter.write_str(ROLE_ADMIN),
+        }
+    }
+}
+
+/// Role given to a single client or to a block of addresses.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RoleAssignment {
+    pub address: Ipv4Cidr,
+    pub role: Role,
+}
+
+/// Roles of the clients, which can be changed while the server runs.
+/// A client gets the role of the most specific block holding its address, or the default role.
+#[derive(Debug)]
+pub struct RoleTable {
+    default_role: Role,
+    assignments: RwLock<Vec<RoleAssignment>>,
+}
+
+impl RoleTable {
+
+    /// Creates the table from the configured roles.
+    /// Clients without an assigned role are anonymous when anonymous access is enabled.
+    pub fn new(config: RolesConfig, anonymous: bool) -> Self {
+
+        let table = Self {
+            default_role: if anonymous { Role::Anonymous } else { config.default_role },
+            assignments: RwLock::new(Vec::new()),
+        };
+
+        // Assigned one by one so that a block listed twice keeps its last role
+        for assignment in config.assignments {
+            table.assign(assignment.address, assignment.role);
+        }
+
+        table
+    }
+
+    pub fn default_role(&self) -> Role {
+        self.default_role
+    }
+
+    /// Returns the role of a client.
+    ///
+    pub fn role_of(&self, client_ip: Ipv4Addr) -> Role {
+        self.assignments.read().unwrap()
+            .iter()
+            .filter(|assignment| assignment.address.contains(client_ip))
+            .max_by_key(|assignment| assignment.address.prefix_len())
+            .map_or(self.default_role, |assignment| assignment.role)
+    }
+
+    /// Gives a role to a block of addresses, replacing its previous role.
+    ///
+    pub fn assign(&self, address: Ipv4Cidr, role: Role) {
+
+        let mut assignments = self.assignments.write().unwrap();
+
+        assignments.retain(|assignment| assignment.address != address);
+        assignments.push(RoleAssignment { address, role });
+    }
+
+    /// Removes the role of a block of addresses.
+    /// Returns false if the block had no role.
+    pub fn unassign(&self, address: Ipv4Cidr) -> bool {
+
+        let mut assignments = self.assignments.write().unwrap();
+        let assignments_count = assignments.len();
+
+        assignments.retain(|assignment| assignment.address != address);
+        assignments.len() != assignments_count
+    }
+
+    /// Returns the assignments sorted by address block.
+    ///
+    pub fn assignments(&self) -> Vec<RoleAssignment> {
+
+        let mut assignments = self.assignments.read().unwrap().clone();
+        assignments.sort_by_key(|assignment| assignment.address);
+
+        assignments
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cidr(cidr: &str) -> Ipv4Cidr {
+        cidr.parse().unwrap()
+    }
+
+    #[test]
+    pub fn test_role_1(){
+
+        assert!(Role::ReadOnly.allows(GET));
+        assert!(Role::ReadOnly.allows(QUIT));
+        assert!(!Role::ReadOnly.allows(CREATE));
+        assert!(!Role::ReadOnly.allows(DELETE));
+
+        assert!(Role::UploadOnly.allows(CREATE));
+        assert!(!Role::UploadOnly.allows(LIST));
+        assert!(!Role::UploadOnly.allows(GET));
+        assert!(!Role::UploadOnly.allows(UPDATE));
+
+        assert!(VERBS.iter().all(|verb| Role::Full.allows(verb)));
+        assert!(Role::Admin.is_admin());
+
+        assert!(Role::Anonymous.allows(STAT));
+        assert!(!Role::Anonymous.allows(SEARCH));
+        assert!(!Role::Anonymous.allows(CREATE));
+
+        assert_eq!(Role::parse("READ_ONLY"), Some(Role::ReadOnly));
+        assert_eq!(Role::parse("superuser"), None);
+        assert_eq!(Role::UploadOnly.to_string(), ROLE_UPLOAD_ONLY);
+
+    }
+
+    #[test]
+    pub fn test_role_table_1(){
+
+        let config: RolesConfig = serde_json::from_str(r#"{
+            "default_role": "read_only",
+            "assignments": [
+                {"address": "10.0.0.0/8", "role": "upload_only"},
+                {"address": "10.1.0.0/16", "role": "full"},
+                {"address": "10.1.2.3", "role": "admin"}
+            ]
+        }"#).unwrap();
+
+        let roles = RoleTable::new(config.clone(), false);
+
+        // The most specific block wins, whatever the order of the assignments
+        assert_eq!(roles.role_of(Ipv4Addr::new(10, 1, 2, 3)), Role::Admin);
+        assert_eq!(roles.role_of(Ipv4Addr::new(10, 1, 2, 4)), Role::Full);
+        assert_eq!(roles.role_of(Ipv4Addr::new(10, 2, 0, 1)), Role::UploadOnly);
+        assert_eq!(roles.role_of(Ipv4Addr::new(192, 168, 0, 1)), Role::ReadOnly);
+
+        // Anonymous access only changes the role of the unassigned clients
+        let roles = RoleTable::new(config, true);
+
+        assert_eq!(roles.role_of(Ipv4Addr::new(10, 2, 0, 1)), Role::UploadOnly);
+        assert_eq!(roles.role_of(Ipv4Addr::new(192, 168, 0, 1)), Role::Anonymous);
+
+    }
+
+    #[test]
+    pub fn test_role_table_2(){
+
+        let roles = RoleTable::new(RolesConfig::default(), false);
+        let client_ip = Ipv4Addr::new(127, 0, 0, 2);
+
+        assert_eq!(roles.role_of(client_ip), Role::Full);
+
+        roles.assign(cidr("127.0.0.0/8"), Role::ReadOnly);
+        roles.assign(cidr("127.0.0.0/8"), Role::UploadOnly);
+
+        assert_eq!(roles.assignments().len(), 1);
+        assert_eq!(roles.role_of(client_ip), Role::UploadOnly);
+
+        assert!(roles.unassign(cidr("127.0.0.0/8")));
+        assert!(!roles.unassign(cidr("127.0.0.0/8")));
+        assert_eq!(roles.role_of(client_ip), Role::Full);
+
+    }
+}
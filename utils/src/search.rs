@@ -0,0 +1,350 @@
+use std::io::{Error, ErrorKind, Read, Result};
+use std::path::Path;
+use std::time::SystemTime;
+use chrono::{DateTime, NaiveDate, Utc};
+use regex::{Regex, RegexBuilder};
+use crate::constants::{SEARCH_AFTER, SEARCH_BEFORE, SEARCH_CONTENT, SEARCH_CONTENT_LIMIT, SEARCH_MAX_SIZE, SEARCH_MIN_SIZE, SEARCH_REGEX};
+use crate::file_index::FileIndex;
+use crate::storage::storage_backend::{FileStat, StorageBackend};
+
+/// Pattern matched against file names, and optionally against file contents.
+#[derive(Debug, Clone)]
+pub enum SearchPattern {
+    Substring(String),
+    Regex(Regex),
+}
+
+impl SearchPattern {
+
+    /// Checks if the pattern matches the given text.
+    /// Substrings are matched case-insensitively.
+    pub fn is_match(&self, text: &str) -> bool {
+        match self {
+            SearchPattern::Substring(pattern) => text.to_lowercase().contains(pattern),
+            SearchPattern::Regex(regex) => regex.is_match(text),
+        }
+    }
+}
+
+/// Query built from the arguments of a SEARCH request.
+/// A file matches if the pattern matches its name, or its content when content search is enabled,
+/// and all the size and date filters hold.
+#[derive(Debug, Clone)]
+pub struct SearchQuery {
+    pattern: SearchPattern,
+    content: bool,
+    min_size: Option<u64>,
+    max_size: Option<u64>,
+    after: Option<SystemTime>,
+    before: Option<SystemTime>,
+}
+
+impl SearchQuery {
+
+    /// Parses the request arguments: the pattern followed by any number of options.
+    /// Options: REGEX, CONTENT, MIN_SIZE=<bytes>, MAX_SIZE=<bytes>, AFTER=<yyyy-mm-dd>, BEFORE=<yyyy-mm-dd>.
+    pub fn parse(arguments: &[&str]) -> Result<Self> {
+
+        let (pattern, options) = match arguments.split_first() {
+            Some((pattern, options)) => (*pattern, options),
+            None => return Err(Self::invalid_input("Missing search pattern")),
+        };
+
+        let mut regex = false;
+        let mut query = Self {
+            pattern: SearchPattern::Substring(pattern.to_lowercase()),
+            content: false,
+            min_size: None,
+            max_size: None,
+            after: None,
+            before: None,
+        };
+
+        for option in options {
+
+            let (key, value) = match option.split_once('=') {
+                Some((key, value)) => (key.to_uppercase(), Some(value)),
+                None => (option.to_uppercase(), None),
+            };
+
+            match (key.as_str(), value) {
+                (SEARCH_REGEX, None) => regex = true,
+                (SEARCH_CONTENT, None) => query.content = true,
+                (SEARCH_MIN_SIZE, Some(value)) => query.min_size = Some(Self::parse_size(value)?),
+                (SEARCH_MAX_SIZE, Some(value)) => query.max_size = Some(Self::parse_size(value)?),
+                (SEARCH_AFTER, Some(value)) => query.after = Some(Self::parse_date(value)?),
+                (SEARCH_BEFORE, Some(value)) => query.before = Some(Self::parse_date(value)?),
+                _ => return Err(Self::invalid_input(&format!("Unknown search option {}", option))),
+            }
+        }
+
+        if regex {
+            let regex = RegexBuilder::new(pattern)
+                .size_limit(1 << 20)
+                .build()
+                .map_err(|error| Self::invalid_input(&error.to_string()))?;
+
+            query.pattern = SearchPattern::Regex(regex);
+        }
+
+        Ok(query)
+    }
+
+    /// Searches the indexed files the client can read, reading the contents from the indexed storage if needed.
+    /// Files the client cannot read are skipped before any check, so their contents are never read.
+    /// Results are sorted by path.
+    pub fn search(&self, index: &FileIndex, can_read: &dyn Fn(&Path) -> bool) -> Vec<FileStat> {
+
+        index.entries()
+            .into_iter()
+            .filter(|entry| can_read(&entry.path) && self.matches(entry, index.storage().as_ref()))
+            .collect()
+    }
+
+    /// Checks if an entry satisfies the query.
+    ///
+    pub fn matches(&self, entry: &FileStat, storage: &dyn StorageBackend) -> bool {
+
+        if self.min_size.is_some_and(|min_size| entry.size < min_size) ||
+            self.max_size.is_some_and(|max_size| entry.size > max_size) ||
+            self.after.is_some_and(|after| entry.modified < after) ||
+            self.before.is_some_and(|before| entry.modified >= before) {
+            return false;
+        }
+
+        let file_name = match entry.path.file_name().and_then(|file_name| file_name.to_str()) {
+            Some(file_name) => file_name,
+            None => return false,
+        };
+
+        if self.pattern.is_match(file_name) {
+            return true;
+        }
+
+        self.content && entry.size <= SEARCH_CONTENT_LIMIT as u64 && self.matches_content(&entry.path, storage)
+    }
+
+    /// Checks if the content of a text file matches the pattern.
+    /// Files that cannot be read or are not valid UTF-8 never match.
+    fn matches_content(&self, path: &Path, storage: &dyn StorageBackend) -> bool {
+
+        let mut content = Vec::new();
+
+        let read_result = storage.open_read(path)
+            .and_then(|file| file.take(SEARCH_CONTENT_LIMIT as u64).read_to_end(&mut content));
+
+        if read_result.is_err() || content.contains(&0) {
+            return false;
+        }
+
+        match String::from_utf8(content) {
+            Ok(content) => self.pattern.is_match(&content),
+            Err(_) => false,
+        }
+    }
+
+    /// Parses a size given in bytes.
+    ///
+    fn parse_size(value: &str) -> Result<u64> {
+        value.parse::<u64>().map_err(|_| Self::invalid_input(&format!("Invalid size {}", value)))
+    }
+
+    /// Parses a yyyy-mm-dd date as midnight UTC.
+    ///
+    fn parse_date(value: &str) -> Result<SystemTime> {
+
+        let date = NaiveDate::parse_from_str(value, "%Y-%m-%d")
+            .map_err(|_| Self::invalid_input(&format!("Invalid date {}", value)))?;
+
+        let date_time: DateTime<Utc> = date.and_hms_opt(0, 0, 0).unwrap().and_utc();
+
+        Ok(SystemTime::from(date_time))
+    }
+
+    fn invalid_input(message: &str) -> Error {
+        Error::new(ErrorKind::InvalidInput, message.to_string())
+    }
+
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use crate::storage::local_backend::LocalBackend;
+    use crate::storage::memory_backend::MemoryBackend;
+    use crate::storage::storage_backend::{StagedWrite, WriteMode};
+    use super::*;
+
+    fn entry(path: &str, size: u64, modified: SystemTime) -> FileStat {
+        FileStat{
+            path: PathBuf::from(path),
+            size,
+            modified,
+        }
+    }
+
+    #[test]
+    pub fn test_search_query_1(){
+
+        let query = SearchQuery::parse(&["REPORT"]).unwrap();
+        let storage = MemoryBackend::new();
+
+        assert!(query.matches(&entry("1/monthly_report.txt", 10, SystemTime::now()), &storage));
+        assert!(!query.matches(&entry("1/summary.txt", 10, SystemTime::now()), &storage));
+
+    }
+
+    #[test]
+    pub fn test_search_query_2(){
+
+        let query = SearchQuery::parse(&["^[0-9]+\\.log$", "regex"]).unwrap();
+        let storage = MemoryBackend::new();
+
+        assert!(query.matches(&entry("1/2024.log", 10, SystemTime::now()), &storage));
+        assert!(!query.matches(&entry("1/a2024.log", 10, SystemTime::now()), &storage));
+
+    }
+
+    #[test]
+    pub fn test_search_query_3(){
+
+        let query = SearchQuery::parse(&["file", "min_size=10", "max_size=20"]).unwrap();
+        let storage = MemoryBackend::new();
+
+        assert!(!query.matches(&entry("1/file", 9, SystemTime::now()), &storage));
+        assert!(query.matches(&entry("1/file", 15, SystemTime::now()), &storage));
+        assert!(!query.matches(&entry("1/file", 21, SystemTime::now()), &storage));
+
+    }
+
+    #[test]
+    pub fn test_search_query_4(){
+
+        let query = SearchQuery::parse(&["file", "AFTER=2024-01-01", "BEFORE=2024-02-01"]).unwrap();
+        let storage = MemoryBackend::new();
+        let january = SearchQuery::parse_date("2024-01-15").unwrap();
+
+        assert!(query.matches(&entry("1/file", 1, january), &storage));
+        assert!(!query.matches(&entry("1/file", 1, january - Duration::from_secs(30 * 24 * 3600)), &storage));
+        assert!(!query.matches(&entry("1/file", 1, january + Duration::from_secs(30 * 24 * 3600)), &storage));
+
+    }
+
+    #[test]
+    pub fn test_search_query_5(){
+
+        assert!(SearchQuery::parse(&[]).is_err());
+        assert!(SearchQuery::parse(&["file", "MIN_SIZE=abc"]).is_err());
+        assert!(SearchQuery::parse(&["file", "AFTER=yesterday"]).is_err());
+        assert!(SearchQuery::parse(&["(", "REGEX"]).is_err());
+        assert!(SearchQuery::parse(&["file", "SOMETHING"]).is_err());
+
+    }
+
+    #[test]
+    pub fn test_search_query_6(){
+
+        let storage = MemoryBackend::new();
+        storage.insert("1/first", b"").unwrap();
+        storage.insert("2/second", b"").unwrap();
+        storage.insert("2/third", b"").unwrap();
+
+        let index = FileIndex::build(Arc::new(storage)).unwrap();
+        let results = SearchQuery::parse(&["IR"]).unwrap().search(&index, &|_| true);
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].path, PathBuf::from("1/first"));
+        assert_eq!(results[1].path, PathBuf::from("2/third"));
+
+    }
+
+    #[test]
+    pub fn test_search_query_7(){
+
+        let storage = MemoryBackend::new();
+        storage.insert("1/haystack.txt", b"there is a needle\nin this haystack\n").unwrap();
+        storage.insert("1/plain.txt", b"nothing to see here\n").unwrap();
+        storage.insert("1/binary.bin", b"needle\0binary").unwrap();
+
+        let index = FileIndex::build(Arc::new(storage)).unwrap();
+
+        let results = SearchQuery::parse(&["needle", "CONTENT"]).unwrap().search(&index, &|_| true);
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].path, PathBuf::from("1/haystack.txt"));
+
+        let results = SearchQuery::parse(&["needle"]).unwrap().search(&index, &|_| true);
+        assert!(results.is_empty());
+
+    }
+
+    #[test]
+    pub fn test_search_query_9(){
+
+        let index = FileIndex::build(Arc::new(LocalBackend::new("./tests/files8").unwrap())).unwrap();
+
+        let results = SearchQuery::parse(&["needle", "CONTENT"]).unwrap().search(&index, &|_| true);
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].path, PathBuf::from("haystack.txt"));
+
+        let results = SearchQuery::parse(&["TXT"]).unwrap().search(&index, &|_| true);
+        assert_eq!(results.len(), 2);
+
+    }
+
+    /// Memory storage recording the files opened for reading.
+    #[derive(Debug, Default)]
+    struct CountingBackend {
+        storage: MemoryBackend,
+        opened: Mutex<Vec<PathBuf>>,
+    }
+
+    impl StorageBackend for CountingBackend {
+
+        fn open_read(&self, path: &Path) -> Result<Box<dyn Read + Send>> {
+            self.opened.lock().unwrap().push(path.to_path_buf());
+            self.storage.open_read(path)
+        }
+
+        fn open_write(&self, path: &Path, mode: WriteMode) -> Result<Box<dyn StagedWrite>> {
+            self.storage.open_write(path, mode)
+        }
+
+        fn delete(&self, path: &Path) -> Result<()> {
+            self.storage.delete(path)
+        }
+
+        fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+            self.storage.rename(from, to)
+        }
+
+        fn list(&self, dir: &Path) -> Result<Vec<FileStat>> {
+            self.storage.list(dir)
+        }
+
+        fn stat(&self, path: &Path) -> Result<FileStat> {
+            self.storage.stat(path)
+        }
+    }
+
+    #[test]
+    pub fn test_search_query_8(){
+
+        let storage = Arc::new(CountingBackend::default());
+        storage.storage.insert("1/notes.txt", b"a needle of the first client\n").unwrap();
+        storage.storage.insert("2/secret.txt", b"a needle of the second client\n").unwrap();
+
+        let index = FileIndex::build(storage.clone()).unwrap();
+        let results = SearchQuery::parse(&["needle", "CONTENT"]).unwrap().search(&index, &|path| path.starts_with("1"));
+
+        // The file of the other client matches on content but is never opened
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].path, PathBuf::from("1/notes.txt"));
+        assert_eq!(*storage.opened.lock().unwrap(), vec![PathBuf::from("1/notes.txt")]);
+
+    }
+
+}
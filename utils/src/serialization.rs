@@ -1,26 +1,52 @@
-use std::fs::{create_dir_all, File};
+use std::fs::{create_dir_all, rename, File};
 use std::io::{BufReader, BufWriter, Result};
 use std::net::Ipv4Addr;
-use std::path::{Path, PathBuf};
-use serde::{Deserialize, Serialize};
+use std::path::Path;
+use std::time::Duration;
+use serde::Serialize;
 use serde::de::DeserializeOwned;
+use crate::constants::{GIGABYTE, KILOBYTE, MEGABYTE, SECONDS_PER_DAY, TEMPORARY_EXTENSION};
 
 /// Saves a serializable object into the given file path.
-///
+/// The object is written to a temporary file which replaces the previous one once synced to the disk,
+/// so that a crash leaves either the old or the new content, never a truncated file.
 pub fn save<T,P>(object: T, file_path: P) -> Result<()>
     where T: Serialize, P: AsRef<Path>
 {
 
-    match file_path.as_ref().parent(){
+    let file_path = file_path.as_ref();
+
+    match file_path.parent(){
         None => (),
         Some(parent) => create_dir_all(parent)?,
     }
 
-    let file = File::create(file_path)?;
-    let writer = BufWriter::new(file);
+    let mut temporary_path = file_path.as_os_str().to_owned();
+    temporary_path.push(TEMPORARY_EXTENSION);
+
+    let file = File::create(&temporary_path)?;
+    let mut writer = BufWriter::new(file);
+
+    serde_json::to_writer(&mut writer, &object)?;
 
-    serde_json::to_writer(writer, &object)?;
+    writer.into_inner().map_err(|error| error.into_error())?.sync_all()?;
+    rename(&temporary_path, file_path)?;
 
+    sync_parent(file_path)
+}
+
+/// Syncs the directory holding the file, making its renaming durable.
+///
+#[cfg(unix)]
+fn sync_parent(file_path: &Path) -> Result<()> {
+    match file_path.parent(){
+        Some(parent) if !parent.as_os_str().is_empty() => File::open(parent)?.sync_all(),
+        _ => File::open(".")?.sync_all(),
+    }
+}
+
+#[cfg(not(unix))]
+fn sync_parent(_file_path: &Path) -> Result<()> {
     Ok(())
 }
 
@@ -43,13 +69,80 @@ pub fn format_ipv4(ip: Ipv4Addr) -> String {
     format!("{}-{}-{}-{}", octets[0], octets[1], octets[2], octets[3])
 }
 
+/// Parses an ip whose octets are separated with dashes, as formatted by format_ipv4.
+///
+pub fn parse_formatted_ipv4(formatted_ip: &str) -> Option<Ipv4Addr> {
+    let octets = formatted_ip.split('-')
+        .map(|octet| octet.parse::<u8>().ok())
+        .collect::<Option<Vec<u8>>>()?;
 
-#[cfg(test)]
+    let octets: [u8; 4] = octets.try_into().ok()?;
+    Some(Ipv4Addr::from(octets))
+}
+
+
+/// Parses a duration written as a number followed by its unit, s, m, h or d, such as 30m.
+///
+pub fn parse_duration(duration: &str) -> Option<Duration> {
+
+    let (unit_index, _) = duration.char_indices().last()?;
+    let (amount, unit) = duration.split_at(unit_index);
+    let amount = amount.parse::<u64>().ok()?;
+
+    let seconds = match unit.to_lowercase().as_str() {
+        "s" => amount,
+        "m" => amount.checked_mul(60)?,
+        "h" => amount.checked_mul(60 * 60)?,
+        "d" => amount.checked_mul(SECONDS_PER_DAY)?,
+        _ => return None,
+    };
+
+    Some(Duration::from_secs(seconds))
+}
+
+/// Formats a duration with its two largest units, such as 1h 30m, rounding down to the second.
+///
+pub fn format_duration(duration: Duration) -> String {
+
+    let seconds = duration.as_secs();
+    let units = [(SECONDS_PER_DAY, "d"), (60 * 60, "h"), (60, "m"), (1, "s")];
+
+    let parts = units.iter()
+        .scan(seconds, |left, (unit_seconds, unit)| {
+            let amount = *left / unit_seconds;
+            *left %= unit_seconds;
+            Some((amount, unit))
+        })
+        .skip_while(|(amount, _)| *amount == 0)
+        .take(2)
+        .filter(|(amount, _)| *amount > 0)
+        .map(|(amount, unit)| format!("{}{}", amount, unit))
+        .collect::<Vec<String>>();
+
+    match parts.is_empty() {
+        true => "0s".to_string(),
+        false => parts.join(" "),
+    }
+}
+
+/// Formats a number of bytes with the largest unit it holds, such as 1.5 MB.
+///
+pub fn format_size(bytes: u64) -> String {
+
+    let units = [(GIGABYTE, "GB"), (MEGABYTE, "MB"), (KILOBYTE, "KB")];
+
+    match units.iter().find(|(unit_bytes, _)| bytes >= *unit_bytes as u64) {
+        Some((unit_bytes, unit)) => format!("{:.1} {}", bytes as f64 / *unit_bytes as f64, unit),
+        None => format!("{} B", bytes),
+    }
+}
 
+#[cfg(test)]
 mod tests{
     use std::collections::HashSet;
     use std::fs;
     use std::net::Ipv4Addr;
+    use std::path::PathBuf;
     use super::*;
 
     #[test]
@@ -63,7 +156,7 @@ mod tests{
         fs::create_dir(PathBuf::from("./tests")).unwrap_or_default();
         save(set,PathBuf::from("./tests/test_save1.json")).unwrap();
 
-        let assert = fs::exists(PathBuf::from("./tests/test_save1.json")).unwrap_or_else(|_|false);
+        let assert = fs::exists(PathBuf::from("./tests/test_save1.json")).unwrap_or(false);
 
         assert!(assert);
 
@@ -81,16 +174,33 @@ mod tests{
         fs::create_dir(PathBuf::from("./tests")).unwrap_or_default();
         save(set,PathBuf::from("./tests/test_save2.json")).unwrap();
 
-        let assert = fs::exists(PathBuf::from("./tests/test_save2.json")).unwrap_or_else(|_|false);
+        let assert = fs::exists(PathBuf::from("./tests/test_save2.json")).unwrap_or(false);
 
         assert!(assert);
 
     }
 
+    #[test]
+    fn test_save_3(){
+
+        let directory = std::env::temp_dir().join(format!("test_save3_{}", std::process::id()));
+        let file_path = directory.join("list.json");
+
+        save(vec![1, 2, 3], &file_path).unwrap();
+        save(vec![4], &file_path).unwrap();
+
+        // The previous content is replaced as a whole and no temporary file is left behind
+        assert_eq!(load::<Vec<i32>,_>(&file_path).unwrap(), vec![4]);
+        assert_eq!(fs::read_dir(&directory).unwrap().count(), 1);
+
+        fs::remove_dir_all(directory).unwrap();
+
+    }
+
     #[test]
     fn test_load_1(){
 
-        let mut set = load(PathBuf::from("./tests/test_load1.json")).unwrap_or_else(|_| HashSet::<i32>::new());
+        let set = load(PathBuf::from("./tests/test_load1.json")).unwrap_or_else(|_| HashSet::<i32>::new());
 
         assert!(set.contains(&2));
         assert!(set.contains(&4));
@@ -99,7 +209,7 @@ mod tests{
 
     #[test]
     fn test_load_2(){
-        let mut set = load(PathBuf::from("./tests/test_load2.json")).unwrap_or_else(|_| HashSet::<Ipv4Addr>::new());
+        let set = load(PathBuf::from("./tests/test_load2.json")).unwrap_or_else(|_| HashSet::<Ipv4Addr>::new());
 
         assert!(set.contains(&Ipv4Addr::new(127, 0, 0, 1)));
         assert!(set.contains(&Ipv4Addr::new(127, 0, 0, 2)));
@@ -127,4 +237,49 @@ mod tests{
 
     }
 
-}
\ No newline at end of file
+    #[test]
+    fn test_parse_formatted_ipv4_1(){
+
+        let ipv4 = Ipv4Addr::new(192, 168, 0, 2);
+
+        assert_eq!(parse_formatted_ipv4(&format_ipv4(ipv4)), Some(ipv4));
+        assert_eq!(parse_formatted_ipv4("192.168.0.2"), None);
+        assert_eq!(parse_formatted_ipv4("files"), None);
+
+    }
+
+    #[test]
+    fn test_parse_duration_1(){
+
+        assert_eq!(parse_duration("30m"), Some(Duration::from_secs(30 * 60)));
+        assert_eq!(parse_duration("2H"), Some(Duration::from_secs(2 * 60 * 60)));
+        assert_eq!(parse_duration("1d"), Some(Duration::from_secs(SECONDS_PER_DAY)));
+        assert_eq!(parse_duration("45s"), Some(Duration::from_secs(45)));
+        assert_eq!(parse_duration("m"), None);
+        assert_eq!(parse_duration("10"), None);
+        assert_eq!(parse_duration(""), None);
+        assert_eq!(parse_duration("5É"), None);
+        assert_eq!(parse_duration("é"), None);
+
+    }
+
+    #[test]
+    fn test_format_duration_1(){
+
+        assert_eq!(format_duration(Duration::from_secs(90 * 60)), "1h 30m");
+        assert_eq!(format_duration(Duration::from_secs(SECONDS_PER_DAY + 59)), "1d");
+        assert_eq!(format_duration(Duration::from_millis(29_999)), "29s");
+        assert_eq!(format_duration(Duration::ZERO), "0s");
+
+    }
+
+    #[test]
+    fn test_format_size_1(){
+
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(1536), "1.5 KB");
+        assert_eq!(format_size(3 * MEGABYTE as u64), "3.0 MB");
+
+    }
+
+}
@@ -0,0 +1,832 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::net::SocketAddrV4;
+use std::sync::{Arc, RwLock};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Sender};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use crate::access_list::AccessList;
+use crate::cidr::Ipv4Cidr;
+use crate::constants::*;
+use crate::list_store::ListStore;
+use crate::maintenance::MaintenanceMode;
+use crate::roles::{Role, RoleAssignment, RoleTable};
+use crate::serialization::{format_duration, format_size, parse_duration};
+use crate::server_utils::config_source::ConfigSource;
+use crate::server_utils::file_transfer_server::ActiveList;
+use crate::server_utils::server_config::{ConfigChanges, ServerConfig};
+use crate::session_registry::{SessionInfo, SessionRegistry};
+
+/// Entry of the white or ban list, as shown to the administrators.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ListedEntry {
+    pub address: Ipv4Cidr,
+    #[serde(default)]
+    pub expires_in_secs: Option<u64>,
+}
+
+/// Outcome of an administration command, sent as a json line through the admin socket
+/// and printed as text on the server terminal.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "reply", rename_all = "snake_case")]
+pub enum AdminReply {
+    Done,
+    ShuttingDown,
+    List {
+        list: ActiveList,
+        entries: Vec<ListedEntry>,
+    },
+    Roles {
+        default_role: Role,
+        assignments: Vec<RoleAssignment>,
+    },
+    Config {
+        config: serde_json::Value,
+        sources: BTreeMap<String, ConfigSource>,
+    },
+    Reloaded {
+        applied: Vec<String>,
+        restart_required: Vec<String>,
+    },
+    Sessions {
+        sessions: Vec<SessionInfo>,
+    },
+    Kicked {
+        id: u64,
+        peer: SocketAddrV4,
+        banned: bool,
+    },
+    Status {
+        maintenance: bool,
+        active_list: ActiveList,
+        sessions: usize,
+        uptime_secs: u64,
+    },
+    Help {
+        commands: Vec<String>,
+    },
+    Error {
+        message: String,
+    },
+}
+
+impl AdminReply {
+
+    fn error(message: &str) -> Self {
+        AdminReply::Error { message: message.to_string() }
+    }
+
+    pub fn is_error(&self) -> bool {
+        matches!(self, AdminReply::Error { .. })
+    }
+}
+
+impl fmt::Display for AdminReply {
+
+    /// Formats the reply the way the server terminal shows it, commands without output giving an empty text.
+    ///
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+
+            AdminReply::Done => Ok(()),
+
+            AdminReply::ShuttingDown => writeln!(formatter, "Shutting down"),
+
+            AdminReply::List { list, entries } => {
+
+                let list_description = match list {
+                    ActiveList::WhiteList => WHITE_LIST_DESC,
+                    ActiveList::BanList => BAN_LIST_DESC,
+                };
+
+                writeln!(formatter, "{}", list_description)?;
+                for entry in entries {
+                    match entry.expires_in_secs {
+                        None => writeln!(formatter, "{}", entry.address)?,
+                        Some(secs) => writeln!(formatter, "{} (expires in {})", entry.address, format_duration(std::time::Duration::from_secs(secs)))?,
+                    }
+                }
+                Ok(())
+            }
+
+            AdminReply::Roles { default_role, assignments } => {
+                writeln!(formatter, "{} {}", DEFAULT_ROLE_DESC, default_role)?;
+                writeln!(formatter, "{}", ROLES_DESC)?;
+                for assignment in assignments {
+                    writeln!(formatter, "{} {}", assignment.address, assignment.role)?;
+                }
+                Ok(())
+            }
+
+            AdminReply::Config { config, sources } => {
+                for (key, value) in config.as_object().into_iter().flatten() {
+                    match sources.get(key) {
+                        Some(source) => writeln!(formatter, "{}: {} [{}]", key, value, source)?,
+                        None => writeln!(formatter, "{}: {}", key, value)?,
+                    }
+                }
+                Ok(())
+            }
+
+            AdminReply::Reloaded { applied, restart_required } => {
+                writeln!(formatter, "{}", RELOADED_DESC)?;
+                if !applied.is_empty() {
+                    writeln!(formatter, "{} {}", APPLIED_SETTINGS_DESC, applied.join(", "))?;
+                }
+                if !restart_required.is_empty() {
+                    writeln!(formatter, "{} {}", RESTART_REQUIRED_DESC, restart_required.join(", "))?;
+                }
+                Ok(())
+            }
+
+            AdminReply::Sessions { sessions } => {
+
+                if sessions.is_empty() {
+                    return writeln!(formatter, "{}", NO_SESSIONS_DESC);
+                }
+
+                let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+
+                for session in sessions {
+                    writeln!(formatter, "{} {} {} {} {} {}, {}/s, for {}",
+                             session.id,
+                             session.peer,
+                             session.role,
+                             session.verb.as_deref().unwrap_or("-"),
+                             session.file.as_deref().unwrap_or("-"),
+                             format_size(session.bytes_transferred),
+                             format_size(session.bytes_per_sec),
+                             format_duration(Duration::from_secs(now.saturating_sub(session.started))))?;
+                }
+                Ok(())
+            }
+
+            AdminReply::Kicked { id, peer, banned } => match banned {
+                true => writeln!(formatter, "{} {} ({}) {}", KICKED_DESC, id, peer, BANNED_DESC),
+                false => writeln!(formatter, "{} {} ({})", KICKED_DESC, id, peer),
+            },
+
+            AdminReply::Status { maintenance, active_list, sessions, uptime_secs } => {
+
+                let active_list = match active_list {
+                    ActiveList::WhiteList => "white list",
+                    ActiveList::BanList => "ban list",
+                };
+
+                writeln!(formatter, "{} {}", MAINTENANCE_DESC, if *maintenance { "on, read-only" } else { "off" })?;
+                writeln!(formatter, "{} {}", ACTIVE_LIST_DESC, active_list)?;
+                writeln!(formatter, "{} {}", SESSION_COUNT_DESC, sessions)?;
+                writeln!(formatter, "{} {}", UPTIME_DESC, format_duration(Duration::from_secs(*uptime_secs)))
+            }
+
+            AdminReply::Help { commands } => {
+                for command in commands {
+                    writeln!(formatter, "{}", command)?;
+                }
+                Ok(())
+            }
+
+            AdminReply::Error { message } => writeln!(formatter, "{}", message),
+        }
+    }
+}
+
+/// Administration commands of the server, shared by the terminal and the admin socket:
+/// SHUTDOWN - shutdowns the server by sending a signal
+/// ADD <IP|CIDR|WILDCARD> [DURATION] - Adds a new ip or block of addresses to the white/ban list, for a while if a duration is given
+/// REMOVE <IP|CIDR|WILDCARD> - Removes an ip or block of addresses from the white/ban list
+/// LIST - Lists the white/ban list entries
+/// SWITCH - Switches current ips list to the opposite one
+/// SHOW_CONFIG - Shows the server configuration and where each setting comes from
+/// SET_ROLE <IP|CIDR> <ROLE> - Gives a role to a client or a block of addresses
+/// REMOVE_ROLE <IP|CIDR> - Removes the role of a client or a block of addresses
+/// LIST_ROLES - Lists the assigned roles
+/// RELOAD - Reads the config file and the saved lists again
+/// SESSIONS - Lists the active sessions
+/// KICK <ID> [BAN [DURATION]] - Closes a session, banning its address if asked
+/// MAINTENANCE <ON|OFF> [PERSIST] - Switches the read-only maintenance mode, saving it for the next start if asked
+/// STATUS - Shows the maintenance mode, the active list, the number of sessions and the uptime
+/// HELP - Lists the commands
+#[derive(Debug, Clone)]
+pub struct AdminConsole {
+    shutdown_signal: Arc<AtomicBool>,
+    white_list: Arc<RwLock<AccessList>>,
+    ban_list: Arc<RwLock<AccessList>>,
+    active_list: Arc<RwLock<ActiveList>>,
+    roles: Arc<RoleTable>,
+    list_store: Arc<ListStore>,
+    sessions: Arc<SessionRegistry>,
+    maintenance: Arc<MaintenanceMode>,
+    started_at: Instant,
+    reload_requests: Sender<ReloadRequest>,
+}
+
+/// Request sent to the server to reload, along with the channel its outcome is sent back on.
+pub(crate) type ReloadRequest = Sender<io::Result<ConfigChanges>>;
+
+/// Time left to the server to reload before the RELOAD command gives up waiting.
+const RELOAD_TIMEOUT: Duration = Duration::from_secs(10);
+
+impl AdminConsole {
+
+    pub(crate) fn new(shutdown_signal: Arc<AtomicBool>,
+                      white_list: Arc<RwLock<AccessList>>,
+                      ban_list: Arc<RwLock<AccessList>>,
+                      active_list: Arc<RwLock<ActiveList>>,
+                      roles: Arc<RoleTable>,
+                      list_store: Arc<ListStore>,
+                      reload_requests: Sender<ReloadRequest>) -> Self {
+        Self {
+            shutdown_signal,
+            white_list,
+            ban_list,
+            active_list,
+            roles,
+            list_store,
+            sessions: Arc::new(SessionRegistry::new()),
+            maintenance: Arc::new(MaintenanceMode::default()),
+            started_at: Instant::now(),
+            reload_requests,
+        }
+    }
+
+    /// Sets the sessions listed and kicked by the console, none being active by default.
+    ///
+    pub(crate) fn sessions(mut self, sessions: Arc<SessionRegistry>) -> Self {
+        self.sessions = sessions;
+        self
+    }
+
+    /// Sets the maintenance mode switched by the console, off by default.
+    ///
+    pub(crate) fn maintenance(mut self, maintenance: Arc<MaintenanceMode>) -> Self {
+        self.maintenance = maintenance;
+        self
+    }
+
+    /// Runs a single command line, case-insensitively.
+    ///
+    pub fn execute(&self, line: &str) -> AdminReply {
+
+        let line = line.to_uppercase();
+        let parts : Vec<&str>= line.split_whitespace().collect();
+
+        // Edge case for empty string
+        let verb = parts.first().copied().unwrap_or(EMPTY);
+        let arguments = parts.get(1..).unwrap_or_default();
+
+        match verb{
+            SHUTDOWN => self.shutdown(),
+            ADD_IP => self.add_ip(arguments),
+            REMOVE_IP => self.remove_ip(arguments),
+            LIST_IP => self.list_ip(),
+            SWITCH => self.switch(),
+            SHOW_CONFIG => self.show_config(),
+            SET_ROLE => self.set_role(arguments),
+            REMOVE_ROLE => self.remove_role(arguments),
+            LIST_ROLES => self.list_roles(),
+            RELOAD => self.reload(),
+            SESSIONS => self.list_sessions(),
+            KICK => self.kick(arguments),
+            MAINTENANCE => self.switch_maintenance(arguments),
+            STATUS => self.status(),
+            HELP => Self::help(),
+            _ => AdminReply::error(UNRECOGNIZED_INPUT),
+        }
+    }
+
+    /// Returns the list selected by the SWITCH command.
+    ///
+    fn current_list(&self, active_list: &ActiveList) -> &Arc<RwLock<AccessList>> {
+        match active_list {
+            ActiveList::BanList => &self.ban_list,
+            ActiveList::WhiteList => &self.white_list,
+        }
+    }
+
+    /// Shuts down the server by setting the shutdown signal to true.
+    ///
+    fn shutdown(&self) -> AdminReply {
+        self.shutdown_signal.store(true, Ordering::Relaxed);
+        AdminReply::ShuttingDown
+    }
+
+    /// Parses an ipv4 or a block of addresses and an optional duration,
+    /// then adds it to the current list, until the duration elapses if one is given.
+    fn add_ip(&self, arguments: &[&str]) -> AdminReply {
+
+        let entry = match arguments {
+            [ip] => ip.parse::<Ipv4Cidr>().ok().map(|cidr| (cidr, None)),
+            [ip, duration] => ip.parse::<Ipv4Cidr>().ok().zip(expiry_of(duration).map(Some)),
+            _ => None,
+        };
+
+        // The active list stays locked until the change is saved to the file of the same list
+        let active_list = self.active_list.read().unwrap();
+        let mut current_list = self.current_list(&active_list).write().unwrap();
+
+        match entry{
+            None => return AdminReply::error(WRONG_INPUT),
+            Some((cidr, None)) => current_list.insert(cidr),
+            Some((cidr, Some(expires))) => current_list.insert_until(cidr, expires),
+        };
+
+        self.save_current_list(&active_list, &current_list)
+    }
+
+    /// Parses an ipv4 or a block of addresses and removes it from the current list.
+    ///
+    fn remove_ip(&self, arguments: &[&str]) -> AdminReply {
+
+        match arguments {
+            [ip] => match ip.parse::<Ipv4Cidr>() {
+                Ok(cidr) => {
+                    let active_list = self.active_list.read().unwrap();
+                    let mut current_list = self.current_list(&active_list).write().unwrap();
+                    current_list.remove(&cidr);
+                    self.save_current_list(&active_list, &current_list)
+                }
+                Err(_) => AdminReply::error(WRONG_INPUT),
+            },
+            _ => AdminReply::error(WRONG_INPUT),
+        }
+    }
+
+    /// Lists the ips and address blocks of the current list, with the time left for the temporary ones.
+    ///
+    fn list_ip(&self) -> AdminReply {
+
+        let now = SystemTime::now();
+        let list = self.active_list.read().unwrap().clone();
+
+        let entries = self.current_list(&list).read().unwrap()
+            .entries()
+            .into_iter()
+            .filter(|entry| !entry.is_expired(now))
+            .map(|entry| ListedEntry {
+                address: entry.cidr,
+                expires_in_secs: entry.remaining(now).map(|remaining| remaining.as_secs()),
+            })
+            .collect();
+
+        AdminReply::List { list, entries }
+    }
+
+    /// Switches the current list to the opposite one.
+    ///
+    fn switch(&self) -> AdminReply {
+
+        let mut active_list = self.active_list.write().unwrap();
+        active_list.switch();
+
+        match self.list_store.save_active_list(&active_list) {
+            Ok(()) => AdminReply::Done,
+            Err(error) => AdminReply::error(&format!("{} {}", SAVE_LISTS_ERROR, error)),
+        }
+    }
+
+    /// Saves the current list after a change, which stays in effect even if it could not be saved.
+    ///
+    fn save_current_list(&self, active_list: &ActiveList, current_list: &AccessList) -> AdminReply {
+
+        let saved = match active_list {
+            ActiveList::BanList => self.list_store.save_ban_list(current_list),
+            ActiveList::WhiteList => self.list_store.save_white_list(current_list),
+        };
+
+        match saved {
+            Ok(()) => AdminReply::Done,
+            Err(error) => AdminReply::error(&format!("{} {}", SAVE_LISTS_ERROR, error)),
+        }
+    }
+
+    /// Returns the current server configuration, with the maintenance mode in effect.
+    ///
+    fn show_config(&self) -> AdminReply {
+
+        let config = ServerConfig::get_config();
+        let mut sources = config.sources.clone();
+
+        let mut value = match serde_json::to_value(&*config) {
+            Ok(value) => value,
+            Err(error) => return AdminReply::error(&error.to_string()),
+        };
+
+        if self.maintenance.is_set_by_admin() {
+            value["maintenance"] = serde_json::Value::Bool(self.maintenance.is_enabled());
+            sources.insert("maintenance".to_string(), ConfigSource::Admin);
+        }
+
+        AdminReply::Config { config: value, sources }
+    }
+
+    /// Parses an ipv4 address or block and a role, then gives the role to the addresses.
+    ///
+    fn set_role(&self, arguments: &[&str]) -> AdminReply {
+
+        let assignment = match arguments {
+            [address, role] => address.parse::<Ipv4Cidr>().ok().zip(Role::parse(role)),
+            _ => None,
+        };
+
+        match assignment{
+            None => AdminReply::error(WRONG_INPUT),
+            Some((address, role)) => {
+                self.roles.assign(address, role);
+                AdminReply::Done
+            }
+        }
+    }
+
+    /// Parses an ipv4 address or block and removes its role.
+    ///
+    fn remove_role(&self, arguments: &[&str]) -> AdminReply {
+
+        match arguments {
+            [address] => match address.parse::<Ipv4Cidr>() {
+                Ok(address) if self.roles.unassign(address) => AdminReply::Done,
+                _ => AdminReply::error(WRONG_INPUT),
+            },
+            _ => AdminReply::error(WRONG_INPUT),
+        }
+    }
+
+    /// Lists the default role and the roles given to each address.
+    ///
+    fn list_roles(&self) -> AdminReply {
+        AdminReply::Roles {
+            default_role: self.roles.default_role(),
+            assignments: self.roles.assignments(),
+        }
+    }
+
+    /// Asks the server to reload and waits for the settings it changed.
+    ///
+    fn reload(&self) -> AdminReply {
+
+        let (sender, receiver) = channel();
+
+        if self.reload_requests.send(sender).is_err() {
+            return AdminReply::error(&format!("{} {}", RELOAD_ERROR, SERVER_NOT_RUNNING));
+        }
+
+        match receiver.recv_timeout(RELOAD_TIMEOUT) {
+            Ok(Ok(changes)) => AdminReply::Reloaded { applied: changes.applied, restart_required: changes.restart_required },
+            Ok(Err(error)) => AdminReply::error(&format!("{} {}", RELOAD_ERROR, error)),
+            Err(error) => AdminReply::error(&format!("{} {}", RELOAD_ERROR, error)),
+        }
+    }
+
+    /// Lists the sessions being handled, oldest first.
+    ///
+    fn list_sessions(&self) -> AdminReply {
+        AdminReply::Sessions { sessions: self.sessions.list() }
+    }
+
+    /// Parses a session id, optionally followed by BAN and a duration, then closes the session.
+    /// The client is told why and its address is added to the ban list if asked, until the duration elapses if one is given.
+    fn kick(&self, arguments: &[&str]) -> AdminReply {
+
+        let (id, ban) = match arguments {
+            [id] => (id.parse::<u64>().ok(), None),
+            [id, KICK_BAN] => (id.parse::<u64>().ok(), Some(None)),
+            [id, KICK_BAN, duration] => match expiry_of(duration) {
+                Some(expires) => (id.parse::<u64>().ok(), Some(Some(expires))),
+                None => (None, None),
+            },
+            _ => (None, None),
+        };
+
+        let Some(id) = id else {
+            return AdminReply::error(WRONG_INPUT);
+        };
+
+        let Some(session) = self.sessions.get(id) else {
+            return AdminReply::error(SESSION_NOT_FOUND);
+        };
+
+        session.abort(SESSION_KICKED);
+
+        let peer = session.peer();
+
+        if let Some(duration) = ban {
+
+            let cidr = Ipv4Cidr::host(*peer.ip());
+            let mut ban_list = self.ban_list.write().unwrap();
+
+            match duration {
+                None => ban_list.insert(cidr),
+                Some(expires) => ban_list.insert_until(cidr, expires),
+            };
+
+            if let Err(error) = self.list_store.save_ban_list(&ban_list) {
+                return AdminReply::error(&format!("{} {}", SAVE_LISTS_ERROR, error));
+            }
+        }
+
+        AdminReply::Kicked { id, peer, banned: ban.is_some() }
+    }
+
+    /// Parses ON or OFF, optionally followed by PERSIST, then switches the maintenance mode.
+    /// The mode is saved for the next start only if asked, otherwise the saved one is forgotten and the config applies again after a restart.
+    fn switch_maintenance(&self, arguments: &[&str]) -> AdminReply {
+
+        let (enabled, persist) = match arguments {
+            [MAINTENANCE_ON] => (true, false),
+            [MAINTENANCE_OFF] => (false, false),
+            [MAINTENANCE_ON, MAINTENANCE_PERSIST] => (true, true),
+            [MAINTENANCE_OFF, MAINTENANCE_PERSIST] => (false, true),
+            _ => return AdminReply::error(WRONG_INPUT),
+        };
+
+        self.maintenance.set(enabled);
+
+        match self.list_store.save_maintenance(persist.then_some(enabled)) {
+            Ok(()) => AdminReply::Done,
+            Err(error) => AdminReply::error(&format!("{} {}", SAVE_MAINTENANCE_ERROR, error)),
+        }
+    }
+
+    /// Returns the maintenance mode, the active list, the number of sessions and the uptime.
+    ///
+    fn status(&self) -> AdminReply {
+        AdminReply::Status {
+            maintenance: self.maintenance.is_enabled(),
+            active_list: self.active_list.read().unwrap().clone(),
+            sessions: self.sessions.len(),
+            uptime_secs: self.started_at.elapsed().as_secs(),
+        }
+    }
+
+    /// Returns all the commands and their usages.
+    ///
+    fn help() -> AdminReply {
+        AdminReply::Help {
+            commands: INPUT_DESCRIPTIONS.iter().map(|description| description.to_string()).collect(),
+        }
+    }
+}
+
+/// Local unix socket accepting administration commands, one per line, each answered by a json line.
+#[cfg(unix)]
+pub mod socket {
+    use std::fs;
+    use std::io::{BufRead, BufReader, ErrorKind, Result, Write};
+    use std::os::unix::fs::PermissionsExt;
+    use std::os::unix::net::{UnixListener, UnixStream};
+    use std::path::{Path, PathBuf};
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::thread;
+    use std::thread::JoinHandle;
+    use std::time::Duration;
+    use tracing::{error, warn};
+    use super::{AdminConsole, AdminReply};
+
+    /// Binds the admin socket, replacing a socket left behind by a previous run, and serves it until the shutdown signal is set.
+    /// The socket is only accessible to the user running the server and is removed once the server stops.
+    pub fn serve(console: AdminConsole, socket_path: PathBuf, shutdown_signal: Arc<AtomicBool>) -> Result<JoinHandle<()>> {
+
+        match fs::remove_file(&socket_path) {
+            Err(error) if error.kind() != ErrorKind::NotFound => return Err(error),
+            _ => (),
+        }
+
+        let listener = UnixListener::bind(&socket_path)?;
+        fs::set_permissions(&socket_path, fs::Permissions::from_mode(0o600))?;
+        listener.set_nonblocking(true)?;
+
+        Ok(thread::spawn(move || {
+
+            while !shutdown_signal.load(Ordering::Relaxed) {
+
+                match listener.accept() {
+
+                    Ok((stream, _address)) => {
+                        let console = console.clone();
+                        thread::spawn(move || {
+                            if let Err(error) = handle_connection(stream, console) {
+                                warn!("Admin connection failed: {}", error);
+                            }
+                        });
+                    }
+
+                    Err(ref error) if error.kind() == ErrorKind::WouldBlock => thread::sleep(Duration::from_millis(100)),
+
+                    Err(error) => {
+                        error!("Admin socket failed: {}", error);
+                        break;
+                    }
+                }
+            }
+
+            let _ = fs::remove_file(&socket_path);
+        }))
+    }
+
+    /// Answers the commands of an admin connection until it is closed.
+    ///
+    fn handle_connection(stream: UnixStream, console: AdminConsole) -> Result<()> {
+
+        stream.set_nonblocking(false)?;
+        let mut writer = stream.try_clone()?;
+
+        for line in BufReader::new(stream).lines() {
+
+            let reply = console.execute(&line?);
+
+            serde_json::to_writer(&mut writer, &reply)?;
+            writer.write_all(b"\n")?;
+        }
+
+        Ok(())
+    }
+
+    /// Sends a command to the admin socket of a running server and returns its reply.
+    ///
+    pub fn send_command<P: AsRef<Path>>(socket_path: P, command: &str) -> Result<AdminReply> {
+
+        let mut stream = UnixStream::connect(socket_path)?;
+        stream.write_all(format!("{}\n", command).as_bytes())?;
+        stream.shutdown(std::net::Shutdown::Write)?;
+
+        let mut reply = String::new();
+        BufReader::new(stream).read_line(&mut reply)?;
+
+        Ok(serde_json::from_str(&reply)?)
+    }
+}
+
+/// Parses a duration and returns the time it elapses from now, or None if it is invalid or too large to represent.
+///
+fn expiry_of(duration: &str) -> Option<SystemTime> {
+    SystemTime::now().checked_add(parse_duration(duration)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::server_utils::server_config::RolesConfig;
+
+    fn console(active_list: ActiveList) -> (Arc<AtomicBool>, AdminConsole) {
+
+        let shutdown_signal = Arc::new(AtomicBool::new(false));
+        let console = AdminConsole::new(Arc::clone(&shutdown_signal),
+                                        Arc::new(RwLock::new(AccessList::new())),
+                                        Arc::new(RwLock::new(AccessList::new())),
+                                        Arc::new(RwLock::new(active_list)),
+                                        Arc::new(RoleTable::new(RolesConfig::default(), false)),
+                                        Arc::new(ListStore::default()),
+                                        channel().0);
+
+        (shutdown_signal, console)
+    }
+
+    #[test]
+    pub fn test_admin_console_1(){
+
+        let (_, console) = console(ActiveList::BanList);
+
+        assert_eq!(console.execute("add 10.0.0.0/8"), AdminReply::Done);
+        assert_eq!(console.execute("ADD 127.0.0.2 30m"), AdminReply::Done);
+        assert!(console.execute("ADD 127.0.0.300").is_error());
+        assert!(console.execute("ADD 1.2.3.4 5é").is_error());
+        assert!(console.execute("ADD 1.2.3.4 200000000000000d").is_error());
+
+        let entries = match console.execute("LIST") {
+            AdminReply::List { list: ActiveList::BanList, entries } => entries,
+            reply => panic!("Unexpected reply {:?}", reply),
+        };
+
+        assert_eq!(entries[0], ListedEntry { address: "10.0.0.0/8".parse().unwrap(), expires_in_secs: None });
+        assert!(entries[1].expires_in_secs.is_some_and(|secs| secs <= 1800));
+
+        // The commands then apply to the white list
+        assert_eq!(console.execute("SWITCH"), AdminReply::Done);
+        assert_eq!(console.execute("LIST"), AdminReply::List { list: ActiveList::WhiteList, entries: Vec::new() });
+
+    }
+
+    #[test]
+    pub fn test_admin_console_2(){
+
+        let (shutdown_signal, console) = console(ActiveList::WhiteList);
+
+        assert_eq!(console.execute("SET_ROLE 10.0.0.0/24 read_only"), AdminReply::Done);
+        assert!(console.execute("REMOVE_ROLE 10.0.1.0/24").is_error());
+
+        assert_eq!(console.execute("LIST_ROLES").to_string(), "Default role: full\nRoles:\n10.0.0.0/24 read_only\n");
+        assert!(console.execute("RELOAD").is_error());
+        assert_eq!(console.execute("").to_string(), "Unrecognized input\n");
+
+        assert_eq!(console.execute("SHUTDOWN"), AdminReply::ShuttingDown);
+        assert!(shutdown_signal.load(Ordering::Relaxed));
+
+    }
+
+    #[test]
+    pub fn test_admin_console_3(){
+
+        let ban_list = Arc::new(RwLock::new(AccessList::new()));
+        let sessions = Arc::new(SessionRegistry::new());
+        let console = AdminConsole::new(Arc::new(AtomicBool::new(false)),
+                                        Arc::new(RwLock::new(AccessList::new())),
+                                        Arc::clone(&ban_list),
+                                        Arc::new(RwLock::new(ActiveList::WhiteList)),
+                                        Arc::new(RoleTable::new(RolesConfig::default(), false)),
+                                        Arc::new(ListStore::default()),
+                                        channel().0)
+            .sessions(Arc::clone(&sessions));
+
+        assert_eq!(console.execute("SESSIONS").to_string(), "No active sessions\n");
+
+        let peer = "127.0.0.2:40000".parse().unwrap();
+        let first = sessions.register(peer, Role::Full);
+        let second = sessions.register(peer, Role::Full);
+
+        first.set_request("GET", Some("report.pdf"));
+
+        match console.execute("SESSIONS") {
+            AdminReply::Sessions { sessions } => assert_eq!(sessions[0].file.as_deref(), Some("report.pdf")),
+            reply => panic!("Unexpected reply {:?}", reply),
+        }
+
+        assert_eq!(console.execute("KICK 1"), AdminReply::Kicked { id: 1, peer, banned: false });
+        assert_eq!(first.abort_reason(), Some(SESSION_KICKED));
+        assert!(!second.is_aborted());
+
+        // The address is banned even though the white list is the active one
+        assert_eq!(console.execute("kick 2 ban 1h").to_string(), "Kicked session 2 (127.0.0.2:40000) and banned\n");
+        assert!(ban_list.read().unwrap().contains(*peer.ip()));
+
+        assert!(console.execute("KICK 3").is_error());
+        assert!(console.execute("KICK 2 BAN FOREVER").is_error());
+        assert!(console.execute("KICK 2 BAN 200000000000000d").is_error());
+
+    }
+
+    #[test]
+    pub fn test_admin_console_4(){
+
+        let directory = std::env::temp_dir().join(format!("test_admin_console_4_{}", std::process::id()));
+        let list_store = Arc::new(ListStore::new(Some(directory.clone()), "white_list.json", "ban_list.json"));
+        let maintenance = Arc::new(MaintenanceMode::new(false));
+        std::fs::create_dir_all(&directory).unwrap();
+
+        let console = AdminConsole::new(Arc::new(AtomicBool::new(false)),
+                                        Arc::new(RwLock::new(AccessList::new())),
+                                        Arc::new(RwLock::new(AccessList::new())),
+                                        Arc::new(RwLock::new(ActiveList::BanList)),
+                                        Arc::new(RoleTable::new(RolesConfig::default(), false)),
+                                        Arc::clone(&list_store),
+                                        channel().0)
+            .maintenance(Arc::clone(&maintenance));
+
+        assert_eq!(console.execute("maintenance on persist"), AdminReply::Done);
+        assert!(maintenance.is_enabled());
+        assert_eq!(list_store.load_maintenance(), Some(true));
+
+        assert_eq!(console.execute("STATUS").to_string(), "Maintenance: on, read-only\nActive list: ban list\nSessions: 0\nUptime: 0s\n");
+
+        match console.execute("SHOW_CONFIG") {
+            AdminReply::Config { config, sources } => {
+                assert_eq!(config["maintenance"], serde_json::Value::Bool(true));
+                assert_eq!(sources["maintenance"], ConfigSource::Admin);
+            }
+            reply => panic!("Unexpected reply {:?}", reply),
+        }
+
+        // Without PERSIST the saved mode is forgotten
+        assert_eq!(console.execute("MAINTENANCE OFF"), AdminReply::Done);
+        assert!(!maintenance.is_enabled());
+        assert_eq!(list_store.load_maintenance(), None);
+
+        assert!(console.execute("MAINTENANCE").is_error());
+        assert!(console.execute("MAINTENANCE READ_ONLY").is_error());
+
+        std::fs::remove_dir_all(directory).unwrap();
+
+    }
+
+    #[test]
+    pub fn test_admin_reply_1(){
+
+        let reply = AdminReply::List {
+            list: ActiveList::BanList,
+            entries: vec![ListedEntry { address: "127.0.0.1".parse().unwrap(), expires_in_secs: Some(90) }],
+        };
+
+        let json = serde_json::to_string(&reply).unwrap();
+
+        assert_eq!(json, r#"{"reply":"list","list":"BanList","entries":[{"address":"127.0.0.1","expires_in_secs":90}]}"#);
+        assert_eq!(serde_json::from_str::<AdminReply>(&json).unwrap(), reply);
+        assert_eq!(reply.to_string(), "Banned ips:\n127.0.0.1 (expires in 1m 30s)\n");
+
+        let reply = AdminReply::Reloaded { applied: vec!["buffer_size".to_string()], restart_required: Vec::new() };
+
+        assert_eq!(reply.to_string(), "Reloaded\nApplied: buffer_size\n");
+
+    }
+}
@@ -0,0 +1,175 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use crate::constants::CONFIG_ENV_PREFIX;
+
+/// Layer a setting was taken from, the later layers replacing the earlier ones.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConfigSource {
+    Default,
+    File,
+    Environment,
+    CommandLine,
+    /// Changed by an administration command while the server runs.
+    Admin,
+}
+
+impl fmt::Display for ConfigSource {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigSource::Default => formatter.write_str("default"),
+            ConfigSource::File => formatter.write_str("file"),
+            ConfigSource::Environment => formatter.write_str("environment"),
+            ConfigSource::CommandLine => formatter.write_str("command_line"),
+            ConfigSource::Admin => formatter.write_str("admin"),
+        }
+    }
+}
+
+/// Top level settings of a config merged layer by layer, keeping track of where each one comes from.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigLayers {
+    settings: Map<String, Value>,
+    sources: BTreeMap<String, ConfigSource>,
+}
+
+impl ConfigLayers {
+
+    /// Starts from the defaults, serialized from the config type.
+    ///
+    pub fn new<T: Serialize>(defaults: &T) -> Result<Self> {
+
+        let mut layers = Self::default();
+        layers.merge(Self::settings_of(serde_json::to_value(defaults)?)?, ConfigSource::Default);
+
+        Ok(layers)
+    }
+
+    /// Replaces the settings with the ones of a config file.
+    ///
+    pub fn merge_file(&mut self, settings: Value) -> Result<()> {
+        self.merge(Self::settings_of(settings)?, ConfigSource::File);
+        Ok(())
+    }
+
+    /// Replaces the settings with the environment variables named after them,
+    /// such as FTP_BUFFER_SIZE for buffer_size.
+    /// Values are read as JSON, except for the text settings and the values which are not valid JSON.
+    pub fn merge_env<F>(&mut self, lookup: F) where F: Fn(&str) -> Option<String> {
+
+        let settings = self.settings.iter()
+            .filter_map(|(key, current)| {
+                let value = lookup(&format!("{}{}", CONFIG_ENV_PREFIX, key.to_uppercase()))?;
+                Some((key.clone(), Self::parse_env_value(current, value)))
+            })
+            .collect::<Map<String, Value>>();
+
+        self.merge(settings, ConfigSource::Environment);
+    }
+
+    /// Builds the config from the merged settings.
+    /// Returns it along with the source of each setting.
+    pub fn build<T: DeserializeOwned>(self) -> Result<(T, BTreeMap<String, ConfigSource>)> {
+        Ok((serde_json::from_value(Value::Object(self.settings))?, self.sources))
+    }
+
+    fn merge(&mut self, settings: Map<String, Value>, source: ConfigSource) {
+        for (key, value) in settings {
+            self.sources.insert(key.clone(), source);
+            self.settings.insert(key, value);
+        }
+    }
+
+    fn settings_of(value: Value) -> Result<Map<String, Value>> {
+        match value {
+            Value::Object(settings) => Ok(settings),
+            _ => Err(Error::new(ErrorKind::InvalidData, "The config must be a table of settings")),
+        }
+    }
+
+    fn parse_env_value(current: &Value, value: String) -> Value {
+        if current.is_string() {
+            return Value::String(value);
+        }
+        serde_json::from_str(&value).unwrap_or(Value::String(value))
+    }
+}
+
+/// Reads the settings of a config file, written in TOML if it has the toml extension and in JSON otherwise.
+///
+pub fn read_config_file<P: AsRef<Path>>(path: P) -> Result<Value> {
+
+    let path = path.as_ref();
+    let contents = fs::read_to_string(path)?;
+
+    if path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("toml")) {
+        return toml::from_str(&contents).map_err(|error| Error::new(ErrorKind::InvalidData, error.message().to_string()));
+    }
+
+    Ok(serde_json::from_str(&contents)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Settings {
+        name: String,
+        size: usize,
+        limit: Option<usize>,
+    }
+
+    #[test]
+    pub fn test_config_layers_1(){
+
+        let defaults = Settings { name: "server".to_string(), size: 10, limit: None };
+        let mut layers = ConfigLayers::new(&defaults).unwrap();
+
+        layers.merge_file(json!({"size": 20, "limit": 5})).unwrap();
+        layers.merge_env(|key| match key {
+            "FTP_NAME" => Some("123".to_string()),
+            "FTP_LIMIT" => Some("7".to_string()),
+            _ => None,
+        });
+
+        let (settings, sources) = layers.build::<Settings>().unwrap();
+
+        // Text settings are kept as they are, even when they look like numbers
+        assert_eq!(settings, Settings { name: "123".to_string(), size: 20, limit: Some(7) });
+        assert_eq!(sources["name"], ConfigSource::Environment);
+        assert_eq!(sources["size"], ConfigSource::File);
+        assert_eq!(sources["limit"], ConfigSource::Environment);
+
+        assert!(ConfigLayers::new(&defaults).unwrap().merge_file(json!([1, 2])).is_err());
+
+    }
+
+    #[test]
+    pub fn test_read_config_file_1(){
+
+        let directory = std::env::temp_dir().join("test_read_config_file_1");
+        fs::create_dir_all(&directory).unwrap();
+
+        let toml_path = directory.join("config.toml");
+        let json_path = directory.join("config.json");
+
+        fs::write(&toml_path, "size = 20\n\n[limits]\nmax = 3\n").unwrap();
+        fs::write(&json_path, r#"{"size": 20, "limits": {"max": 3}}"#).unwrap();
+
+        assert_eq!(read_config_file(&toml_path).unwrap(), read_config_file(&json_path).unwrap());
+
+        fs::write(&toml_path, "size = ").unwrap();
+        assert_eq!(read_config_file(&toml_path).unwrap_err().kind(), ErrorKind::InvalidData);
+
+        fs::remove_dir_all(&directory).unwrap();
+
+    }
+}
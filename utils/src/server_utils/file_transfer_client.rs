@@ -2,7 +2,7 @@ use std::io;
 use std::net::{Shutdown, SocketAddrV4, TcpStream};
 use io::Result;
 use std::io::{BufRead, BufReader, Read, Write};
-use crate::constants::{KILOBYTE, CREATE, UPDATE, READY_TO_RECEIVE, EMPTY,QUIT};
+use crate::constants::{KILOBYTE, CREATE, UPDATE, READY_TO_RECEIVE, EMPTY, QUIT, REJECTED_PORT};
 
 const BUFFER_SIZE: usize = 4 * KILOBYTE;
 
@@ -38,17 +38,25 @@ impl FileTransferClient {
             let parts: Vec<&str> = line.split_whitespace().collect();
 
             // write the request
-            command_stream.write(line.as_bytes())?;
+            command_stream.write_all(line.as_bytes())?;
 
             // wait and read the data connection port
-            command_stream.read(&mut buffer)?;
+            command_stream.read_exact(&mut buffer[..3])?;
             let port = u16::from_be_bytes([buffer[0], buffer[1]]);
 
+            // The server sends the reason it rejected the connection instead of a data port
+            if port == REJECTED_PORT {
+                let mut reason = String::new();
+                command_stream.read_to_string(&mut reason)?;
+                print!("{reason}");
+                break 'reader_loop;
+            }
+
             // connect to the data stream and read responses
-            let data_stream = TcpStream::connect((self.client_address.ip().clone(),port))?;
+            let data_stream = TcpStream::connect((*self.client_address.ip(),port))?;
 
             // edge case
-            let verb = if parts.len() == 0{
+            let verb = if parts.is_empty(){
                 EMPTY
             }else{
                 &parts[0].to_uppercase()
@@ -126,7 +134,7 @@ impl FileTransferClient {
                 break;
             }
 
-            data_stream.write(line.as_bytes())?;
+            data_stream.write_all(line.as_bytes())?;
             data_stream.flush()?;
 
 
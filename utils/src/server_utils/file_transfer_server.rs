@@ -1,46 +1,88 @@
-use std::{fs, io, mem, thread};
+use std::{io, thread};
 use std::net::{Ipv4Addr, Shutdown, SocketAddr, SocketAddrV4, TcpListener, TcpStream};
 use io::Result;
-use std::collections::HashSet;
-use std::fs::{create_dir_all, remove_file, OpenOptions};
+use std::collections::{HashMap, HashSet};
+use std::fs::create_dir_all;
 use std::io::{BufRead, BufReader, Read, Write};
 use std::net::SocketAddr::V6;
 use std::net::SocketAddr::V4;
-use std::path::{PathBuf};
+use std::path::{Path, PathBuf};
 use std::sync::{Arc, OnceLock, RwLock};
 use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::channel;
 use std::thread::JoinHandle;
-use std::time::Duration;
-use memmap2::Mmap;
+use std::time::{Duration, Instant, SystemTime};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use tracing::{debug, error, info, info_span, warn};
 use crate::constants::*;
-use crate::directory_tree::DirectoryTree;
+use crate::access_list::AccessList;
+use crate::audit_log::{AuditLog, TransferDirection, TransferRecord};
+use crate::auto_ban::{AutoBan, AutoBanner, Offence};
+use crate::connection_limiter::ConnectionLimiter;
+use crate::file_index::FileIndex;
+use crate::list_store::ListStore;
+use crate::maintenance::MaintenanceMode;
+use crate::public_area::PublicArea;
+use crate::roles::RoleTable;
+use crate::search::SearchQuery;
+use crate::session_registry::{Session, SessionRegistry};
 use crate::server_utils::file_transfer_server::ActiveList::{BanList, WhiteList};
-use crate::mapped_file::MappedFile;
-use crate::serialization::{format_ipv4, load, save};
-use crate::server_utils::port_allocator::PortAllocator;
-use crate::server_utils::server_config::ServerConfig;
+use crate::serialization::{format_duration, format_ipv4, load, parse_formatted_ipv4};
+use crate::server_utils::admin;
+use crate::server_utils::admin::{AdminConsole, AdminReply, ReloadRequest};
+use crate::server_utils::port_allocator::{PortAllocator, PortLease};
+use crate::server_utils::server_config::{AnonymousConfig, AuditConfig, AutoBanConfig, ConnectionLimitsConfig, ConfigChanges, RolesConfig, ServerConfig, TrashConfig, VersioningConfig};
+use crate::storage::dedup_backend::DedupBackend;
+use crate::storage::local_backend::LocalBackend;
+use crate::storage::storage_backend::{validate_path, FileStat, StagedWrite, StorageBackend, WriteMode};
+use crate::sharing::{Access, ShareRegistry};
 use crate::thread_pool::ThreadPool;
+use crate::trash_bin::TrashBin;
+use crate::version_store::VersionStore;
 
-/// Basic file transfer server.
-///
-
-type ProtectedSet<T> = Arc<RwLock<HashSet<T>>>;
 type ProtectedType<T> = Arc<RwLock<T>>;
 
 static PORT_ALLOCATOR: OnceLock<Arc<PortAllocator>> = OnceLock::new();
+static AUDIT_LOG: OnceLock<AuditLog> = OnceLock::new();
 
+/// Basic file transfer server.
+///
 #[derive(Debug)]
 pub struct FileTransferServer {
     command_server_address: SocketAddrV4,
-    data_directory: PathBuf,
-    serialized_lists_directory: Option<PathBuf>,
+    storage: Arc<dyn StorageBackend>,
+    versions: Arc<VersionStore>,
+    trash: Arc<TrashBin>,
+    shares: Arc<ShareRegistry>,
+    roles: Arc<RoleTable>,
+    public: Arc<PublicArea>,
+    auto_ban: Arc<AutoBanner>,
+    connection_limiter: Arc<ConnectionLimiter>,
+    sessions: Arc<SessionRegistry>,
+    maintenance: Arc<MaintenanceMode>,
+    admin_socket_path: Option<PathBuf>,
+    stdin_console: bool,
+    list_store: Arc<ListStore>,
 
     active_list: ProtectedType<ActiveList>,
-    white_list: ProtectedSet<Ipv4Addr>,
-    ban_list: ProtectedSet<Ipv4Addr>,
-    white_list_name: String,
-    ban_list_name: String,
+    white_list: ProtectedType<AccessList>,
+    ban_list: ProtectedType<AccessList>,
+}
+
+/// State shared by the handlers of the client requests.
+///
+#[derive(Clone)]
+struct ClientContext {
+    file_index: Arc<FileIndex>,
+    versions: Arc<VersionStore>,
+    trash: Arc<TrashBin>,
+    shares: Arc<ShareRegistry>,
+    roles: Arc<RoleTable>,
+    public: Arc<PublicArea>,
+    auto_ban: Arc<AutoBanner>,
+    sessions: Arc<SessionRegistry>,
+    maintenance: Arc<MaintenanceMode>,
 }
 
 impl FileTransferServer {
@@ -48,63 +90,162 @@ impl FileTransferServer {
     /// Binds the command server to its address, creates the storage directory and sets the current directory.
     /// Handles each new client in a loop using a thread pool.
     ///
-    pub fn start(mut self) -> Result<()>{
+    pub fn start(self) -> Result<()>{
 
         // Bind and set non-blocking to true
         let command_server = TcpListener::bind(self.command_server_address)?;
         command_server.set_nonblocking(true)?;
 
-        println!("Server started on {}", self.command_server_address);
+        info!("Server started on {}", self.command_server_address);
+
+        if self.maintenance.is_enabled() {
+            warn!("Maintenance mode is on, only the reading requests are handled");
+        }
 
         // Init the shutdown signal, create the data directory and set the current directory to iy
         let shutdown_signal = Arc::new(AtomicBool::new(false));
 
+        // Shut down on SIGTERM and SIGINT, a second signal exiting at once
+        #[cfg(unix)]
+        for signal in [signal_hook::consts::SIGTERM, signal_hook::consts::SIGINT] {
+            signal_hook::flag::register_conditional_shutdown(signal, 1, Arc::clone(&shutdown_signal))?;
+            signal_hook::flag::register(signal, Arc::clone(&shutdown_signal))?;
+        }
+
+        // Index the storage once, it will be kept up to date by each request
+        let file_index = Arc::new(FileIndex::build(Arc::clone(&self.storage))?);
+        info!("Indexed {} files", file_index.len());
+
+        // Drop the versions and trashed files which expired while the server was down
+        Self::sweep(&self.versions, &self.trash)?;
+
+        // Keep the index consistent with changes made outside the server while the watcher is alive
+        #[cfg(feature = "watch")]
+        let _watcher = match ServerConfig::get_watch_data_directory() && self.storage.local_root().is_some(){
+            true => Some(crate::file_index::watcher::watch(Arc::clone(&file_index))?),
+            false => None,
+        };
+
+        #[cfg(not(feature = "watch"))]
+        if ServerConfig::get_watch_data_directory(){
+            warn!("Watching the data directory is not supported by this build");
+        }
 
         // Start the thread pool and the input thread
         let mut thread_pool = ThreadPool::new(ServerConfig::get_server_num_threads());
 
+        // Reload on SIGHUP and on the RELOAD command
+        let reload_signal = Arc::new(AtomicBool::new(false));
+        let (reload_sender, reload_requests) = channel::<ReloadRequest>();
 
-        let input_thread_handle = Self::input_thread(Arc::clone(&shutdown_signal),
-                                                                         Arc::clone(&self.white_list),
-                                                                         Arc::clone(&self.ban_list),
-                                                                         Arc::clone(&self.active_list));
+        #[cfg(unix)]
+        signal_hook::flag::register(signal_hook::consts::SIGHUP, Arc::clone(&reload_signal))?;
+
+
+        let console = AdminConsole::new(Arc::clone(&shutdown_signal),
+                                        Arc::clone(&self.white_list),
+                                        Arc::clone(&self.ban_list),
+                                        Arc::clone(&self.active_list),
+                                        Arc::clone(&self.roles),
+                                        Arc::clone(&self.list_store),
+                                        reload_sender)
+            .sessions(Arc::clone(&self.sessions))
+            .maintenance(Arc::clone(&self.maintenance));
+
+        let input_thread_handle = match self.stdin_console {
+            true => Some(Self::input_thread(console.clone())),
+            false => None,
+        };
+
+        let admin_thread_handle = match &self.admin_socket_path {
+            Some(socket_path) => Self::admin_thread(console, socket_path.clone(), Arc::clone(&shutdown_signal))?,
+            None => None,
+        };
+
+        let sweeper_thread_handle = Self::sweeper_thread(Arc::clone(&shutdown_signal),
+                                                         Arc::clone(&self.versions),
+                                                         Arc::clone(&self.trash),
+                                                         Arc::clone(&self.white_list),
+                                                         Arc::clone(&self.ban_list),
+                                                         Arc::clone(&self.auto_ban));
+
+        let context = ClientContext {
+            file_index,
+            versions: Arc::clone(&self.versions),
+            trash: Arc::clone(&self.trash),
+            shares: Arc::clone(&self.shares),
+            roles: Arc::clone(&self.roles),
+            public: Arc::clone(&self.public),
+            auto_ban: Arc::clone(&self.auto_ban),
+            sessions: Arc::clone(&self.sessions),
+            maintenance: Arc::clone(&self.maintenance),
+        };
 
         while !shutdown_signal.load(Ordering::Relaxed) {
 
+            let reload_request = reload_requests.try_recv().ok();
+
+            if reload_request.is_some() || reload_signal.swap(false, Ordering::Relaxed) {
+
+                let reloaded = self.reload(&mut thread_pool);
+
+                match &reloaded {
+                    Ok(changes) => info!(applied = ?changes.applied, restart_required = ?changes.restart_required, "Reloaded"),
+                    Err(error) => error!("{} {}", RELOAD_ERROR, error),
+                }
+
+                if let Some(reload_request) = reload_request {
+                    let _ = reload_request.send(reloaded);
+                }
+            }
+
             // Non-blocking accept in order to handle the shutdown signal
             match command_server.accept(){
 
                 Ok((stream,address)) =>{
                     let signal_clone = Arc::clone(&shutdown_signal);
-                    let data_dir_clone = self.data_directory.clone();
+                    let context_clone = context.clone();
 
                     let client_ip = match Self::ipv4_from_sockaddr(address){
                         Some(addr) => addr,
                         None => continue,
                     };
 
-                    println!("{address:?}");
-                    // Handle the client
+                    debug!(peer = %address, "Accepted a connection");
 
                     match self.active_list.read().unwrap().clone(){
 
                         // Case when the ban list is selected and the client is banned
-                        BanList if self.ban_list.read().unwrap().contains(&client_ip) => {
+                        BanList if self.ban_list.read().unwrap().contains(client_ip) => {
 
                             stream.shutdown(Shutdown::Both)?;
                             continue;
                         },
 
                         // Case when the white list is selected and the client is not on the white list
-                        WhiteList if !self.white_list.read().unwrap().contains(&client_ip) => {
+                        WhiteList if !self.white_list.read().unwrap().contains(client_ip) => {
                             stream.shutdown(Shutdown::Both)?;
                             continue;
                         },
 
-                        // Case when the client has access
-                        _ => thread_pool.execute(move || {
-                                Self::handle_client_once(stream,Arc::clone(&signal_clone),data_dir_clone).unwrap();
+                        // Case when the client floods the server and gets banned by this very connection
+                        _ if Self::record_offence(&self.auto_ban, client_ip, Offence::Connection) && self.active_list.read().unwrap().is_ban_list() => {
+                            stream.shutdown(Shutdown::Both)?;
+                            continue;
+                        },
+
+                        // Case when the client has access, as long as neither it nor the server has too many sessions
+                        _ => match self.connection_limiter.try_acquire(client_ip) {
+
+                            None => Self::reject(stream, TOO_MANY_CONNECTIONS),
+
+                            Some(permit) => thread_pool.execute(move || {
+                                let _permit = permit;
+                                if let Err(error) = Self::handle_client_once(stream,Arc::clone(&signal_clone),context_clone) {
+                                    error!(%client_ip, "Failed to handle the client: {}", error);
+                                }
                             }),
+                        },
 
                     }
 
@@ -125,193 +266,260 @@ impl FileTransferServer {
 
         }
 
-        // drop the thread pool and wait for the input pool to finish
+        // Stop accepting clients, let the ongoing transfers finish for a while then abort the others
+        drop(command_server);
+        self.drain();
+
+        // drop the thread pool and wait for the admin and sweeper threads to finish
         drop(thread_pool);
-        input_thread_handle.join().unwrap()?;
-        Ok(())
+
+        // The input thread stays blocked on the terminal when the shutdown came from the admin socket
+        if let Some(input_thread_handle) = input_thread_handle.filter(|handle| handle.is_finished()) {
+            input_thread_handle.join().unwrap()?;
+        }
+
+        if let Some(admin_thread_handle) = admin_thread_handle {
+            admin_thread_handle.join().unwrap();
+        }
+
+        sweeper_thread_handle.join().unwrap();
+
+        self.save_lists()
     }
 
-    /// STDIN thread waiting to receive commands:
-    /// SHUTDOWN - shutdowns the server by sending a signal
-    /// ADD <IP> - Adds a new ip to the white/ban list
-    /// REMOVE <IP> - Removes an ip from the white/ban list
-    /// LIST - Lists the white/ban list ips
-    /// SWITCH - Switches current ips list to the opposite one
-    /// SWITCH - Switches current ips list to the opposite one
-    /// HELP - Lists the commands
-    fn input_thread(shutdown_signal: Arc<AtomicBool>,
-                    white_list: ProtectedSet<Ipv4Addr>,
-                    ban_list: ProtectedSet<Ipv4Addr>,
-                    active_list: ProtectedType<ActiveList>) -> JoinHandle<Result<()>> {
+    /// Waits for the ongoing sessions to end until the shutdown timeout elapses, then aborts their transfers.
+    /// The clients waiting for a worker are rejected as soon as they get one.
+    fn drain(&self) {
 
-        thread::spawn(move || {
+        let timeout = ServerConfig::get_shutdown_timeout();
+        let deadline = Instant::now() + timeout;
+
+        let sessions = self.connection_limiter.total_sessions();
 
-            let mut reader = BufReader::new(io::stdin());
+        if sessions > 0 {
+            info!("Waiting up to {}s for {} sessions to end", timeout.as_secs(), sessions);
+        }
 
+        while self.connection_limiter.total_sessions() > 0 && Instant::now() < deadline {
+            thread::sleep(TRANSFER_POLL_INTERVAL);
+        }
 
-            let mut current_list: ProtectedSet<Ipv4Addr> = match active_list.read().unwrap().clone(){
+        let sessions = self.connection_limiter.total_sessions();
 
-                BanList => Arc::clone(&ban_list),
-                WhiteList => Arc::clone(&white_list),
-            };
+        if sessions > 0 {
+            warn!("Aborting the transfers of {} sessions", sessions);
+        }
 
-            let mut current_list_desc = match active_list.read().unwrap().clone(){
-                WhiteList => WHITE_LIST_DESC,
-                BanList => BAN_LIST_DESC,
-            };
+        self.sessions.abort_all(TRANSFER_ABORTED);
+    }
 
-            for line in reader.lines(){
+    /// Reads the saved lists and the config file again, then applies the settings which do not require a restart.
+    /// The ongoing sessions are kept, even when they go over the new limits.
+    fn reload(&self, thread_pool: &mut ThreadPool) -> Result<ConfigChanges> {
 
-                let line = line?.to_uppercase();
-                let parts : Vec<&str>= line.split_whitespace().collect();
+        // Read everything before changing anything, so that a failed reload leaves the server as it was
+        let white_list = self.list_store.load_white_list()?;
+        let ban_list = self.list_store.load_ban_list()?;
+        let active_list = self.list_store.load_active_list();
 
-                // Edge case for empty string
-                let verb = if parts.len() == 0{
-                    EMPTY.to_string()
-                }else{
-                    parts[0].to_uppercase()
-                };
+        let changes = ServerConfig::reload()?;
+        let config = ServerConfig::get_config();
 
-                let verb = verb.as_str();
+        thread_pool.resize(config.server_num_threads);
+        self.connection_limiter.set_config(config.connection_limits.clone());
+        self.auto_ban.set_rules(config.auto_ban.clone());
 
-                // Extract the second argument or get a default value to it that will fail in parsings
-                let second_argument = match parts.len(){
-                    2 => parts[1].to_string(),
-                    _ => EMPTY.to_string(),
-                };
+        if let Some(port_allocator) = PORT_ALLOCATOR.get() {
+            port_allocator.set_range(config.first_port, config.last_port);
+        }
 
-                match verb{
+        if let Some(white_list) = white_list {
+            *self.white_list.write().unwrap() = white_list;
+        }
 
-                    SHUTDOWN =>{
-                        Self::shutdown_input(Arc::clone(&shutdown_signal));
-                        break;
-                    }
+        if let Some(ban_list) = ban_list {
+            *self.ban_list.write().unwrap() = ban_list;
+        }
+
+        if let Some(active_list) = active_list {
+            *self.active_list.write().unwrap() = active_list;
+        }
 
-                    ADD_IP => Self::add_ip_input(second_argument, Arc::clone(&current_list)),
-                    REMOVE_IP => Self::remove_ip_input(second_argument, Arc::clone(&current_list)),
-                    LIST_IP => Self::list_ip_input(Arc::clone(&current_list), current_list_desc),
+        Ok(changes)
+    }
 
-                    SWITCH => {
+    /// Background thread purging the expired versions, trashed files, list entries and offences periodically
+    /// until the shutdown signal is set.
+    fn sweeper_thread(shutdown_signal: Arc<AtomicBool>,
+                      versions: Arc<VersionStore>,
+                      trash: Arc<TrashBin>,
+                      white_list: ProtectedType<AccessList>,
+                      ban_list: ProtectedType<AccessList>,
+                      auto_ban: Arc<AutoBanner>) -> JoinHandle<()> {
 
-                        match active_list.read().unwrap().clone(){
-                            WhiteList => {
-                                current_list = Arc::clone(&ban_list);
-                                current_list_desc = BAN_LIST_DESC;
-                            },
-                            BanList => {
-                                current_list = Arc::clone(&white_list);
-                                current_list_desc = WHITE_LIST_DESC;
-                            },
-                        }
+        thread::spawn(move || {
 
-                        active_list.write().unwrap().switch();
-                        println!()
-                    },
+            let mut last_sweep = Instant::now();
+            let mut last_list_sweep = Instant::now();
 
-                    SHOW_CONFIG => Self::show_config_input(),
+            while !shutdown_signal.load(Ordering::Relaxed) {
 
-                    HELP => Self::help_input(),
+                if last_sweep.elapsed() >= SWEEP_INTERVAL {
+                    if let Err(error) = Self::sweep(&versions, &trash) {
+                        error!("Failed to purge expired files: {}", error);
+                    }
+                    last_sweep = Instant::now();
+                }
 
-                    _ => Self::unrecognized_input(),
+                if last_list_sweep.elapsed() >= LIST_SWEEP_INTERVAL {
+                    Self::sweep_list(&white_list, WHITE_LIST_DESC);
+                    Self::sweep_list(&ban_list, BAN_LIST_DESC);
+                    auto_ban.forget_stale(Instant::now());
+                    last_list_sweep = Instant::now();
                 }
 
+                thread::sleep(Duration::from_millis(100));
             }
-
-            Ok(())
         })
-
     }
 
-    /// Lists the ips of the selected list.
+    /// Deletes the versions and trashed files which are no longer retained.
     ///
-    fn list_ip_input(current_list: ProtectedSet<Ipv4Addr>, list_description: &str){
+    fn sweep(versions: &VersionStore, trash: &TrashBin) -> Result<()> {
 
-        println!("{}",list_description);
-        for ip in current_list.read().unwrap().iter(){
-            println!("{}", ip);
+        let pruned_versions = versions.prune_all()?;
+        if pruned_versions > 0 {
+            info!("Pruned {} expired versions", pruned_versions);
         }
-        println!();
-    }
 
-    /// Parses a string into an ipv4 and adds it to the current list if the parsing was successful.
-    ///
-    fn add_ip_input(ip: String, current_list: ProtectedSet<Ipv4Addr>){
-
-        let ip = ip.parse::<Ipv4Addr>();
-
-        match ip{
-            Err(_) => println!("{}",WRONG_INPUT),
-            Ok(ip) => {
-                current_list.write().unwrap().insert(ip);
-            },
+        let purged_files = trash.purge_expired()?;
+        if purged_files > 0 {
+            info!("Purged {} expired files from the trash", purged_files);
         }
-        println!();
 
+        Ok(())
     }
-    /// Parses a string into an ipv4 and removes it from the current list if the parsing was successful.
-    ///
-    fn remove_ip_input(ip: String, current_list: Arc<RwLock<HashSet<Ipv4Addr>>>) {
 
-        let ip = ip.parse::<Ipv4Addr>();
+    /// Replies to a connection without handling its request, by sending the rejected port and the reason
+    /// through the command connection before closing it.
+    fn reject(mut stream: TcpStream, message: &str) {
 
-        match ip{
-            Err(_) => println!("{}",WRONG_INPUT),
-            Ok(ip) => {
-                current_list.write().unwrap().remove(&ip);
-            },
-        }
-        println!();
+        let mut rejection = Vec::new();
+        rejection.extend_from_slice(&REJECTED_PORT.to_be_bytes());
+        rejection.push(b'\n');
+        rejection.extend_from_slice(message.as_bytes());
 
+        // The client may already be gone, which must not stop the server
+        let _ = stream.write_all(&rejection);
+        let _ = stream.shutdown(Shutdown::Both);
     }
 
+    /// Records an offence of a client against the automatic ban rules, logging the ban it may cause.
+    /// Returns true if the client was banned.
+    fn record_offence(auto_ban: &AutoBanner, client_ip: Ipv4Addr, offence: Offence) -> bool {
 
-    /// Shuts down the server by setting the shutdown signal to true.
-    ///
-    fn shutdown_input(shutdown_signal: Arc<AtomicBool>) {
-        println!("Shutting down");
-        shutdown_signal.store(true, Ordering::Relaxed);
-        println!();
+        let AutoBan { client_ip, rule } = match auto_ban.record(client_ip, offence) {
+            Some(ban) => ban,
+            None => return false,
+        };
+
+        let duration = match rule.ban_secs {
+            Some(ban_secs) => format!("for {}", format_duration(Duration::from_secs(ban_secs))),
+            None => "permanently".to_string(),
+        };
+
+        warn!("Automatically banned {} {} after {} {} offences within {}s",
+              client_ip, duration, rule.max_count, rule.offence, rule.window_secs);
+
+        true
     }
 
-    /// Prints current server configuration.
+    /// Removes the expired entries of a white or ban list.
     ///
-    fn show_config_input(){
-        println!("{:#?}",ServerConfig::get_config());
-        println!()
+    fn sweep_list(list: &ProtectedType<AccessList>, list_description: &str) {
+
+        let expired = list.write().unwrap().purge_expired(SystemTime::now());
+
+        for entry in expired {
+            info!("{} {} expired", list_description, entry.cidr);
+        }
     }
 
-    /// Prints all the inputs and their usages.
+    /// Serves the admin socket at the given path, if the platform supports unix sockets.
     ///
-    fn help_input(){
+    #[cfg(unix)]
+    fn admin_thread(console: AdminConsole, socket_path: PathBuf, shutdown_signal: Arc<AtomicBool>) -> Result<Option<JoinHandle<()>>> {
 
-        for description in INPUT_DESCRIPTIONS{
-            println!("{}", description);
-        }
-        println!();
+        let handle = admin::socket::serve(console, socket_path.clone(), shutdown_signal)?;
+        info!("Admin socket listening on {}", socket_path.display());
 
+        Ok(Some(handle))
     }
 
-    /// Prints a warning message.
-    ///
-    fn unrecognized_input(){
-        println!("Unrecognized input");
-        println!();
+    #[cfg(not(unix))]
+    fn admin_thread(_console: AdminConsole, _socket_path: PathBuf, _shutdown_signal: Arc<AtomicBool>) -> Result<Option<JoinHandle<()>>> {
+        warn!("The admin socket is not supported on this platform");
+        Ok(None)
+    }
+
+    /// STDIN thread waiting to receive the administration commands listed by AdminConsole.
+    /// Stops after the SHUTDOWN command.
+    fn input_thread(console: AdminConsole) -> JoinHandle<Result<()>> {
+
+        thread::spawn(move || {
+
+            let reader = BufReader::new(io::stdin());
+
+            for line in reader.lines(){
+
+                let reply = console.execute(&line?);
+
+                println!("{}", reply);
+
+                if reply == AdminReply::ShuttingDown {
+                    break;
+                }
+            }
+
+            Ok(())
+        })
+
     }
 
     /// Handles a single client request, then shuts down the connection.
     ///
-    fn handle_client_once(mut stream: TcpStream,shutdown_signal: Arc<AtomicBool>, data_directory: PathBuf) -> Result<()>{
+    fn handle_client_once(stream: TcpStream,
+                          shutdown_signal: Arc<AtomicBool>,
+                          context: ClientContext) -> Result<()>{
 
-        let address = stream.local_addr()?;
+        let ClientContext { file_index, versions, trash, shares, roles, public, auto_ban, sessions, maintenance } = context;
+
+        let address = stream.peer_addr()?;
         let client_ip = Self::ipv4_from_sockaddr(address).unwrap();
-        let client_dir_path = data_directory.join(format_ipv4(client_ip));
+        let client_dir_path = PathBuf::from(format_ipv4(client_ip));
 
-        if !client_dir_path.exists(){
-            fs::create_dir(&client_dir_path)?;
-        }
+        // Public files are readable by everyone and writable by the publishers
+        // Admins reach every file, the other clients their own files and the ones shared with them
+        // Anonymous clients only reach the public files
+        let role = roles.role_of(client_ip);
+
+        // The session is listed until the request is handled
+        let session = sessions.register(SocketAddrV4::new(client_ip, address.port()), role);
+
+        // Every event of the session carries its id, the client address and the verb once received
+        let span = info_span!("session", id = session.id(), peer = %address, verb = tracing::field::Empty);
+        let _entered = span.enter();
 
-        let invalid_path = data_directory.join(EMPTY);
-        let data_dir_tree = DirectoryTree::new(data_directory)?;
+        let can_publish = public.can_publish(client_ip, role);
+        let can_read = |path: &Path| {
+            public.contains(path) || !role.is_anonymous() && (role.is_admin() || shares.can_read(path, client_ip))
+        };
+        let can_write = |path: &Path| {
+            match public.contains(path) {
+                true => can_publish,
+                false => !role.is_anonymous() && (role.is_admin() || shares.can_write(path, client_ip)),
+            }
+        };
 
         let mut reader = BufReader::new(&stream);
         let mut line = String::new();
@@ -321,9 +529,14 @@ impl FileTransferServer {
         // Check in a loop if the shutdown signal is set then try to read a new line
         loop{
 
-            // Check the shutdown signal
+            // Check the shutdown signal, no new request is handled once it is set nor once the session is kicked
             if shutdown_signal.load(Ordering::Relaxed){
-                stream.shutdown(Shutdown::Both)?;
+                Self::reject(stream.try_clone()?, SERVER_SHUTTING_DOWN);
+                return Ok(());
+            }
+
+            if let Some(reason) = session.abort_reason() {
+                Self::reject(stream.try_clone()?, reason);
                 return Ok(());
             }
 
@@ -336,6 +549,9 @@ impl FileTransferServer {
 
                 Err(e) => return Err(e),
 
+                // The client left without sending a request
+                Ok(0) => return Ok(()),
+
                 Ok(_) => {break;}
 
             };
@@ -345,19 +561,61 @@ impl FileTransferServer {
         let parts : Vec<&str>= line.split_whitespace().collect();
 
         // Edge case for empty string
-        let verb = if parts.len() == 0{
+        let verb = if parts.is_empty(){
             EMPTY.to_string()
         }else{
             parts[0].to_uppercase()
         };
 
         let verb = verb.as_str();
+        span.record("verb", verb);
+
+        // The role is checked before anything else is done with the request
+        if VERBS.contains(&verb) && !role.allows(verb) {
+            warn!(%role, "Command not permitted for the role");
+            Self::record_offence(&auto_ban, client_ip, Offence::AccessDenied);
+            Self::send_message(COMMAND_NOT_PERMITTED, stream.try_clone()?, &session)?;
+            let _ = stream.shutdown(Shutdown::Both);
+            return Ok(());
+        }
+
+        // Only the verbs reading the storage are handled during maintenance, without counting as an offence
+        if VERBS.contains(&verb) && !maintenance.allows(verb) {
+            info!("Refused during maintenance");
+            Self::send_message(MAINTENANCE_MODE, stream.try_clone()?, &session)?;
+            let _ = stream.shutdown(Shutdown::Both);
+            return Ok(());
+        }
 
         let file_path = match parts.len(){
             2 => Some(parts[1]),
             _ => None,
         };
 
+        // Version requests also carry the version id after the file name
+        let version_arguments = match parts.len() {
+            3 => parts[2].parse::<u64>().ok().map(|version_id| (parts[1], version_id)),
+            _ => None,
+        };
+
+        let file_name_argument = match verb {
+            GET_VERSION | RESTORE => version_arguments.map(|(file_name, _)| file_name),
+            SHARE | UNSHARE => parts.get(1).copied(),
+            _ => file_path,
+        };
+
+        session.set_request(verb, file_name_argument);
+        info!(file = file_name_argument, "Handling the request");
+
+        // File names are single path components, anything else is answered before reaching the storage
+        if let Some(file_path) = file_name_argument {
+            if [GET, DELETE, CREATE, UPDATE, VERSIONS, GET_VERSION, RESTORE, UNDELETE, SHARE, UNSHARE, STAT, PUBLISH].contains(&verb) && !Self::is_valid_file_name(file_path) {
+                Self::send_message(INVALID_FILE_NAME, stream.try_clone()?, &session)?;
+                let _ = stream.shutdown(Shutdown::Both);
+                return Ok(());
+            }
+        }
+
         match verb{
 
             GET => {
@@ -365,10 +623,17 @@ impl FileTransferServer {
 
                 match file_path {
                     Some(file_path) =>{
-                        let path = data_dir_tree.find_file(file_path)?.unwrap_or_else(||invalid_path.clone());
-                        Self::get(path,writer_stream)?
+                        let path = file_index.get(file_path).map(|entry| entry.path);
+
+                        match path {
+                            Some(path) if !can_read(&path) => {
+                                Self::record_offence(&auto_ban, client_ip, Offence::AccessDenied);
+                                Self::send_message(ACCESS_DENIED,writer_stream,&session)?
+                            },
+                            path => Self::get(file_index,path,writer_stream,&session)?,
+                        }
                     },
-                    None => Self::send_verb_details(GET,writer_stream)?
+                    None => Self::send_verb_details(GET,writer_stream,&session)?
                 };
 
             },
@@ -378,10 +643,16 @@ impl FileTransferServer {
 
                 match file_path {
                     Some(file_path) =>{
-                        let path = client_dir_path.join(file_path);
-                        Self::delete(path,writer_stream)?
+
+                        // Publishers may delete public files, the other files are deleted by their owner
+                        let path = match file_index.get(file_path) {
+                            Some(entry) if public.contains(&entry.path) && can_publish => entry.path,
+                            _ => client_dir_path.join(file_path),
+                        };
+
+                        Self::delete(path,file_index,versions,trash,shares,writer_stream,&session)?
                     },
-                    None => Self::send_verb_details(DELETE,writer_stream)?
+                    None => Self::send_verb_details(DELETE,writer_stream,&session)?
                 };
             },
 
@@ -391,9 +662,39 @@ impl FileTransferServer {
                 match file_path {
                     Some(file_path) => {
                         let path = client_dir_path.join(file_path);
-                        Self::create(data_dir_tree.clone(),path,writer_stream)?
+                        Self::create(file_index,path,writer_stream,&session)?
                     },
-                    None => Self::send_verb_details(CREATE,writer_stream)?
+                    None => Self::send_verb_details(CREATE,writer_stream,&session)?
+                };
+            }
+
+            PUBLISH => {
+                let writer_stream = stream.try_clone()?;
+
+                match file_path {
+                    Some(file_path) if public.is_enabled() => {
+                        match can_publish {
+                            true => Self::create(file_index,public.path_of(file_path),writer_stream,&session)?,
+                            false => {
+                                Self::record_offence(&auto_ban, client_ip, Offence::AccessDenied);
+                                Self::send_message(ACCESS_DENIED,writer_stream,&session)?
+                            },
+                        }
+                    },
+                    Some(_file_path) => Self::send_message(PUBLIC_AREA_DISABLED,writer_stream,&session)?,
+                    None => Self::send_verb_details(PUBLISH,writer_stream,&session)?
+                };
+            }
+
+            STAT => {
+                let writer_stream = stream.try_clone()?;
+
+                match file_path {
+                    Some(file_path) => {
+                        let entry = file_index.get(file_path).filter(|entry| can_read(&entry.path));
+                        Self::stat(entry,writer_stream,&session)?
+                    },
+                    None => Self::send_verb_details(STAT,writer_stream,&session)?
                 };
             }
 
@@ -402,45 +703,165 @@ impl FileTransferServer {
 
                 match file_path {
                     Some(file_path) => {
+
+                        // Files of other owners can be updated through a write grant
+                        let path = match file_index.get(file_path) {
+                            Some(entry) => entry.path,
+                            None => client_dir_path.join(file_path),
+                        };
+
+                        match can_write(&path) {
+                            true => Self::update(path,file_index,versions,writer_stream,&session)?,
+                            false => {
+                                Self::record_offence(&auto_ban, client_ip, Offence::AccessDenied);
+                                Self::send_message(ACCESS_DENIED,writer_stream,&session)?
+                            },
+                        }
+                    },
+                    None => Self::send_verb_details(UPDATE,writer_stream,&session)?
+                };
+            }
+
+            VERSIONS => {
+                let writer_stream = stream.try_clone()?;
+
+                match file_path {
+                    Some(file_path) => {
+                        let path = client_dir_path.join(file_path);
+                        Self::versions(versions,path,writer_stream,&session)?
+                    },
+                    None => Self::send_verb_details(VERSIONS,writer_stream,&session)?
+                };
+            }
+
+            GET_VERSION => {
+                let writer_stream = stream.try_clone()?;
+
+                match version_arguments {
+                    Some((file_path, version_id)) => {
+                        let path = client_dir_path.join(file_path);
+                        Self::get_version(versions,path,version_id,writer_stream,&session)?
+                    },
+                    None => Self::send_verb_details(GET_VERSION,writer_stream,&session)?
+                };
+            }
+
+            RESTORE => {
+                let writer_stream = stream.try_clone()?;
+
+                match version_arguments {
+                    Some((file_path, version_id)) => {
                         let path = client_dir_path.join(file_path);
-                        Self::update(path,writer_stream)?
+                        Self::restore(file_index,versions,path,version_id,writer_stream,&session)?
                     },
-                    None => Self::send_verb_details(UPDATE,writer_stream)?
+                    None => Self::send_verb_details(RESTORE,writer_stream,&session)?
                 };
             }
 
+            TRASH => {
+                let writer_stream = stream.try_clone()?;
+                Self::list_trash(trash,&client_dir_path,writer_stream,&session)?;
+            }
+
+            UNDELETE => {
+                let writer_stream = stream.try_clone()?;
+
+                match file_path {
+                    Some(file_path) => {
+                        let path = client_dir_path.join(file_path);
+                        Self::undelete(file_index,trash,path,writer_stream,&session)?
+                    },
+                    None => Self::send_verb_details(UNDELETE,writer_stream,&session)?
+                };
+            }
+
+            EMPTY_TRASH => {
+                let writer_stream = stream.try_clone()?;
+                trash.empty(&client_dir_path)?;
+                Self::send_message(TRASH_EMPTIED,writer_stream,&session)?;
+            }
+
+            SHARE => {
+                let writer_stream = stream.try_clone()?;
+
+                let access = match parts.get(3) {
+                    Some(access) => Access::parse(access),
+                    None => Some(Access::Read),
+                };
+
+                match (parts.len(), access) {
+                    (3 | 4, Some(access)) => {
+                        let path = client_dir_path.join(parts[1]);
+                        Self::share(file_index,shares,path,parts[2],access,writer_stream,&session)?
+                    },
+                    _ => Self::send_verb_details(SHARE,writer_stream,&session)?
+                };
+            }
+
+            UNSHARE => {
+                let writer_stream = stream.try_clone()?;
+
+                match parts.len() {
+                    3 => {
+                        let path = client_dir_path.join(parts[1]);
+                        Self::unshare(shares,path,parts[2],writer_stream,&session)?
+                    },
+                    _ => Self::send_verb_details(UNSHARE,writer_stream,&session)?
+                };
+            }
+
+            LIST_SHARED => {
+                let writer_stream = stream.try_clone()?;
+                Self::list_shared(shares,client_ip,writer_stream,&session)?;
+            }
+
             LIST => {
                 let writer_stream = stream.try_clone()?;
-                Self::list(data_dir_tree.clone(), writer_stream)?;
+
+                let entries = file_index.entries()
+                    .into_iter()
+                    .filter(|entry| can_read(&entry.path))
+                    .collect();
+
+                Self::list(entries, writer_stream, &session)?;
+            }
+
+            SEARCH => {
+                let writer_stream = stream.try_clone()?;
+
+                match parts.len() {
+                    1 => Self::send_verb_details(SEARCH,writer_stream,&session)?,
+                    _ => Self::search(file_index, &can_read, &parts[1..], writer_stream, &session)?,
+                };
             }
 
             LIST_OWNED => {
                 let writer_stream = stream.try_clone()?;
-                let client_dir_tree = DirectoryTree::new(client_dir_path)?;
-                Self::list(client_dir_tree,writer_stream)?;
+                Self::list(file_index.entries_under(&client_dir_path),writer_stream,&session)?;
             }
 
             QUIT => {
                 let writer_stream = stream.try_clone()?;
-                Self::quit(writer_stream)?;
+                Self::quit(writer_stream,&session)?;
                 return Ok(());
             }
 
             HELP => {
                 let writer_stream = stream.try_clone()?;
-                Self::help(writer_stream)?
+                Self::help(writer_stream,&session)?
             }
 
             _ => {
                 let writer_stream = stream.try_clone()?;
-                Self::unrecognized(writer_stream)?
+                Self::record_offence(&auto_ban, client_ip, Offence::UnrecognizedCommand);
+                Self::unrecognized(writer_stream,&session)?
             },
 
         }
 
         // Shutdown the command connection if it has not been shut already
         // If it has already been shut this call will return an Err() which SHOULD NOT BE PROPAGATED
-        stream.shutdown(Shutdown::Both);
+        let _ = stream.shutdown(Shutdown::Both);
 
         Ok(())
 
@@ -448,8 +869,8 @@ impl FileTransferServer {
 
     /// Creates a passive socket on an ephemeral port.
     /// Send the port number to the client via the writer stream.
-    ///
-    fn create_data_stream(mut writer_stream: TcpStream) -> Result<TcpStream>{
+    /// Stops waiting for the client to connect once the session is aborted.
+    fn create_data_stream(mut writer_stream: TcpStream, session: &Session) -> Result<TcpStream>{
 
         let port = Self::get_port_allocator().alloc();
         let address = SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, port);
@@ -458,48 +879,137 @@ impl FileTransferServer {
         let data_server = TcpListener::bind(address)?;
         let data_port = data_server.local_addr()?.port();
 
-        println!("{data_port}");
         // Send the port to the client through the command connection
         let mut port_bytes_message = Vec::new();
         port_bytes_message.extend_from_slice(&data_port.to_be_bytes());
-        port_bytes_message.push('\n' as u8);
+        port_bytes_message.push(b'\n');
 
         writer_stream.write_all(port_bytes_message.as_slice())?;
-        println!("Sent port");
+        debug!(data_port, "Sent the data port");
         // Wait for the client to connect to the data connection
-        let (data_stream,_address) = data_server.accept()?;
-        println!("Accepted");
+        data_server.set_nonblocking(true)?;
+
+        let data_stream = loop {
+            match data_server.accept(){
+
+                Ok((data_stream,_address)) => break data_stream,
+
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => {
+                    if session.is_aborted() {
+                        Self::get_port_allocator().dealloc(port);
+                        return Err(Self::transfer_aborted(session));
+                    }
+                    thread::sleep(DATA_ACCEPT_POLL_INTERVAL);
+                }
+
+                Err(e) => return Err(e),
+            }
+        };
+
+        data_stream.set_nonblocking(false)?;
+        debug!(data_port, "Accepted the data connection");
         Ok(data_stream)
     }
 
     /// Treat a get request.
-    /// Create a memory mapped file to be sent in chunks through a data connection.
+    /// Read the file from the storage and send it in chunks through a data connection.
     ///
-    fn get(file_path: PathBuf, writer_stream: TcpStream) -> Result<()> {
+    fn get(file_index: Arc<FileIndex>, file_path: Option<PathBuf>, writer_stream: TcpStream, session: &Session) -> Result<()> {
 
-        let mut data_stream = Self::create_data_stream(writer_stream)?;
-        let mut data_port = data_stream.local_addr()?.port();
+        let mut data_stream = Self::create_data_stream(writer_stream,session)?;
+        let _data_port = PortLease::new(Self::get_port_allocator(), data_stream.local_addr()?.port());
 
-        let file = OpenOptions::new()
-            .read(true)
-            .write(false)
-            .truncate(false)
-            .create(false)
-            .open(&file_path);
+        let file = match &file_path {
+            Some(file_path) => file_index.storage().open_read(file_path),
+            None => Err(io::Error::from(io::ErrorKind::NotFound)),
+        };
 
         // Send the file if it exists otherwise send an error code
         match file{
 
             Ok(file) => {
 
-                // map the current file
-                let mmap = unsafe{Mmap::map(&file)?};
+                let sent = Self::send_file(file, &mut data_stream, session);
+                Self::record_transfer(session, TransferDirection::Outgoing, file_path.as_deref(), sent.is_ok());
 
-                // Send the file in chunks
-                for chunk in mmap.chunks(ServerConfig::get_buffer_size()){
-                    data_stream.write_all(chunk)?;
+                match sent {
+                    Err(_error) if session.is_aborted() => Self::reply_aborted(&mut data_stream, session),
+                    result => result?,
                 }
+            },
 
+            Err(_error) => {
+                data_stream.write_all(FILE_NOT_FOUND.as_bytes())?;
+            }
+        }
+
+        // Shutdown the temporary data connection, the port is freed once the lease is dropped
+        data_stream.shutdown(Shutdown::Both)?;
+        Ok(())
+
+    }
+
+    /// Sends the content of a file in chunks through the data connection.
+    /// Stops once the session is aborted, even if the client does not read anymore.
+    fn send_file(mut file: Box<dyn Read + Send>, data_stream: &mut TcpStream, session: &Session) -> Result<()> {
+
+        let mut send_buffer = vec![0; ServerConfig::get_buffer_size()];
+
+        // Writes wait for the client a limited time in order to notice an abort
+        data_stream.set_write_timeout(Some(TRANSFER_POLL_INTERVAL))?;
+
+        loop{
+
+            let bytes_read = match file.read(&mut send_buffer)?{
+                0 => return Ok(()),
+                bytes_read => bytes_read,
+            };
+
+            let mut bytes_sent = 0;
+
+            while bytes_sent < bytes_read {
+
+                if session.is_aborted() {
+                    return Err(Self::transfer_aborted(session));
+                }
+
+                match data_stream.write(&send_buffer[bytes_sent..bytes_read]){
+                    Ok(0) => return Err(io::Error::from(io::ErrorKind::WriteZero)),
+                    Ok(bytes_written) => {
+                        bytes_sent += bytes_written;
+                        session.add_bytes(bytes_written);
+                    },
+                    Err(ref e) if Self::is_timeout(e) => {},
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+    }
+
+    /// Deletes a file and sends a message to mark the status through a temporary connection.
+    /// The file is moved to the trash when it is enabled, otherwise its content is kept as a version when versioning is enabled.
+    /// The grants of the file are revoked.
+    fn delete(file_path: PathBuf,
+              file_index: Arc<FileIndex>,
+              versions: Arc<VersionStore>,
+              trash: Arc<TrashBin>,
+              shares: Arc<ShareRegistry>,
+              writer_stream: TcpStream, session: &Session) -> Result<()> {
+
+        let mut data_stream = Self::create_data_stream(writer_stream,session)?;
+        let _data_port = PortLease::new(Self::get_port_allocator(), data_stream.local_addr()?.port());
+
+        let remove_result = match trash.is_enabled() {
+            true => trash.trash(&file_path),
+            false => versions.delete(&file_path),
+        };
+
+        match remove_result {
+            Ok(_) => {
+                file_index.remove_path(&file_path);
+                shares.remove_file(&file_path)?;
+                Self::record_transfer(session, TransferDirection::Deleted, Some(&file_path), true);
+                data_stream.write_all(DELETE_SUCCESSFUL.as_bytes())?;
             }
 
             Err(_error) => {
@@ -507,29 +1017,304 @@ impl FileTransferServer {
             }
         }
 
-        // Shutdown the temporary data connection and free the port
+        data_stream.shutdown(Shutdown::Both)?;
+
+        Ok(())
+    }
+
+    /// Attempts to create the given file. If it already exists, a message is sent through the data stream and the connection ends.
+    /// If the file is a new one, transmits through the data stream a ready message, and reads chunks of the file in a loop
+    /// until the data connection is ended.
+    ///
+    fn create(file_index: Arc<FileIndex>, file_path: PathBuf, writer_stream: TcpStream, session: &Session) -> Result<()>  {
+
+        let mut data_stream = Self::create_data_stream(writer_stream,session)?;
+        let _data_port = PortLease::new(Self::get_port_allocator(), data_stream.local_addr()?.port());
+
+        let file_name = file_path.file_name().unwrap().to_str().unwrap();
+
+        // Claim the name across the storage, if there is another file named the same signal it
+        if file_index.reserve(file_name, &file_path).is_err() {
+            data_stream.write_all(ALREADY_EXISTS.as_bytes())?;
+            data_stream.shutdown(Shutdown::Both)?;
+            return Ok(())
+        }
+
+        // Stage the new file, else release the name, send a message through the data stream and end the connection
+        let staged_write = match file_index.storage().open_write(&file_path, WriteMode::Create){
+
+            Err(error) => {
+
+                file_index.remove_path(&file_path);
+
+                return match error.kind() {
+                    io::ErrorKind::AlreadyExists => {
+                        data_stream.write_all(ALREADY_EXISTS.as_bytes())?;
+                        data_stream.shutdown(Shutdown::Both)?;
+                        Ok(())
+                    },
+
+                    _ => Err(error)
+                }
+
+            }
+
+            Ok(staged_write) => {staged_write}
+        };
+
+        // Write to announce that the file can be transferred
+        data_stream.write_all(READY_TO_RECEIVE.as_bytes())?;
+
+        let receive_result = Self::receive_file(&mut data_stream, staged_write, session);
+        Self::record_transfer(session, TransferDirection::Incoming, Some(&file_path), receive_result.is_ok());
+
+        // The claimed name is dropped from the index if the file was not committed
+        file_index.refresh(&file_path)?;
+
+        match receive_result {
+            Err(_error) if session.is_aborted() => Self::reply_aborted(&mut data_stream, session),
+            result => result?,
+        }
+
+        Ok(())
+    }
+
+    /// Attempts to open and truncate the given file. If it does not exist, a message is sent through the data stream and the connection ends.
+    /// If the file is a new one, transmits through the data stream a ready message, and reads chunks of the file in a loop
+    /// until the data connection is ended. The replaced content is archived once the whole file is received.
+    ///
+    fn update(path: PathBuf, file_index: Arc<FileIndex>, versions: Arc<VersionStore>, writer_stream: TcpStream, session: &Session) -> Result<()>  {
+
+        let mut data_stream = Self::create_data_stream(writer_stream,session)?;
+        let _data_port = PortLease::new(Self::get_port_allocator(), data_stream.local_addr()?.port());
+
+        let staged_write = match file_index.storage().open_write(&path, WriteMode::Overwrite){
+
+            Err(error) => {
+
+                return match error.kind() {
+                    io::ErrorKind::NotFound => {
+                        data_stream.write_all(FILE_NOT_FOUND.as_bytes())?;
+                        data_stream.shutdown(Shutdown::Both)?;
+                        Ok(())
+                    },
+
+                    _ => Err(error)
+                }
+
+            }
+
+            Ok(staged_write) => {staged_write}
+        };
+
+        // Write to announce that the file can be transferred
+        data_stream.write_all(READY_TO_RECEIVE.as_bytes())?;
+
+        let staged_write = match Self::receive_content(&mut data_stream, staged_write, session) {
+
+            Err(_error) if session.is_aborted() => {
+                Self::record_transfer(session, TransferDirection::Incoming, Some(&path), false);
+                Self::reply_aborted(&mut data_stream, session);
+                return Ok(());
+            }
+
+            Err(error) => {
+                Self::record_transfer(session, TransferDirection::Incoming, Some(&path), false);
+                return Err(error);
+            }
+
+            Ok(staged_write) => staged_write,
+        };
+
+        // Keep the previous content before publishing the new one
+        let published = match versions.archive(&path) {
+            Ok(()) => staged_write.commit(),
+            Err(error) => staged_write.abort().and(Err(error)),
+        };
+
+        Self::record_transfer(session, TransferDirection::Incoming, Some(&path), published.is_ok());
+
+        published?;
+        file_index.refresh(&path)?;
+
+        Ok(())
+    }
+
+    /// Reads chunks of a file from the data connection into a staged write until the connection is ended.
+    /// The write is committed once the whole file is received and aborted if the transfer fails.
+    fn receive_file(data_stream: &mut TcpStream, staged_write: Box<dyn StagedWrite>, session: &Session) -> Result<()> {
+        Self::receive_content(data_stream, staged_write, session)?.commit()
+    }
+
+    /// Reads chunks of a file from the data connection into a staged write until the connection is ended.
+    /// The write is returned uncommitted once the whole file is received and aborted if the transfer fails or is aborted.
+    fn receive_content(data_stream: &mut TcpStream, mut staged_write: Box<dyn StagedWrite>, session: &Session) -> Result<Box<dyn StagedWrite>> {
+
+        let mut receive_buffer = vec![0; ServerConfig::get_buffer_size()];
+
+        // Reads wait for the client a limited time in order to notice an abort
+        if let Err(error) = data_stream.set_read_timeout(Some(TRANSFER_POLL_INTERVAL)) {
+            staged_write.abort()?;
+            return Err(error);
+        }
+
+        loop{
+
+            let write_result = match data_stream.read(&mut receive_buffer){
+
+                _ if session.is_aborted() => Err(Self::transfer_aborted(session)),
+
+                Ok(0) => break,
+
+                Ok(bytes_received) => {
+                    session.add_bytes(bytes_received);
+                    staged_write.write_all(&receive_buffer[..bytes_received])
+                },
+
+                Err(ref e) if Self::is_timeout(e) => Ok(()),
+
+                Err(error) => Err(error),
+
+            };
+
+            if let Err(error) = write_result {
+                staged_write.abort()?;
+                return Err(error);
+            }
+
+        }
+
+        Ok(staged_write)
+    }
+
+    /// Sends the id, size and archive time of each version of a file, oldest first.
+    ///
+    fn versions(versions: Arc<VersionStore>, path: PathBuf, writer_stream: TcpStream, session: &Session) -> Result<()> {
+
+        let mut data_stream = Self::create_data_stream(writer_stream,session)?;
+        let data_port = data_stream.local_addr()?.port();
+
+        let history = versions.versions(&path)?;
+
+        if history.is_empty() {
+            data_stream.write_all(NO_VERSIONS_FOUND.as_bytes())?;
+        }
+
+        for version in history {
+
+            let archived: DateTime<Utc> = version.archived.into();
+
+            let line = format!("{}\t{}\t{}\n", version.id, version.size, archived.format("%Y-%m-%d %H:%M:%S"));
+            data_stream.write_all(line.as_bytes())?;
+        }
+
+        data_stream.shutdown(Shutdown::Both)?;
+        Self::get_port_allocator().dealloc(data_port);
+
+        Ok(())
+    }
+
+    /// Sends the content of a version of a file in chunks through a data connection.
+    ///
+    fn get_version(versions: Arc<VersionStore>, path: PathBuf, version_id: u64, writer_stream: TcpStream, session: &Session) -> Result<()> {
+
+        let mut data_stream = Self::create_data_stream(writer_stream,session)?;
+        let data_port = data_stream.local_addr()?.port();
+
+        match versions.open_version(&path, version_id) {
+
+            Ok(file) => match Self::send_file(file, &mut data_stream, session) {
+                Err(_error) if session.is_aborted() => Self::reply_aborted(&mut data_stream, session),
+                result => result?,
+            },
+
+            Err(_error) => {
+                data_stream.write_all(VERSION_NOT_FOUND.as_bytes())?;
+            }
+        }
+
+        data_stream.shutdown(Shutdown::Both)?;
+        Self::get_port_allocator().dealloc(data_port);
+
+        Ok(())
+    }
+
+    /// Replaces the content of a file by one of its versions, recreating the file if it was deleted.
+    /// A deleted file is only recreated if no other file has taken its name in the meantime.
+    fn restore(file_index: Arc<FileIndex>, versions: Arc<VersionStore>, path: PathBuf, version_id: u64, writer_stream: TcpStream, session: &Session) -> Result<()> {
+
+        let mut data_stream = Self::create_data_stream(writer_stream,session)?;
+        let data_port = data_stream.local_addr()?.port();
+
+        let file_name = path.file_name().unwrap().to_str().unwrap();
+        let is_indexed = file_index.get(file_name).is_some_and(|entry| entry.path == path);
+
+        if !is_indexed && file_index.reserve(file_name, &path).is_err() {
+            data_stream.write_all(ALREADY_EXISTS.as_bytes())?;
+            data_stream.shutdown(Shutdown::Both)?;
+            Self::get_port_allocator().dealloc(data_port);
+            return Ok(())
+        }
+
+        let restore_result = versions.restore(&path, version_id);
+
+        // The claimed name is dropped from the index if the file was not restored
+        file_index.refresh(&path)?;
+
+        match restore_result {
+            Ok(_) => data_stream.write_all(VERSION_RESTORED.as_bytes())?,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => data_stream.write_all(VERSION_NOT_FOUND.as_bytes())?,
+            Err(error) => return Err(error),
+        }
+
+        data_stream.shutdown(Shutdown::Both)?;
+        Self::get_port_allocator().dealloc(data_port);
+
+        Ok(())
+    }
+
+    /// Send an end connection message through the data connection and shutdown the command connection.
+    ///
+    fn quit(writer_stream: TcpStream, session: &Session) -> Result<()> {
+
+        let mut data_stream = Self::create_data_stream(writer_stream.try_clone().unwrap(),session)?;
+        let data_port = data_stream.local_addr()?.port();
+
+        data_stream.write_all(QUIT_MESSAGE.as_bytes())?;
         data_stream.shutdown(Shutdown::Both)?;
         Self::get_port_allocator().dealloc(data_port);
-        Ok(())
 
+        writer_stream.shutdown(Shutdown::Both)?;
+
+        Ok(())
     }
 
-    /// Deletes a file and sends a message to mark the status through a temporary connection.
-    fn delete(file_path: PathBuf, writer_stream: TcpStream) -> Result<()> {
 
-        let mut data_stream = Self::create_data_stream(writer_stream)?;
-        let mut data_port = data_stream.local_addr()?.port();
+    /// Sends the verb details through a data stream.
+    ///
+    fn send_verb_details(verb: &str, writer_stream: TcpStream, session: &Session) -> Result<()> {
 
-        let remove_result = remove_file(&file_path);
+        let mut data_stream = Self::create_data_stream(writer_stream.try_clone().unwrap(),session)?;
+        let data_port = data_stream.local_addr()?.port();
 
-        match remove_result {
-            Ok(_) => {
-                data_stream.write_all(DELETE_SUCCESSFUL.as_bytes())?;
-            }
+        match verb{
+
+            GET => data_stream.write_all(GET_DESC.as_bytes())?,
+            DELETE => data_stream.write_all(DELETE_DESC.as_bytes())?,
+            CREATE => data_stream.write_all(CREATE_DESC.as_bytes())?,
+            UPDATE => data_stream.write_all(UPDATE_DESC.as_bytes())?,
+            QUIT => data_stream.write_all(QUIT_DESC.as_bytes())?,
+            SEARCH => data_stream.write_all(SEARCH_DESC.as_bytes())?,
+            VERSIONS => data_stream.write_all(VERSIONS_DESC.as_bytes())?,
+            GET_VERSION => data_stream.write_all(GET_VERSION_DESC.as_bytes())?,
+            RESTORE => data_stream.write_all(RESTORE_DESC.as_bytes())?,
+            UNDELETE => data_stream.write_all(UNDELETE_DESC.as_bytes())?,
+            SHARE => data_stream.write_all(SHARE_DESC.as_bytes())?,
+            UNSHARE => data_stream.write_all(UNSHARE_DESC.as_bytes())?,
+            STAT => data_stream.write_all(STAT_DESC.as_bytes())?,
+            PUBLISH => data_stream.write_all(PUBLISH_DESC.as_bytes())?,
+            _ => data_stream.write_all("How did you get here?".as_bytes())?,
 
-            Err(_error) => {
-                data_stream.write_all(FILE_NOT_FOUND.as_bytes())?;
-            }
         }
 
         data_stream.shutdown(Shutdown::Both)?;
@@ -538,182 +1323,168 @@ impl FileTransferServer {
         Ok(())
     }
 
-    /// Attempts to create the given file. If it already exists, a message is sent through the data stream and the connection ends.
-    /// If the file is a new one, transmits through the data stream a ready message, and reads chunks of the file in a loop
-    /// until the data connection is ended.
+    /// Sends the name, size and deletion time of each trashed file of the client, oldest deletion first.
     ///
-    fn create(data_dir_tree: DirectoryTree<PathBuf>, file_path: PathBuf, writer_stream: TcpStream) -> Result<()>  {
+    fn list_trash(trash: Arc<TrashBin>, client_dir_path: &Path, writer_stream: TcpStream, session: &Session) -> Result<()> {
 
-        let mut data_stream = Self::create_data_stream(writer_stream)?;
+        let mut data_stream = Self::create_data_stream(writer_stream,session)?;
         let data_port = data_stream.local_addr()?.port();
 
-        let file_name = file_path.file_name().unwrap().to_str().unwrap();
+        let files = trash.files(client_dir_path)?;
 
-        // If there is another file named the same across the data directory signal it
-        match data_dir_tree.find_file(file_name)?{
-            None => (),
-            Some(_) => {
-                data_stream.write_all(ALREADY_EXISTS.as_bytes())?;
-                data_stream.shutdown(Shutdown::Both)?;
-                Self::get_port_allocator().dealloc(data_port);
-                return Ok(())
-            }
+        if files.is_empty() {
+            data_stream.write_all(TRASH_EMPTY.as_bytes())?;
         }
 
-        // Create the file if it does not exist, else send a message through the data stream and end the connection
-        let file = OpenOptions::new()
-            .create_new(true)
-            .read(true)
-            .write(true)
-            .append(true)
-            .truncate(false)
-            .open(&file_path);
-
-        let file = match file{
-
-            Err(error) => {
-
-                return match error.kind() {
-                    io::ErrorKind::AlreadyExists => {
-                        data_stream.write_all(ALREADY_EXISTS.as_bytes())?;
-                        data_stream.shutdown(Shutdown::Both)?;
-                        Self::get_port_allocator().dealloc(data_port);
-                        Ok(())
-                    },
-
-                    _ => Err(error)
-                }
+        for file in files {
 
-            }
+            let deleted: DateTime<Utc> = file.deleted.into();
 
-            Ok(file) => {file}
-        };
+            let line = format!("{}\t{}\t{}\n", file.file_name, file.size, deleted.format("%Y-%m-%d %H:%M:%S"));
+            data_stream.write_all(line.as_bytes())?;
+        }
 
-        // Write to announce that the file can be transferred
-        data_stream.write_all(READY_TO_RECEIVE.as_bytes())?;
+        data_stream.shutdown(Shutdown::Both)?;
+        Self::get_port_allocator().dealloc(data_port);
 
-        // Map the file and append the received chunks through the data connection.
-        let mut mapped_file = MappedFile::new(file)?;
-        let mut receive_buffer = vec![0; ServerConfig::get_buffer_size()];
+        Ok(())
+    }
 
-        loop{
+    /// Moves the latest trashed file with the given name back to its place.
+    /// The file is only restored if no other file has taken its name in the meantime.
+    fn undelete(file_index: Arc<FileIndex>, trash: Arc<TrashBin>, path: PathBuf, writer_stream: TcpStream, session: &Session) -> Result<()> {
 
-            match data_stream.read(&mut receive_buffer){
+        let mut data_stream = Self::create_data_stream(writer_stream,session)?;
+        let data_port = data_stream.local_addr()?.port();
 
-                Ok(0) => break,
+        let file_name = path.file_name().unwrap().to_str().unwrap();
 
-                Ok(bytes_received) => mapped_file.write_append(&receive_buffer[..bytes_received])?,
+        if file_index.reserve(file_name, &path).is_err() {
+            data_stream.write_all(ALREADY_EXISTS.as_bytes())?;
+            data_stream.shutdown(Shutdown::Both)?;
+            Self::get_port_allocator().dealloc(data_port);
+            return Ok(())
+        }
 
-                Err(error) => {
-                    return Err(error);
-                }
+        let restore_result = trash.restore(&path);
 
-            }
+        // The claimed name is dropped from the index if the file was not restored
+        file_index.refresh(&path)?;
 
+        match restore_result {
+            Ok(_) => data_stream.write_all(FILE_RESTORED.as_bytes())?,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => data_stream.write_all(FILE_NOT_FOUND.as_bytes())?,
+            Err(error) if error.kind() == io::ErrorKind::AlreadyExists => data_stream.write_all(ALREADY_EXISTS.as_bytes())?,
+            Err(error) => return Err(error),
         }
 
+        data_stream.shutdown(Shutdown::Both)?;
         Self::get_port_allocator().dealloc(data_port);
+
         Ok(())
     }
 
-    /// Attempts to open and truncate the given file. If it does not exist, a message is sent through the data stream and the connection ends.
-    /// If the file is a new one, transmits through the data stream a ready message, and reads chunks of the file in a loop
-    /// until the data connection is ended.
+    /// Grants access to one of the client files to another client or to a group.
     ///
-    fn update(path: PathBuf, writer_stream: TcpStream) -> Result<()>  {
+    fn share(file_index: Arc<FileIndex>, shares: Arc<ShareRegistry>, path: PathBuf, grantee: &str, access: Access, writer_stream: TcpStream, session: &Session) -> Result<()> {
 
-        let mut data_stream = Self::create_data_stream(writer_stream)?;
-        let data_port = data_stream.local_addr()?.port();
-
-        let file = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .append(false)
-            .truncate(true)
-            .create(false)
-            .open(&path);
-
-
-        let file = match file{
+        let file_name = path.file_name().unwrap().to_str().unwrap();
+        let is_owned = file_index.get(file_name).is_some_and(|entry| entry.path == path);
 
-            Err(error) => {
-
-                return match error.kind() {
-                    io::ErrorKind::NotFound => {
-                        data_stream.write_all(FILE_NOT_FOUND.as_bytes())?;
-                        data_stream.shutdown(Shutdown::Both)?;
-                        Self::get_port_allocator().dealloc(data_port);
-                        Ok(())
-                    },
+        let message = match (is_owned, shares.parse_grantee(grantee)) {
+            (false, _) => FILE_NOT_FOUND,
+            (true, None) => UNKNOWN_GRANTEE,
+            (true, Some(grantee)) => {
+                shares.share(&path, grantee, access)?;
+                SHARE_SUCCESSFUL
+            }
+        };
 
-                    _ => Err(error)
-                }
+        Self::send_message(message, writer_stream, session)
+    }
 
-            }
+    /// Revokes the access to one of the client files granted to another client or to a group.
+    ///
+    fn unshare(shares: Arc<ShareRegistry>, path: PathBuf, grantee: &str, writer_stream: TcpStream, session: &Session) -> Result<()> {
 
-            Ok(file) => {file}
+        let message = match shares.parse_grantee(grantee) {
+            None => UNKNOWN_GRANTEE,
+            Some(grantee) => match shares.unshare(&path, &grantee)? {
+                true => UNSHARE_SUCCESSFUL,
+                false => GRANT_NOT_FOUND,
+            },
         };
 
-        // Write to announce that the file can be transferred
-        data_stream.write_all(READY_TO_RECEIVE.as_bytes())?;
-
-        // Map the file and append the received chunks through the data connection.
-        let mut mapped_file = MappedFile::new(file)?;
-        let mut receive_buffer = vec![0; ServerConfig::get_buffer_size()];
+        Self::send_message(message, writer_stream, session)
+    }
 
-        loop{
+    /// Sends the name, owner and access of each file other clients shared with the client.
+    ///
+    fn list_shared(shares: Arc<ShareRegistry>, client_ip: Ipv4Addr, writer_stream: TcpStream, session: &Session) -> Result<()> {
 
-            match data_stream.read(&mut receive_buffer){
+        let mut data_stream = Self::create_data_stream(writer_stream,session)?;
+        let data_port = data_stream.local_addr()?.port();
 
-                Ok(0) => break,
+        let shared_files = shares.shared_with(client_ip);
 
-                Ok(bytes_received) => mapped_file.write_append(&receive_buffer[..bytes_received])?,
+        if shared_files.is_empty() {
+            data_stream.write_all(NO_SHARED_FILES.as_bytes())?;
+        }
 
-                Err(error) => {
-                    return Err(error);
-                }
+        for shared_file in shared_files {
 
-            }
+            let file_name = shared_file.path.file_name().unwrap().to_str().unwrap();
+            let owner = shared_file.path.parent()
+                .and_then(|owner_dir| parse_formatted_ipv4(owner_dir.to_str()?))
+                .map_or(String::new(), |owner| owner.to_string());
 
+            let line = format!("{}\t{}\t{}\n", file_name, owner, shared_file.access);
+            data_stream.write_all(line.as_bytes())?;
         }
 
+        data_stream.shutdown(Shutdown::Both)?;
         Self::get_port_allocator().dealloc(data_port);
+
         Ok(())
     }
 
-    /// Send an end connection message through the data connection and shutdown the command connection.
+    /// Sends the names of the given files.
     ///
-    fn quit(mut writer_stream: TcpStream) -> Result<()> {
+    fn list(files: Vec<FileStat>,writer_stream: TcpStream, session: &Session) -> Result<()> {
 
-        let mut data_stream = Self::create_data_stream(writer_stream.try_clone().unwrap())?;
+        let mut data_stream = Self::create_data_stream(writer_stream,session)?;
         let data_port = data_stream.local_addr()?.port();
 
-        data_stream.write_all(QUIT_MESSAGE.as_bytes())?;
+        for file in files {
+
+            let file_name = file.path.file_name().unwrap();
+            let formatted_name = format!("{}\n",file_name.to_str().unwrap());
+            data_stream.write_all(formatted_name.as_bytes())?;
+
+        }
+
         data_stream.shutdown(Shutdown::Both)?;
         Self::get_port_allocator().dealloc(data_port);
 
-        writer_stream.shutdown(Shutdown::Both)?;
-
         Ok(())
     }
 
-
-    /// Sends the verb details through a data stream.
+    /// Sends the name, size and last modification time of a file, or a not found message.
     ///
-    fn send_verb_details(verb: &str, mut writer_stream: TcpStream) -> Result<()> {
+    fn stat(entry: Option<FileStat>, writer_stream: TcpStream, session: &Session) -> Result<()> {
 
-        let mut data_stream = Self::create_data_stream(writer_stream.try_clone().unwrap())?;
+        let mut data_stream = Self::create_data_stream(writer_stream,session)?;
         let data_port = data_stream.local_addr()?.port();
 
-        match verb{
+        match entry {
 
-            GET => data_stream.write_all(GET_DESC.as_bytes())?,
-            DELETE => data_stream.write_all(DELETE_DESC.as_bytes())?,
-            CREATE => data_stream.write_all(CREATE_DESC.as_bytes())?,
-            UPDATE => data_stream.write_all(UPDATE_DESC.as_bytes())?,
-            QUIT => data_stream.write_all(QUIT_DESC.as_bytes())?,
-            _ => data_stream.write_all("How did you get here?".as_bytes())?,
+            Some(entry) => {
+                let modified: DateTime<Utc> = entry.modified.into();
+                let line = format!("{}\t{}\t{}\n", entry.path.file_name().unwrap().to_str().unwrap(), entry.size, modified.format("%Y-%m-%d %H:%M:%S"));
+                data_stream.write_all(line.as_bytes())?;
+            }
 
+            None => data_stream.write_all(FILE_NOT_FOUND.as_bytes())?,
         }
 
         data_stream.shutdown(Shutdown::Both)?;
@@ -722,21 +1493,37 @@ impl FileTransferServer {
         Ok(())
     }
 
-    /// Lists all files in the hierarchy of the current directory.
-    ///
-    fn list(data_dir_tree: DirectoryTree<PathBuf>,writer_stream: TcpStream) -> Result<()> {
+    /// Searches the files the client can read through GET.
+    /// Sends the name, size and last modification time of each match, or the reason the query was rejected.
+    fn search(file_index: Arc<FileIndex>, can_read: &dyn Fn(&Path) -> bool, arguments: &[&str], writer_stream: TcpStream, session: &Session) -> Result<()> {
 
-        let mut data_stream = Self::create_data_stream(writer_stream)?;
+        let mut data_stream = Self::create_data_stream(writer_stream,session)?;
         let data_port = data_stream.local_addr()?.port();
 
-        let file_paths = data_dir_tree.list_files_in_tree()?;
+        match SearchQuery::parse(arguments) {
 
-        for path in file_paths {
+            Err(error) => {
+                let message = format!("{}\n{}\n", error, SEARCH_DESC);
+                data_stream.write_all(message.as_bytes())?;
+            }
 
-            let file_name = path.file_name().unwrap();
-            let formatted_name = format!("{}\n",file_name.to_str().unwrap());
-            data_stream.write_all(formatted_name.as_bytes())?;
+            Ok(query) => {
+
+                let results = query.search(&file_index, can_read);
+
+                if results.is_empty() {
+                    data_stream.write_all(NO_MATCHING_FILES.as_bytes())?;
+                }
+
+                for entry in results {
 
+                    let file_name = entry.path.file_name().unwrap();
+                    let modified: DateTime<Utc> = entry.modified.into();
+
+                    let line = format!("{}\t{}\t{}\n", file_name.to_str().unwrap(), entry.size, modified.format("%Y-%m-%d %H:%M:%S"));
+                    data_stream.write_all(line.as_bytes())?;
+                }
+            }
         }
 
         data_stream.shutdown(Shutdown::Both)?;
@@ -747,8 +1534,8 @@ impl FileTransferServer {
 
     /// The server sends the usages of each verb.
     ///
-    fn help(writer_stream: TcpStream) -> Result<()> {
-        let mut data_stream = Self::create_data_stream(writer_stream)?;
+    fn help(writer_stream: TcpStream, session: &Session) -> Result<()> {
+        let mut data_stream = Self::create_data_stream(writer_stream,session)?;
         let data_port = data_stream.local_addr()?.port();
 
         for description in VERB_DESCRIPTIONS{
@@ -763,34 +1550,68 @@ impl FileTransferServer {
 
     /// Treats an unrecognized request.
     ///
-    fn unrecognized(writer_stream: TcpStream) -> Result<()> {
-        let mut data_stream = Self::create_data_stream(writer_stream)?;
+    fn unrecognized(writer_stream: TcpStream, session: &Session) -> Result<()> {
+        Self::send_message(UNRECOGNIZED_MESSAGE, writer_stream, session)
+    }
+
+    /// Sends a single message through a data stream.
+    ///
+    fn send_message(message: &str, writer_stream: TcpStream, session: &Session) -> Result<()> {
+        let mut data_stream = Self::create_data_stream(writer_stream,session)?;
         let data_port = data_stream.local_addr()?.port();
 
-        data_stream.write_all(UNRECOGNIZED_MESSAGE.to_string().as_bytes())?;
+        data_stream.write_all(message.as_bytes())?;
 
         data_stream.shutdown(Shutdown::Both)?;
         Self::get_port_allocator().dealloc(data_port);
         Ok(())
     }
 
-    /// Saves the current lists into the specified directory.
-    /// Panics if the serialization fails.
-    fn save_lists(&self){
+    /// Records the transfer of the current request in the audit log, if it is enabled.
+    /// A failed record is logged without failing the request.
+    fn record_transfer(session: &Session, direction: TransferDirection, file_path: Option<&Path>, completed: bool) {
 
-        if let Some(directory) = &self.serialized_lists_directory {
+        let (Some(audit_log), Some(file_path)) = (AUDIT_LOG.get(), file_path) else {
+            return;
+        };
 
-            let white_list_path = PathBuf::from(directory).join(&self.white_list_name);
-            let ban_list_path = PathBuf::from(directory).join(&self.ban_list_name);
+        if let Err(error) = audit_log.record(&TransferRecord::of(session, direction, file_path, completed)) {
+            error!("Failed to write the audit log: {}", error);
+        }
+    }
+
+    /// Tells the client why its transfer was aborted, by the shutdown or by a kick.
+    /// The client may already be gone, which must not stop the server.
+    fn reply_aborted(data_stream: &mut TcpStream, session: &Session) {
+        let _ = data_stream.write_all(session.abort_reason().unwrap_or(TRANSFER_ABORTED).as_bytes());
+    }
 
-            let white_list = self.white_list.read().unwrap().clone();
-            let ban_list = self.ban_list.read().unwrap().clone();
+    fn transfer_aborted(session: &Session) -> io::Error {
+        io::Error::new(io::ErrorKind::ConnectionAborted, session.abort_reason().unwrap_or(TRANSFER_ABORTED).trim_end())
+    }
 
-            save(white_list,white_list_path).expect("Failed to save white list");
-            save(ban_list,ban_list_path).expect("Failed to save ban list");
+    /// Checks if a read or write on a stream with a timeout failed because the timeout elapsed.
+    ///
+    fn is_timeout(error: &io::Error) -> bool {
+        matches!(error.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut)
+    }
 
-        }
+    /// Checks that a requested file name is a single path component.
+    ///
+    fn is_valid_file_name(file_name: &str) -> bool {
+        let path = Path::new(file_name);
+        path.file_name().is_some_and(|name| name == file_name) && validate_path(path).is_ok()
+    }
+
+    /// Saves the current lists and the active one into the specified directory, with the expiry time of the temporary entries.
+    /// Done as the last step of the shutdown.
+    fn save_lists(&self) -> Result<()> {
+
+        self.list_store.save_white_list(&self.white_list.read().unwrap())?;
+        self.list_store.save_ban_list(&self.ban_list.read().unwrap())?;
+        self.list_store.save_active_list(&self.active_list.read().unwrap())?;
 
+        Ok(())
     }
 
     /// Extracts the ipv4 of a std::net::SocketAddr if it exists.
@@ -798,7 +1619,7 @@ impl FileTransferServer {
     fn ipv4_from_sockaddr(address: SocketAddr) -> Option<Ipv4Addr>{
 
         match address{
-            V4(addr) => Some(addr.ip().clone()),
+            V4(addr) => Some(*addr.ip()),
             V6(_addr) => None
         }
 
@@ -812,39 +1633,65 @@ impl FileTransferServer {
 
 }
 
-/// After drop the server will save its lists as json files.
-///
-impl Drop for FileTransferServer {
-    fn drop(&mut self) {
-        self.save_lists()
-    }
-}
-
 /// Simple builder for a File Transfer Server
 ///
 pub struct FileTransferServerBuilder{
     command_server_address: SocketAddrV4,
     data_directory: PathBuf,
+    storage: Option<Arc<dyn StorageBackend>>,
+    deduplicate_storage: bool,
+    versioning: VersioningConfig,
+    trash: TrashConfig,
+    shares_file_name: Option<String>,
+    groups: HashMap<String, HashSet<Ipv4Addr>>,
+    roles: RolesConfig,
+    anonymous: AnonymousConfig,
+    auto_ban: AutoBanConfig,
+    connection_limits: ConnectionLimitsConfig,
+    admin_socket_path: Option<PathBuf>,
+    stdin_console: bool,
     serialized_lists_directory: Option<PathBuf>,
+    maintenance: bool,
+    saved_maintenance: Option<bool>,
 
     active_list: ActiveList,
-    white_list: HashSet<Ipv4Addr>,
-    ban_list: HashSet<Ipv4Addr>,
+    white_list: AccessList,
+    ban_list: AccessList,
     white_list_name: String,
     ban_list_name: String,
 }
 
+impl Default for FileTransferServerBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl FileTransferServerBuilder {
 
     pub fn new() -> Self{
         FileTransferServerBuilder{
             command_server_address: SocketAddrV4::new(Ipv4Addr::UNSPECIFIED, 8080),
             data_directory: PathBuf::from("./"),
+            storage: None,
+            deduplicate_storage: false,
+            versioning: VersioningConfig::default(),
+            trash: TrashConfig::default(),
+            shares_file_name: None,
+            groups: HashMap::new(),
+            roles: RolesConfig::default(),
+            anonymous: AnonymousConfig::default(),
+            auto_ban: AutoBanConfig::default(),
+            connection_limits: ConnectionLimitsConfig::default(),
+            admin_socket_path: None,
+            stdin_console: true,
             serialized_lists_directory: None,
+            maintenance: false,
+            saved_maintenance: None,
 
             active_list: WhiteList,
-            white_list: HashSet::new(),
-            ban_list: HashSet::new(),
+            white_list: AccessList::new(),
+            ban_list: AccessList::new(),
             white_list_name: String::new(),
             ban_list_name: String::new(),
         }
@@ -873,6 +1720,34 @@ impl FileTransferServerBuilder {
         self
     }
 
+    /// Sets the storage of the served files.
+    /// If no storage is set, the files are kept on the local file system in the data directory.
+    pub fn storage_backend<B: StorageBackend + 'static>(mut self, storage: B) -> Self{
+        self.storage = Some(Arc::new(storage));
+        self
+    }
+
+    /// Keeps a single copy of identical files in a content-addressed blob store on top of the storage.
+    ///
+    pub fn deduplicate_storage(mut self) -> Self{
+        self.deduplicate_storage = true;
+        self
+    }
+
+    /// Sets the retention policy of the versions kept when files are updated or deleted.
+    ///
+    pub fn versioning(mut self, versioning: VersioningConfig) -> Self{
+        self.versioning = versioning;
+        self
+    }
+
+    /// Sets the trash policy, deleted files being moved to the trash of their owner when it is enabled.
+    ///
+    pub fn trash(mut self, trash: TrashConfig) -> Self{
+        self.trash = trash;
+        self
+    }
+
     pub fn activate_ban_list(mut self) -> Self{
         self.active_list = BanList;
         self
@@ -883,6 +1758,13 @@ impl FileTransferServerBuilder {
         self
     }
 
+    /// Activates the given list, the ban list or the white list.
+    ///
+    pub fn active_list(mut self, active_list: ActiveList) -> Self{
+        self.active_list = active_list;
+        self
+    }
+
     pub fn serialized_lists_directory(mut self,dir: PathBuf) -> Self{
 
         create_dir_all(dir.clone()).expect("Failed to create serialized lists directory");
@@ -890,8 +1772,8 @@ impl FileTransferServerBuilder {
         self
     }
 
-    /// Loads the lists given as a parameter from the serialized lists directory if it exists.
-    ///
+    /// Loads the lists given as a parameter from the serialized lists directory if it exists,
+    /// along with the list which was active when the server last saved it and the saved maintenance mode.
     pub fn load_lists(mut self,white_list_name: &str,ban_list_name: &str)-> Self{
 
         self.white_list_name = white_list_name.to_string();
@@ -902,39 +1784,160 @@ impl FileTransferServerBuilder {
             let white_list_path = PathBuf::from(directory).join(white_list_name);
             let ban_list_path = PathBuf::from(directory).join(ban_list_name);
 
-            self.white_list = load(white_list_path).unwrap_or_else(|_| HashSet::new());
-            self.ban_list = load(ban_list_path).unwrap_or_else(|_| HashSet::new());
+            self.white_list = load(white_list_path).unwrap_or_else(|_| AccessList::new());
+            self.ban_list = load(ban_list_path).unwrap_or_else(|_| AccessList::new());
+
+            // The list switched to while the server ran wins over the one activated by the builder
+            if let Ok(active_list) = load(PathBuf::from(directory).join(ACTIVE_LIST_FILE_NAME)) {
+                self.active_list = active_list;
+            }
+
+            self.saved_maintenance = load(PathBuf::from(directory).join(MAINTENANCE_FILE_NAME)).ok();
         }
 
 
         self
     }
 
+    /// Keeps the file grants in the given file of the serialized lists directory.
+    /// Without it, grants only last until the server stops.
+    pub fn shares_file_name(mut self, shares_file_name: &str) -> Self{
+        self.shares_file_name = Some(shares_file_name.to_string());
+        self
+    }
+
+    /// Sets the groups of clients files can be shared with.
+    ///
+    pub fn groups(mut self, groups: HashMap<String, HashSet<Ipv4Addr>>) -> Self{
+        self.groups = groups;
+        self
+    }
+
+    /// Sets the roles of the clients, every client having full access by default.
+    ///
+    pub fn roles(mut self, roles: RolesConfig) -> Self{
+        self.roles = roles;
+        self
+    }
+
+    /// Sets the anonymous access to the public directory, disabled by default.
+    ///
+    pub fn anonymous(mut self, anonymous: AnonymousConfig) -> Self{
+        self.anonymous = anonymous;
+        self
+    }
+
+    /// Sets the rules adding misbehaving clients to the ban list.
+    ///
+    pub fn auto_ban(mut self, auto_ban: AutoBanConfig) -> Self{
+        self.auto_ban = auto_ban;
+        self
+    }
+
+    /// Sets the maximum number of sessions, overall and per client, every connection being accepted by default.
+    ///
+    pub fn connection_limits(mut self, connection_limits: ConnectionLimitsConfig) -> Self{
+        self.connection_limits = connection_limits;
+        self
+    }
+
+    /// Serves the administration commands on a unix socket at the given path, none being served by default.
+    ///
+    pub fn admin_socket_path(mut self, admin_socket_path: Option<PathBuf>) -> Self{
+        self.admin_socket_path = admin_socket_path;
+        self
+    }
+
+    /// Reads the administration commands from the standard input, enabled by default.
+    ///
+    pub fn stdin_console(mut self, stdin_console: bool) -> Self{
+        self.stdin_console = stdin_console;
+        self
+    }
+
+    /// Starts the server in read-only maintenance mode, unless a mode was saved by the MAINTENANCE command.
+    ///
+    pub fn maintenance(mut self, maintenance: bool) -> Self{
+        self.maintenance = maintenance;
+        self
+    }
+
     /// Constructs the singleton port allocator of this struct.
     pub fn init_port_allocator(self,first_port: u16, last_port: u16) -> Self{
         PORT_ALLOCATOR.get_or_init(|| Arc::new(PortAllocator::new(first_port, last_port)));
         self
     }
 
+    /// Opens the singleton audit log of the transfers, which records nothing when disabled.
+    /// Fails if the audit file cannot be opened.
+    pub fn init_audit_log(self, audit: AuditConfig) -> Result<Self>{
+
+        if AUDIT_LOG.get().is_none() {
+            let path = audit.path.display().to_string();
+            let audit_log = AuditLog::open(audit).map_err(|error| io::Error::new(error.kind(), format!("Failed to open the audit log {}: {}", path, error)))?;
+            let _ = AUDIT_LOG.set(audit_log);
+        }
+
+        Ok(self)
+    }
+
     pub fn build(self) -> FileTransferServer{
 
+        let data_directory = self.data_directory;
+        let storage = self.storage.unwrap_or_else(||
+            Arc::new(LocalBackend::new(data_directory).expect("Failed to create local storage"))
+        );
+
+        let storage: Arc<dyn StorageBackend> = match self.deduplicate_storage {
+            true => Arc::new(DedupBackend::new(storage).expect("Failed to load the deduplicated storage")),
+            false => storage,
+        };
+
+        let versions = Arc::new(VersionStore::new(Arc::clone(&storage), self.versioning));
+        let trash = Arc::new(TrashBin::new(Arc::clone(&storage), self.trash));
+
+        let shares = match (&self.serialized_lists_directory, &self.shares_file_name) {
+            (Some(directory), Some(file_name)) => ShareRegistry::load(directory.join(file_name), self.groups).expect("Failed to load shares"),
+            _ => ShareRegistry::new(self.groups),
+        };
+
+        let ban_list = Arc::new(RwLock::new(self.ban_list));
+        let list_store = Arc::new(ListStore::new(self.serialized_lists_directory, &self.white_list_name, &self.ban_list_name));
+
+        // Saved at once so that a reload keeps a list activated after the saved one was loaded, such as the command line one
+        if let Err(error) = list_store.save_active_list(&self.active_list) {
+            error!("{} {}", SAVE_LISTS_ERROR, error);
+        }
+
         FileTransferServer{
             command_server_address:self.command_server_address,
-            data_directory: self.data_directory,
-            serialized_lists_directory: self.serialized_lists_directory,
+            storage,
+            versions,
+            trash,
+            shares: Arc::new(shares),
+            roles: Arc::new(RoleTable::new(self.roles, self.anonymous.enabled)),
+            public: Arc::new(PublicArea::new(self.anonymous)),
+            auto_ban: Arc::new(AutoBanner::new(self.auto_ban, Arc::clone(&ban_list), Arc::clone(&list_store))),
+            connection_limiter: Arc::new(ConnectionLimiter::new(self.connection_limits)),
+            sessions: Arc::new(SessionRegistry::new()),
+            maintenance: Arc::new(match self.saved_maintenance {
+                Some(enabled) => MaintenanceMode::restored(enabled),
+                None => MaintenanceMode::new(self.maintenance),
+            }),
+            admin_socket_path: self.admin_socket_path,
+            stdin_console: self.stdin_console,
+            list_store,
 
             active_list: Arc::new(RwLock::new(self.active_list)),
             white_list: Arc::new(RwLock::new(self.white_list)),
-            ban_list: Arc::new(RwLock::new(self.ban_list)),
-            white_list_name: self.white_list_name,
-            ban_list_name: self.ban_list_name,
+            ban_list,
         }
     }
 
 }
 
-#[derive(Serialize,Deserialize,Debug,Clone)]
-enum ActiveList{
+#[derive(Serialize,Deserialize,Debug,Clone,PartialEq)]
+pub enum ActiveList{
     BanList,
     WhiteList,
 }
@@ -957,15 +1960,14 @@ impl ActiveList{
     pub fn switch(&mut self){
         match &self{
 
-            BanList => {mem::replace(self, WhiteList);},
-            WhiteList => {mem::replace(self, BanList);},
+            BanList => *self = WhiteList,
+            WhiteList => *self = BanList,
         }
     }
 
 }
 
 #[cfg(test)]
-
 mod tests{
     use super::*;
 
@@ -988,10 +1990,7 @@ mod tests{
         let mut active_list = BanList;
         active_list.switch();
 
-        match active_list{
-            BanList => assert!(false),
-            WhiteList => assert!(true),
-        }
+        assert!(active_list.is_white_list());
     }
 
     #[test]
@@ -999,9 +1998,73 @@ mod tests{
         let mut active_list = WhiteList;
         active_list.switch();
 
-        match active_list{
-            BanList => assert!(true),
-            WhiteList => assert!(false),
-        }
+        assert!(active_list.is_ban_list());
+    }
+
+    #[test]
+    pub fn test_send_file_1(){
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let _client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (mut data_stream, _address) = listener.accept().unwrap();
+
+        let sessions = Arc::new(SessionRegistry::new());
+        let session = sessions.register(SocketAddrV4::new(Ipv4Addr::LOCALHOST, 40000), crate::roles::Role::Full);
+        let session_clone = sessions.get(session.id()).unwrap();
+
+        let aborter = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(300));
+            session_clone.abort(TRANSFER_ABORTED);
+        });
+
+        // The client never reads, the transfer still stops once aborted
+        let file = Box::new(io::repeat(0).take(256 * MEGABYTE as u64));
+        let error = FileTransferServer::send_file(file, &mut data_stream, &session).unwrap_err();
+
+        assert_eq!(error.kind(), io::ErrorKind::ConnectionAborted);
+        assert!(sessions.list()[0].bytes_transferred > 0);
+        aborter.join().unwrap();
+    }
+
+    #[test]
+    pub fn test_reload_1(){
+
+        let directory = std::env::temp_dir().join(format!("test_reload_{}", std::process::id()));
+        let lists_directory = directory.join("lists");
+        create_dir_all(&lists_directory).unwrap();
+        crate::serialization::save(BanList, lists_directory.join(ACTIVE_LIST_FILE_NAME)).unwrap();
+
+        // The white list given on the command line replaces the saved ban list
+        let server = FileTransferServerBuilder::new()
+            .data_directory(directory.join("data"))
+            .serialized_lists_directory(lists_directory)
+            .load_lists("white_list.json", "ban_list.json")
+            .active_list(WhiteList)
+            .build();
+
+        server.reload(&mut ThreadPool::new(1)).unwrap();
+
+        assert!(server.active_list.read().unwrap().is_white_list());
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    pub fn test_build_1(){
+
+        let directory = std::env::temp_dir().join(format!("test_build_{}", std::process::id()));
+        let lists_directory = directory.join("lists");
+
+        // A directory in place of the saved active list makes saving it fail
+        create_dir_all(lists_directory.join(ACTIVE_LIST_FILE_NAME)).unwrap();
+
+        let server = FileTransferServerBuilder::new()
+            .data_directory(directory.join("data"))
+            .serialized_lists_directory(lists_directory)
+            .load_lists("white_list.json", "ban_list.json")
+            .active_list(WhiteList)
+            .build();
+
+        assert!(server.active_list.read().unwrap().is_white_list());
+        std::fs::remove_dir_all(directory).unwrap();
     }
 }
\ No newline at end of file
@@ -1,24 +1,32 @@
-use std::collections::HashSet;
-use std::sync::{Condvar, Mutex};
+use std::sync::{Arc, Condvar, Mutex};
 
-/// Data structure used to allocate a port on demand in a multithreaded context.
-pub struct PortAllocator{
-    pool: (Mutex<Vec<u16>>,Condvar),
+/// Free ports of the allocator and the range they are taken from.
+struct PortPool{
+    free_ports: Vec<u16>,
     first_port: u16,
     last_port: u16,
 }
 
+impl PortPool {
+    fn contains(&self, port: u16) -> bool {
+        port >= self.first_port && self.last_port >= port
+    }
+}
+
+/// Data structure used to allocate a port on demand in a multithreaded context.
+pub struct PortAllocator{
+    pool: (Mutex<PortPool>,Condvar),
+}
+
 impl PortAllocator {
     /// Creates a new port allocator that will use a certain range of ports
     pub fn new(first_port: u16, last_port: u16) -> Self{
         assert!(first_port <= last_port);
 
-        let pool = (first_port..=last_port).collect::<Vec<u16>>();
+        let free_ports = (first_port..=last_port).collect::<Vec<u16>>();
 
         Self{
-            pool: (Mutex::new(pool),Condvar::new()),
-            first_port,
-            last_port,
+            pool: (Mutex::new(PortPool { free_ports, first_port, last_port }),Condvar::new()),
         }
 
     }
@@ -27,43 +35,83 @@ impl PortAllocator {
     pub fn alloc(&self) -> u16{
 
         let (mutex,condvar) = &self.pool;
-        let mut vec_guard = mutex.lock().unwrap();
+        let mut pool_guard = mutex.lock().unwrap();
 
-        if vec_guard.is_empty(){
-            vec_guard = condvar.wait(vec_guard).unwrap();
+        while pool_guard.free_ports.is_empty(){
+            pool_guard = condvar.wait(pool_guard).unwrap();
         }
 
-        vec_guard.pop().unwrap()
+        pool_guard.free_ports.pop().unwrap()
 
     }
 
     /// Will deallocate the given port by pushing it back in the vector. Will notify one thread that there is a new free port.
-    /// Will panic if the given port is not in the given range.
+    /// Ports left out of the range by set_range are dropped instead.
     /// Will not panic if the deallocated port was already free.
     pub fn dealloc(&self, port: u16){
-        assert!(port >= self.first_port && self.last_port >= port);
 
         let (mutex,condvar) = &self.pool;
-        let mut vec_guard = mutex.lock().unwrap();
+        let mut pool_guard = mutex.lock().unwrap();
+
+        if pool_guard.contains(port){
+            pool_guard.free_ports.push(port);
+            condvar.notify_one();
+        }
+
+    }
+
+    /// Changes the range of the ports, the allocated ones staying in use until they are deallocated.
+    /// Will panic if the range is empty.
+    pub fn set_range(&self, first_port: u16, last_port: u16){
+        assert!(first_port <= last_port);
+
+        let (mutex,condvar) = &self.pool;
+        let mut pool_guard = mutex.lock().unwrap();
+
+        let allocated_ports = (pool_guard.first_port..=pool_guard.last_port)
+            .filter(|port| !pool_guard.free_ports.contains(port))
+            .collect::<Vec<u16>>();
+
+        *pool_guard = PortPool {
+            free_ports: (first_port..=last_port).filter(|port| !allocated_ports.contains(port)).collect(),
+            first_port,
+            last_port,
+        };
 
-        vec_guard.push(port);
-        condvar.notify_one();
+        condvar.notify_all();
 
     }
 
     /// Returns the current port pool size.
     pub fn pool_size(&self) -> usize{
-        let (mutex,condvar) = &self.pool;
-        mutex.lock().unwrap().len()
+        let (mutex,_condvar) = &self.pool;
+        mutex.lock().unwrap().free_ports.len()
     }
 }
 
-#[cfg(test)]
+/// Allocated port given back to its allocator once dropped, whichever way the transfer using it ends.
+///
+pub struct PortLease{
+    allocator: Arc<PortAllocator>,
+    port: u16,
+}
+
+impl PortLease {
+    pub fn new(allocator: Arc<PortAllocator>, port: u16) -> Self{
+        Self{ allocator, port }
+    }
+}
+
+impl Drop for PortLease {
+    fn drop(&mut self){
+        self.allocator.dealloc(self.port);
+    }
+}
 
+#[cfg(test)]
 mod tests {
     use super::*;
     use std::thread;
-    use std::sync::Arc;
 
     #[test]
     fn test_port_allocator_1(){
@@ -98,12 +146,12 @@ mod tests {
 
         let mut handles = Vec::with_capacity(5);
 
-        for i in 1..=5{
+        for _ in 1..=5{
 
             let allocator_clone = allocator.clone();
 
             let handle = thread::spawn(move || {
-                return allocator_clone.alloc();
+                allocator_clone.alloc()
             });
 
             handles.push(handle);
@@ -115,4 +163,31 @@ mod tests {
 
         assert_eq!(ports, ports_after);
     }
+
+    #[test]
+    fn test_port_allocator_4(){
+        let allocator = PortAllocator::new(1,3);
+        let port = allocator.alloc();
+
+        allocator.set_range(2,5);
+
+        // The allocated port is not handed out twice, the ones out of the range are dropped
+        assert_eq!(allocator.pool_size(),3);
+
+        allocator.dealloc(1);
+        allocator.dealloc(port);
+
+        assert_eq!(allocator.pool_size(),4);
+    }
+
+    #[test]
+    fn test_port_lease_1(){
+        let allocator = Arc::new(PortAllocator::new(1,2));
+        let lease = PortLease::new(Arc::clone(&allocator), allocator.alloc());
+
+        assert_eq!(allocator.pool_size(),1);
+
+        drop(lease);
+        assert_eq!(allocator.pool_size(),2);
+    }
 }
@@ -1,23 +1,39 @@
-use std::env;
-use std::net::SocketAddrV4;
-use std::path::{PathBuf};
-use std::sync::OnceLock;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::{env, fmt};
+use std::net::{Ipv4Addr, SocketAddrV4};
+use std::path::{Path, PathBuf};
+use std::io::{Error, ErrorKind, Result};
+use std::sync::{Arc, OnceLock, RwLock};
+use std::time::Duration;
 use serde::{Deserialize, Serialize};
+use tracing_subscriber::EnvFilter;
 use crate::constants;
-use crate::constants::CONFIG_PATH_ENV;
-use crate::serialization::load;
+use crate::auto_ban::BanRule;
+use crate::cidr::Ipv4Cidr;
+use crate::roles::{Role, RoleAssignment};
+use crate::constants::{CONFIG_PATH_ENV, DEFAULT_CONFIG_PATH, MAX_AGE_DAYS, MAX_BAN_SECS};
+use crate::server_utils::config_source::{read_config_file, ConfigLayers, ConfigSource};
+use crate::server_utils::file_transfer_server::ActiveList;
 
-const DEFAULT_CONFIG_PATH: &str = "./config.json";
+/// Server configurations, loaded on first use and replaced on each reload.
+///
+pub static CONFIG_DATA: OnceLock<RwLock<Arc<ServerConfig>>> = OnceLock::new();
 
-/// Once initialized only structure for the server configurations.
+/// Settings given on the command line, set once before the config is loaded.
 ///
-pub static CONFIG_DATA: OnceLock<ServerConfig> = OnceLock::new();
+static CONFIG_OVERRIDES: OnceLock<ConfigOverrides> = OnceLock::new();
 
-/// Structure used to store all server configurations.
+/// Settings applied by a reload while the server runs, the others requiring a restart.
 ///
+pub const RELOADABLE_SETTINGS: [&str;7] = ["server_num_threads","buffer_size","first_port","last_port","auto_ban","connection_limits","shutdown_timeout_secs"];
+
+/// Structure used to store all server configurations.
+/// Every setting missing from the config file takes its default value.
 #[derive(Debug,Deserialize,Serialize)]
+#[serde(default)]
 pub struct ServerConfig {
     pub command_address: SocketAddrV4,
+    /// Directory of the served files. Its .staging directory is emptied at every start.
     pub data_dir_path: PathBuf,
     pub  serialized_lists_path: PathBuf,
     pub white_list_file_name: String,
@@ -26,20 +42,424 @@ pub struct ServerConfig {
     pub buffer_size: usize,
     pub first_port: u16,
     pub last_port: u16,
+    #[serde(default)]
+    pub watch_data_directory: bool,
+    #[serde(default)]
+    pub storage: StorageConfig,
+    #[serde(default)]
+    pub deduplicate_storage: bool,
+    #[serde(default)]
+    pub versioning: VersioningConfig,
+    #[serde(default)]
+    pub trash: TrashConfig,
+    #[serde(default = "ServerConfig::default_shares_file_name")]
+    pub shares_file_name: String,
+    #[serde(default)]
+    pub groups: HashMap<String, HashSet<Ipv4Addr>>,
+    #[serde(default)]
+    pub roles: RolesConfig,
+    #[serde(default)]
+    pub anonymous: AnonymousConfig,
+    #[serde(default)]
+    pub auto_ban: AutoBanConfig,
+    #[serde(default)]
+    pub connection_limits: ConnectionLimitsConfig,
+    #[serde(default)]
+    pub admin_socket_path: Option<PathBuf>,
+    #[serde(default = "ServerConfig::default_stdin_console")]
+    pub stdin_console: bool,
+    #[serde(default = "ServerConfig::default_active_list")]
+    pub active_list: ActiveList,
+    /// Time given to the ongoing transfers to finish once the server shuts down, before they are aborted.
+    pub shutdown_timeout_secs: u64,
+    /// Starts the server in read-only maintenance mode, unless the MAINTENANCE command saved another mode.
+    pub maintenance: bool,
+    pub logging: LoggingConfig,
+    pub audit: AuditConfig,
+    /// Where each setting comes from, filled in when the config is loaded.
+    #[serde(skip)]
+    pub sources: BTreeMap<String, ConfigSource>,
+}
+
+/// Settings given on the command line, which replace the ones of the config file whenever it is loaded.
+/// The config path replaces the CONFIG_PATH environment variable.
+#[derive(Debug,Clone,Default)]
+pub struct ConfigOverrides {
+    pub config_path: Option<PathBuf>,
+    pub command_address: Option<SocketAddrV4>,
+    pub data_dir_path: Option<PathBuf>,
+    pub first_port: Option<u16>,
+    pub last_port: Option<u16>,
+    pub server_num_threads: Option<usize>,
+    pub active_list: Option<ActiveList>,
+    pub stdin_console: Option<bool>,
+}
+
+impl ConfigOverrides {
+
+    /// Replaces the settings of the config with the ones given.
+    /// They are recorded as coming from the command line.
+    pub fn apply(&self, config: &mut ServerConfig) {
+
+        if let Some(command_address) = self.command_address {
+            config.command_address = command_address;
+            config.sources.insert("command_address".to_string(), ConfigSource::CommandLine);
+        }
+        if let Some(data_dir_path) = &self.data_dir_path {
+            config.data_dir_path = data_dir_path.clone();
+            config.sources.insert("data_dir_path".to_string(), ConfigSource::CommandLine);
+        }
+        if let Some(first_port) = self.first_port {
+            config.first_port = first_port;
+            config.sources.insert("first_port".to_string(), ConfigSource::CommandLine);
+        }
+        if let Some(last_port) = self.last_port {
+            config.last_port = last_port;
+            config.sources.insert("last_port".to_string(), ConfigSource::CommandLine);
+        }
+        if let Some(server_num_threads) = self.server_num_threads {
+            config.server_num_threads = server_num_threads;
+            config.sources.insert("server_num_threads".to_string(), ConfigSource::CommandLine);
+        }
+        if let Some(active_list) = &self.active_list {
+            config.active_list = active_list.clone();
+            config.sources.insert("active_list".to_string(), ConfigSource::CommandLine);
+        }
+        if let Some(stdin_console) = self.stdin_console {
+            config.stdin_console = stdin_console;
+            config.sources.insert("stdin_console".to_string(), ConfigSource::CommandLine);
+        }
+    }
+}
+
+/// Storage in which the served files are kept.
+///
+#[derive(Debug,Clone,Default,Deserialize,Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum StorageConfig {
+    /// Files are kept in the data directory.
+    #[default]
+    Local,
+    /// Files are kept as objects of an S3 compatible bucket.
+    S3(S3Config),
+}
+
+/// Connection settings of an S3 compatible bucket.
+/// Credentials missing from the config are taken from the AWS environment variables and profile.
+/// The endpoint must be set for services other than AWS, such as MinIO, which usually also need path style requests.
+#[derive(Debug,Clone,Deserialize,Serialize)]
+pub struct S3Config {
+    pub bucket: String,
+    pub region: String,
+    #[serde(default)]
+    pub endpoint: Option<String>,
+    #[serde(default)]
+    pub access_key: Option<String>,
+    #[serde(default)]
+    pub secret_key: Option<String>,
+    #[serde(default)]
+    pub key_prefix: String,
+    #[serde(default)]
+    pub path_style: bool,
+    #[serde(default = "S3Config::default_part_size")]
+    pub part_size: usize,
+}
+
+impl S3Config {
+    fn default_part_size() -> usize {
+        constants::S3_DEFAULT_PART_SIZE
+    }
+}
+
+/// Retention policy of the previous versions of a file, kept whenever it is updated or deleted.
+/// Versions beyond the newest max_versions, or archived more than max_age_days ago, are pruned.
+/// Without any limit every version is kept.
+#[derive(Debug,Clone,Default,Deserialize,Serialize)]
+pub struct VersioningConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub max_versions: Option<usize>,
+    #[serde(default)]
+    pub max_age_days: Option<u64>,
+}
+
+/// Trash area where deleted files are kept until they are restored, purged or the trash is emptied.
+/// Without a max age trashed files are only deleted when the trash is emptied.
+#[derive(Debug,Clone,Default,Deserialize,Serialize)]
+pub struct TrashConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub max_age_days: Option<u64>,
+}
+
+/// Roles of the clients, given to single addresses or to address blocks such as 10.0.0.0/8.
+/// The most specific block holding a client address gives its role, otherwise the default role applies.
+#[derive(Debug,Clone,Default,Deserialize,Serialize)]
+pub struct RolesConfig {
+    #[serde(default)]
+    pub default_role: Role,
+    #[serde(default)]
+    pub assignments: Vec<RoleAssignment>,
+}
+
+/// Anonymous access to a public directory of the storage.
+/// Once enabled, clients without an assigned role are anonymous and may only GET, LIST and STAT the public files,
+/// which every other client can read too. Only admins and publishers may upload to the public directory.
+#[derive(Debug,Clone,Deserialize,Serialize)]
+pub struct AnonymousConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "AnonymousConfig::default_public_dir")]
+    pub public_dir: String,
+    #[serde(default)]
+    pub publishers: Vec<Ipv4Cidr>,
+}
+
+impl AnonymousConfig {
+    fn default_public_dir() -> String {
+        "public".to_string()
+    }
+}
+
+impl Default for AnonymousConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            public_dir: AnonymousConfig::default_public_dir(),
+            publishers: Vec::new(),
+        }
+    }
+}
+
+/// Rules adding the misbehaving clients to the ban list, none by default.
+///
+#[derive(Debug,Clone,Default,Deserialize,Serialize)]
+pub struct AutoBanConfig {
+    #[serde(default)]
+    pub rules: Vec<BanRule>,
+}
+
+/// Maximum number of sessions handled or waiting for a worker at once, overall and per client address.
+/// Without a limit the server accepts every connection.
+#[derive(Debug,Clone,Default,Deserialize,Serialize)]
+pub struct ConnectionLimitsConfig {
+    #[serde(default)]
+    pub max_sessions: Option<usize>,
+    #[serde(default)]
+    pub max_sessions_per_ip: Option<usize>,
+}
+
+/// Log events of the server, written to the standard error unless a log directory is given.
+/// The level filters the events, such as "info" or "info,utils::server_utils::admin=debug".
+#[derive(Debug,Clone,Deserialize,Serialize)]
+pub struct LoggingConfig {
+    #[serde(default = "LoggingConfig::default_level")]
+    pub level: String,
+    #[serde(default)]
+    pub format: LogFormat,
+    #[serde(default)]
+    pub file: Option<LogFileConfig>,
+}
+
+impl LoggingConfig {
+    fn default_level() -> String {
+        constants::DEFAULT_LOG_LEVEL.to_string()
+    }
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            level: LoggingConfig::default_level(),
+            format: LogFormat::default(),
+            file: None,
+        }
+    }
+}
+
+/// Layout of the log events, one human readable line or one JSON object per event.
+///
+#[derive(Debug,Clone,Copy,Default,PartialEq,Eq,Deserialize,Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Pretty,
+    Json,
+}
+
+/// Log files of a directory, named after the prefix and the period they cover, such as server.2024-05-01.log.
+/// A new file is started each period, the oldest ones being removed beyond max_files.
+#[derive(Debug,Clone,Deserialize,Serialize)]
+pub struct LogFileConfig {
+    pub directory: PathBuf,
+    #[serde(default = "LogFileConfig::default_prefix")]
+    pub prefix: String,
+    #[serde(default)]
+    pub rotation: LogRotation,
+    #[serde(default)]
+    pub max_files: Option<usize>,
+}
+
+impl LogFileConfig {
+    fn default_prefix() -> String {
+        constants::DEFAULT_LOG_FILE_PREFIX.to_string()
+    }
+}
+
+/// Period covered by each log file.
+///
+#[derive(Debug,Clone,Copy,Default,PartialEq,Eq,Deserialize,Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogRotation {
+    Minutely,
+    Hourly,
+    #[default]
+    Daily,
+    Never,
+}
+
+/// Append-only record of the files downloaded, uploaded and deleted by the clients, disabled by default.
+/// Once the file would grow over max_size_bytes it is renamed after the current time and a new one is started.
+/// The oldest renamed files beyond max_files are only removed if delete_rotated is set, the records being kept otherwise.
+#[derive(Debug,Clone,Deserialize,Serialize)]
+pub struct AuditConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default = "AuditConfig::default_path")]
+    pub path: PathBuf,
+    #[serde(default)]
+    pub format: AuditFormat,
+    #[serde(default)]
+    pub max_size_bytes: Option<u64>,
+    #[serde(default)]
+    pub max_files: Option<usize>,
+    #[serde(default)]
+    pub delete_rotated: bool,
+}
+
+impl AuditConfig {
+    fn default_path() -> PathBuf {
+        PathBuf::from(constants::DEFAULT_AUDIT_LOG_PATH)
+    }
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            path: AuditConfig::default_path(),
+            format: AuditFormat::default(),
+            max_size_bytes: None,
+            max_files: None,
+            delete_rotated: false,
+        }
+    }
+}
+
+/// Layout of the audit records, the wu-ftpd xferlog line or one JSON object per line.
+///
+#[derive(Debug,Clone,Copy,Default,PartialEq,Eq,Deserialize,Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuditFormat {
+    #[default]
+    Xferlog,
+    Json,
+}
+
+/// Setting of the config which can not be used, along with the reason.
+///
+#[derive(Debug,Clone,PartialEq)]
+pub struct ConfigProblem {
+    pub field: String,
+    pub reason: String,
+}
+
+impl ConfigProblem {
+    fn new(field: &str, reason: impl Into<String>) -> Self {
+        Self {
+            field: field.to_string(),
+            reason: reason.into(),
+        }
+    }
+}
+
+impl fmt::Display for ConfigProblem {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(formatter, "{}: {}", self.field, self.reason)
+    }
+}
+
+/// Settings which differ between two configurations, split between the ones a reload applies and the ones requiring a restart.
+///
+#[derive(Debug,Clone,Default,PartialEq,Deserialize,Serialize)]
+pub struct ConfigChanges {
+    pub applied: Vec<String>,
+    pub restart_required: Vec<String>,
+}
+
+impl ConfigChanges {
+
+    /// Compares the settings of two configurations, sorted by name.
+    ///
+    pub fn between(current: &ServerConfig, reloaded: &ServerConfig) -> Result<Self> {
+
+        let current = serde_json::to_value(current)?;
+        let reloaded = serde_json::to_value(reloaded)?;
+
+        let mut changes = Self::default();
+
+        if let (Some(current), Some(reloaded)) = (current.as_object(), reloaded.as_object()) {
+
+            for (setting, value) in reloaded {
+
+                if current.get(setting) == Some(value) {
+                    continue;
+                }
+
+                match RELOADABLE_SETTINGS.contains(&setting.as_str()) {
+                    true => changes.applied.push(setting.clone()),
+                    false => changes.restart_required.push(setting.clone()),
+                }
+            }
+        }
+
+        Ok(changes)
+    }
 }
 
 impl Default for ServerConfig {
     fn default() -> Self {
         Self{
-            command_address: constants::EPHEMERAL_ADDRESS,
-            data_dir_path: PathBuf::default(),
-            serialized_lists_path: PathBuf::default(),
-            white_list_file_name: String::default(),
-            ban_list_file_name: String::default(),
-            server_num_threads: 0,
-            buffer_size: 0,
-            first_port: 1,
-            last_port: 2,
+            command_address: constants::DEFAULT_COMMAND_ADDRESS,
+            data_dir_path: PathBuf::from(constants::DEFAULT_DATA_DIR_PATH),
+            serialized_lists_path: PathBuf::from(constants::DEFAULT_SERIALIZED_LISTS_PATH),
+            white_list_file_name: constants::DEFAULT_WHITE_LIST_FILE_NAME.to_string(),
+            ban_list_file_name: constants::DEFAULT_BAN_LIST_FILE_NAME.to_string(),
+            server_num_threads: constants::DEFAULT_SERVER_NUM_THREADS,
+            buffer_size: constants::DEFAULT_BUFFER_SIZE,
+            first_port: constants::DEFAULT_FIRST_PORT,
+            last_port: constants::DEFAULT_LAST_PORT,
+            watch_data_directory: false,
+            storage: StorageConfig::default(),
+            deduplicate_storage: false,
+            versioning: VersioningConfig::default(),
+            trash: TrashConfig::default(),
+            shares_file_name: ServerConfig::default_shares_file_name(),
+            groups: HashMap::new(),
+            roles: RolesConfig::default(),
+            anonymous: AnonymousConfig::default(),
+            auto_ban: AutoBanConfig::default(),
+            connection_limits: ConnectionLimitsConfig::default(),
+            admin_socket_path: None,
+            stdin_console: ServerConfig::default_stdin_console(),
+            active_list: ServerConfig::default_active_list(),
+            shutdown_timeout_secs: constants::DEFAULT_SHUTDOWN_TIMEOUT_SECS,
+            maintenance: false,
+            logging: LoggingConfig::default(),
+            audit: AuditConfig::default(),
+            sources: BTreeMap::new(),
         }
     }
 }
@@ -50,22 +470,251 @@ impl ServerConfig {
     /// Initializes the config data on the first call and returns it.
     /// The config file path is set using the CONFIG_PATH environment variable.
     /// If the environment variable is not set a default path is used.
-    pub fn get_config() -> &'static ServerConfig {
+    /// Panics if the config file can not be loaded or is invalid, which init reports without panicking.
+    pub fn get_config() -> Arc<ServerConfig> {
+
+        let config = CONFIG_DATA.get_or_init(||{
+
+            let config = Self::load_config().unwrap_or_else(|error| panic!("{} {}: {}",constants::CONFIG_LOAD_ERROR,Self::config_path(),error));
+            RwLock::new(Arc::new(config))
+
+        });
+
+        Arc::clone(&config.read().unwrap())
+    }
+
+    /// Loads and validates the config file if it was not loaded yet.
+    /// The error lists every problem found in the config.
+    pub fn init() -> Result<()> {
+
+        if CONFIG_DATA.get().is_none() {
+            let config = Self::load_config().map_err(|error| Error::new(error.kind(), format!("{} {}: {}",constants::CONFIG_LOAD_ERROR,Self::config_path(),error)))?;
+            CONFIG_DATA.get_or_init(|| RwLock::new(Arc::new(config)));
+        }
+
+        Ok(())
+    }
+
+    /// Reads the config file again and applies its reloadable settings to the current configurations.
+    /// Returns the changed settings, the ones requiring a restart keep their current value until then.
+    pub fn reload() -> Result<ConfigChanges> {
+
+        let config = Self::load_config()?;
+
+        let current = Self::get_config();
+        let changes = ConfigChanges::between(&current, &config)?;
+        let config = current.with_reloadable_settings(&config)?;
+
+        *CONFIG_DATA.get().expect("The config is loaded by get_config").write().unwrap() = Arc::new(config);
+
+        Ok(changes)
+    }
+
+    /// Returns a copy of this config with the reloadable settings, and their sources, taken from the reloaded one.
+    ///
+    pub fn with_reloadable_settings(&self, reloaded: &ServerConfig) -> Result<ServerConfig> {
+
+        let mut settings = serde_json::to_value(self)?;
+        let reloaded_settings = serde_json::to_value(reloaded)?;
+
+        if let (Some(settings), Some(reloaded_settings)) = (settings.as_object_mut(), reloaded_settings.as_object()) {
+            for setting in RELOADABLE_SETTINGS {
+                if let Some(value) = reloaded_settings.get(setting) {
+                    settings.insert(setting.to_string(), value.clone());
+                }
+            }
+        }
+
+        let mut config: ServerConfig = serde_json::from_value(settings)?;
+        config.sources = self.sources.clone();
+
+        for setting in RELOADABLE_SETTINGS {
+            match reloaded.sources.get(setting) {
+                Some(source) => config.sources.insert(setting.to_string(), *source),
+                None => config.sources.remove(setting),
+            };
+        }
+
+        Ok(config)
+    }
+
+    /// Sets the settings given on the command line, which must happen before the config is loaded.
+    /// Returns false if they were already set.
+    pub fn set_overrides(overrides: ConfigOverrides) -> bool {
+        CONFIG_OVERRIDES.set(overrides).is_ok()
+    }
+
+    /// Returns the path of the config file, given on the command line, by the CONFIG_PATH environment variable or the default one.
+    ///
+    pub fn config_path() -> String {
+
+        if let Some(config_path) = CONFIG_OVERRIDES.get().and_then(|overrides| overrides.config_path.as_ref()) {
+            return config_path.display().to_string();
+        }
+
+        env::var(CONFIG_PATH_ENV).unwrap_or_else(|_| DEFAULT_CONFIG_PATH.to_string())
+    }
+
+    fn config_path_given() -> bool {
+        CONFIG_OVERRIDES.get().is_some_and(|overrides| overrides.config_path.is_some()) || env::var_os(CONFIG_PATH_ENV).is_some()
+    }
+
+    /// Loads the config from its layers, validated once merged: the defaults, the config file,
+    /// the FTP_ environment variables and the settings given on the command line.
+    /// A missing config file is only an error if its path was given, without replacing the current configurations.
+    pub fn load_config() -> Result<ServerConfig> {
+
+        let mut layers = ConfigLayers::new(&ServerConfig::default())?;
 
-        CONFIG_DATA.get_or_init(||{
+        match read_config_file(Self::config_path()) {
+            Ok(settings) => layers.merge_file(settings)?,
+            Err(error) if error.kind() == ErrorKind::NotFound && !Self::config_path_given() => {}
+            Err(error) => return Err(error),
+        }
+        layers.merge_env(|key| env::var(key).ok());
+
+        let (mut config, sources) = layers.build::<ServerConfig>()?;
+        config.sources = sources;
+
+        if let Some(overrides) = CONFIG_OVERRIDES.get() {
+            overrides.apply(&mut config);
+        }
+        let problems = config.validate();
+
+        if !problems.is_empty() {
+            let problems = problems.iter().map(|problem| format!("\n  {}", problem)).collect::<String>();
+            return Err(Error::new(ErrorKind::InvalidData, format!("{}{}", constants::INVALID_CONFIG, problems)));
+        }
+
+        Ok(config)
+    }
 
+    /// Checks the settings which would fail or hang the server once in use.
+    /// Returns every problem found, none if the config is valid.
+    pub fn validate(&self) -> Vec<ConfigProblem> {
 
+        let mut problems = Vec::new();
+
+        if self.server_num_threads == 0 {
+            problems.push(ConfigProblem::new("server_num_threads", "must be at least 1"));
+        }
 
-            match env::var(CONFIG_PATH_ENV){
-                Ok(config_path) => load::<ServerConfig,String>(config_path.clone()).expect(&format!("{} {}",constants::CONFIG_LOAD_ERROR,config_path)),
-                Err(_) => load::<ServerConfig,&str>(DEFAULT_CONFIG_PATH).expect(&format!("{} {}",constants::CONFIG_LOAD_ERROR,DEFAULT_CONFIG_PATH)),
+        if self.buffer_size == 0 {
+            problems.push(ConfigProblem::new("buffer_size", "must be at least 1"));
+        }
+
+        if self.first_port == 0 {
+            problems.push(ConfigProblem::new("first_port", "must not be 0"));
+        }
+
+        if self.first_port > self.last_port {
+            problems.push(ConfigProblem::new("first_port", format!("must not be greater than last_port ({})", self.last_port)));
+        }
+
+        if (self.first_port..=self.last_port).contains(&self.command_address.port()) {
+            problems.push(ConfigProblem::new("command_address", format!("port {} is in the data port range {}-{}", self.command_address.port(), self.first_port, self.last_port)));
+        }
+
+        for (field, file_name) in [("white_list_file_name", &self.white_list_file_name), ("ban_list_file_name", &self.ban_list_file_name), ("shares_file_name", &self.shares_file_name)] {
+            if !Self::is_file_name(file_name) {
+                problems.push(ConfigProblem::new(field, format!("{:?} is not a file name", file_name)));
+            }
+            else if file_name == constants::ACTIVE_LIST_FILE_NAME {
+                problems.push(ConfigProblem::new(field, format!("{:?} is reserved for the active list", file_name)));
             }
+            else if file_name == constants::MAINTENANCE_FILE_NAME {
+                problems.push(ConfigProblem::new(field, format!("{:?} is reserved for the maintenance mode", file_name)));
+            }
+        }
+
+        if self.white_list_file_name == self.ban_list_file_name {
+            problems.push(ConfigProblem::new("ban_list_file_name", "must differ from white_list_file_name"));
+        }
+
+        if let StorageConfig::S3(s3) = &self.storage {
+            if s3.bucket.is_empty() {
+                problems.push(ConfigProblem::new("storage.bucket", "must not be empty"));
+            }
+            if s3.part_size < constants::S3_MIN_PART_SIZE {
+                problems.push(ConfigProblem::new("storage.part_size", format!("must be at least {} bytes", constants::S3_MIN_PART_SIZE)));
+            }
+        }
+
+        if self.versioning.max_versions == Some(0) {
+            problems.push(ConfigProblem::new("versioning.max_versions", "must be at least 1 when set"));
+        }
+
+        if self.versioning.max_age_days.is_some_and(|days| days > MAX_AGE_DAYS) {
+            problems.push(ConfigProblem::new("versioning.max_age_days", format!("must be at most {}, leave it out to keep the versions", MAX_AGE_DAYS)));
+        }
+
+        if self.trash.max_age_days.is_some_and(|days| days > MAX_AGE_DAYS) {
+            problems.push(ConfigProblem::new("trash.max_age_days", format!("must be at most {}, leave it out to keep the trashed files", MAX_AGE_DAYS)));
+        }
 
-        })
+        if !Self::is_file_name(&self.anonymous.public_dir) || self.anonymous.public_dir.starts_with('.') {
+            problems.push(ConfigProblem::new("anonymous.public_dir", format!("{:?} is not a directory name, or is reserved for the server", self.anonymous.public_dir)));
+        }
+
+        for (index, rule) in self.auto_ban.rules.iter().enumerate() {
+            if rule.max_count == 0 {
+                problems.push(ConfigProblem::new(&format!("auto_ban.rules[{}].max_count", index), "must be at least 1"));
+            }
+            if rule.window_secs == 0 {
+                problems.push(ConfigProblem::new(&format!("auto_ban.rules[{}].window_secs", index), "must be at least 1"));
+            }
+            if rule.ban_secs.is_some_and(|ban_secs| ban_secs > MAX_BAN_SECS) {
+                problems.push(ConfigProblem::new(&format!("auto_ban.rules[{}].ban_secs", index), format!("must be at most {}, leave it out for a permanent ban", MAX_BAN_SECS)));
+            }
+        }
+
+        if self.connection_limits.max_sessions == Some(0) {
+            problems.push(ConfigProblem::new("connection_limits.max_sessions", "must be at least 1 when set"));
+        }
+
+        if self.connection_limits.max_sessions_per_ip == Some(0) {
+            problems.push(ConfigProblem::new("connection_limits.max_sessions_per_ip", "must be at least 1 when set"));
+        }
+
+        if let Err(error) = EnvFilter::try_new(&self.logging.level) {
+            problems.push(ConfigProblem::new("logging.level", error.to_string()));
+        }
+
+        if let Some(file) = &self.logging.file {
+            if !Self::is_file_name(&file.prefix) {
+                problems.push(ConfigProblem::new("logging.file.prefix", format!("{:?} is not a file name", file.prefix)));
+            }
+            if file.max_files == Some(0) {
+                problems.push(ConfigProblem::new("logging.file.max_files", "must be at least 1 when set"));
+            }
+        }
+
+        if self.audit.enabled && self.audit.path.file_name().is_none() {
+            problems.push(ConfigProblem::new("audit.path", format!("{:?} is not a file path", self.audit.path)));
+        }
+
+        if self.audit.max_size_bytes == Some(0) {
+            problems.push(ConfigProblem::new("audit.max_size_bytes", "must be at least 1 when set"));
+        }
+
+        if self.audit.max_files == Some(0) {
+            problems.push(ConfigProblem::new("audit.max_files", "must be at least 1 when set"));
+        }
+        else if self.audit.max_files.is_some() && !self.audit.delete_rotated {
+            problems.push(ConfigProblem::new("audit.max_files", "deletes the oldest records, set audit.delete_rotated to allow it"));
+        }
+
+        problems
+    }
+
+    /// Checks if the name is a single component of a path.
+    ///
+    fn is_file_name(name: &str) -> bool {
+        !name.is_empty() && Path::new(name).file_name() == Some(name.as_ref())
     }
 
     pub fn get_command_address() -> SocketAddrV4 {
-        Self::get_config().command_address.clone()
+        Self::get_config().command_address
     }
     pub fn get_data_dir_path() -> PathBuf {
         Self::get_config().data_dir_path.clone()
@@ -85,11 +734,40 @@ impl ServerConfig {
     }
     pub fn get_first_port() -> u16 {Self::get_config().first_port}
     pub fn get_last_port() -> u16 {Self::get_config().last_port}
+    pub fn get_watch_data_directory() -> bool {Self::get_config().watch_data_directory}
+    pub fn get_storage() -> StorageConfig {Self::get_config().storage.clone()}
+    pub fn get_deduplicate_storage() -> bool {Self::get_config().deduplicate_storage}
+    pub fn get_versioning() -> VersioningConfig {Self::get_config().versioning.clone()}
+    pub fn get_trash() -> TrashConfig {Self::get_config().trash.clone()}
+    pub fn get_shares_file_name() -> String {Self::get_config().shares_file_name.clone()}
+    pub fn get_groups() -> HashMap<String, HashSet<Ipv4Addr>> {Self::get_config().groups.clone()}
+    pub fn get_roles() -> RolesConfig {Self::get_config().roles.clone()}
+    pub fn get_anonymous() -> AnonymousConfig {Self::get_config().anonymous.clone()}
+    pub fn get_auto_ban() -> AutoBanConfig {Self::get_config().auto_ban.clone()}
+    pub fn get_connection_limits() -> ConnectionLimitsConfig {Self::get_config().connection_limits.clone()}
+    pub fn get_admin_socket_path() -> Option<PathBuf> {Self::get_config().admin_socket_path.clone()}
+    pub fn get_stdin_console() -> bool {Self::get_config().stdin_console}
+    pub fn get_active_list() -> ActiveList {Self::get_config().active_list.clone()}
+    pub fn get_shutdown_timeout() -> Duration {Duration::from_secs(Self::get_config().shutdown_timeout_secs)}
+    pub fn get_maintenance() -> bool {Self::get_config().maintenance}
+    pub fn get_logging() -> LoggingConfig {Self::get_config().logging.clone()}
+    pub fn get_audit() -> AuditConfig {Self::get_config().audit.clone()}
+
+    fn default_shares_file_name() -> String {
+        "shares.json".to_string()
+    }
+
+    fn default_stdin_console() -> bool {
+        true
+    }
+
+    fn default_active_list() -> ActiveList {
+        ActiveList::BanList
+    }
 }
 
 
 #[cfg(test)]
-
 mod tests {
     use crate::constants::CONFIG_PATH_ENV;
     use super::*;
@@ -98,11 +776,234 @@ mod tests {
     pub fn test_server_config_1(){
 
         env::set_var(CONFIG_PATH_ENV, "./tests/config.json");
-        let server_config = ServerConfig::get_config();
+        ServerConfig::get_config();
 
         assert_eq!(ServerConfig::get_buffer_size(),8192);
         assert_eq!(ServerConfig::get_server_num_threads(),10);
         assert_eq!(ServerConfig::get_data_dir_path(),PathBuf::from("./data"));
 
     }
-}
\ No newline at end of file
+
+    #[test]
+    pub fn test_storage_config_1(){
+
+        let storage: StorageConfig = serde_json::from_str(r#"{"type":"local"}"#).unwrap();
+        assert!(matches!(storage, StorageConfig::Local));
+
+        let storage: StorageConfig = serde_json::from_str(r#"{"type":"s3","bucket":"files","region":"us-east-1"}"#).unwrap();
+
+        match storage {
+            StorageConfig::S3(config) => {
+                assert_eq!(config.bucket,"files");
+                assert_eq!(config.part_size,constants::S3_DEFAULT_PART_SIZE);
+                assert!(config.endpoint.is_none());
+                assert!(!config.path_style);
+            }
+            StorageConfig::Local => panic!("Expected an S3 storage"),
+        }
+
+    }
+
+    #[test]
+    pub fn test_versioning_config_1(){
+
+        let versioning: VersioningConfig = serde_json::from_str(r#"{"enabled":true,"max_versions":5}"#).unwrap();
+
+        assert!(versioning.enabled);
+        assert_eq!(versioning.max_versions,Some(5));
+        assert!(versioning.max_age_days.is_none());
+        assert!(!VersioningConfig::default().enabled);
+
+    }
+
+    #[test]
+    pub fn test_anonymous_config_1(){
+
+        let anonymous: AnonymousConfig = serde_json::from_str(r#"{"enabled":true,"publishers":["10.0.0.0/24"]}"#).unwrap();
+
+        assert!(anonymous.enabled);
+        assert_eq!(anonymous.public_dir,"public");
+        assert_eq!(anonymous.publishers[0].prefix_len(),24);
+        assert!(!AnonymousConfig::default().enabled);
+
+    }
+
+    #[test]
+    pub fn test_auto_ban_config_1(){
+
+        let auto_ban: AutoBanConfig = serde_json::from_str(r#"{"rules":[
+            {"offence":"unrecognized_command","max_count":5,"window_secs":60,"ban_secs":1800},
+            {"offence":"connection","max_count":100,"window_secs":10}
+        ]}"#).unwrap();
+
+        assert_eq!(auto_ban.rules[0].offence,crate::auto_ban::Offence::UnrecognizedCommand);
+        assert!(auto_ban.rules[1].ban_secs.is_none());
+        assert!(AutoBanConfig::default().rules.is_empty());
+
+    }
+
+    #[test]
+    pub fn test_config_changes_1(){
+
+        let current = ServerConfig::default();
+        let reloaded = ServerConfig {
+            buffer_size: 4096,
+            last_port: 10,
+            data_dir_path: PathBuf::from("./elsewhere"),
+            ..ServerConfig::default()
+        };
+
+        let changes = ConfigChanges::between(&current, &reloaded).unwrap();
+
+        assert_eq!(changes.applied, vec!["buffer_size", "last_port"]);
+        assert_eq!(changes.restart_required, vec!["data_dir_path"]);
+        assert_eq!(ConfigChanges::between(&current, &ServerConfig::default()).unwrap(), ConfigChanges::default());
+
+    }
+
+    #[test]
+    pub fn test_config_changes_2(){
+
+        let mut current = ServerConfig::default();
+        current.sources.insert("data_dir_path".to_string(), ConfigSource::CommandLine);
+
+        let mut reloaded = ServerConfig {
+            buffer_size: 4096,
+            last_port: 10,
+            data_dir_path: PathBuf::from("./elsewhere"),
+            ..ServerConfig::default()
+        };
+        reloaded.sources.insert("buffer_size".to_string(), ConfigSource::File);
+        reloaded.sources.insert("data_dir_path".to_string(), ConfigSource::File);
+
+        let config = current.with_reloadable_settings(&reloaded).unwrap();
+
+        assert_eq!(config.buffer_size, 4096);
+        assert_eq!(config.last_port, 10);
+        assert_eq!(config.data_dir_path, current.data_dir_path);
+        assert_eq!(config.sources.get("buffer_size"), Some(&ConfigSource::File));
+        assert_eq!(config.sources.get("data_dir_path"), Some(&ConfigSource::CommandLine));
+        assert_eq!(ConfigChanges::between(&config, &reloaded).unwrap().applied, Vec::<String>::new());
+
+    }
+
+    #[test]
+    pub fn test_validate_config_1(){
+
+        let config = ServerConfig {
+            command_address: "0.0.0.0:7878".parse().unwrap(),
+            white_list_file_name: "white_list.json".to_string(),
+            ban_list_file_name: "ban_list.json".to_string(),
+            server_num_threads: 4,
+            buffer_size: 8192,
+            first_port: 50000,
+            last_port: 50100,
+            ..ServerConfig::default()
+        };
+
+        assert!(config.validate().is_empty());
+
+        let config = ServerConfig {
+            server_num_threads: 0,
+            buffer_size: 0,
+            first_port: 50100,
+            last_port: 50000,
+            ban_list_file_name: "../ban_list.json".to_string(),
+            versioning: VersioningConfig { enabled: true, max_versions: None, max_age_days: Some(u64::MAX) },
+            trash: TrashConfig { enabled: true, max_age_days: Some(MAX_AGE_DAYS + 1) },
+            auto_ban: AutoBanConfig { rules: vec![BanRule { offence: crate::auto_ban::Offence::Connection, max_count: 1, window_secs: 1, ban_secs: Some(u64::MAX) }] },
+            audit: AuditConfig { enabled: true, path: PathBuf::from("/"), max_size_bytes: Some(0), max_files: Some(5), ..AuditConfig::default() },
+            ..config
+        };
+
+        let fields = config.validate().into_iter().map(|problem| problem.field).collect::<Vec<String>>();
+
+        assert_eq!(fields, vec!["server_num_threads", "buffer_size", "first_port", "ban_list_file_name", "versioning.max_age_days", "trash.max_age_days", "auto_ban.rules[0].ban_secs", "audit.path", "audit.max_size_bytes", "audit.max_files"]);
+
+    }
+
+    #[test]
+    pub fn test_validate_config_2(){
+
+        let config = ServerConfig {
+            command_address: "0.0.0.0:50050".parse().unwrap(),
+            white_list_file_name: "lists.json".to_string(),
+            ban_list_file_name: "lists.json".to_string(),
+            server_num_threads: 4,
+            buffer_size: 8192,
+            first_port: 50000,
+            last_port: 50100,
+            connection_limits: ConnectionLimitsConfig { max_sessions: Some(0), max_sessions_per_ip: None },
+            logging: LoggingConfig {
+                level: "info,=verbose".to_string(),
+                format: LogFormat::Json,
+                file: Some(LogFileConfig { directory: PathBuf::from("logs"), prefix: "server".to_string(), rotation: LogRotation::Hourly, max_files: Some(0) }),
+            },
+            ..ServerConfig::default()
+        };
+
+        let problems = config.validate();
+
+        assert_eq!(problems[0].to_string(), "command_address: port 50050 is in the data port range 50000-50100");
+        assert_eq!(problems[1].field, "ban_list_file_name");
+        assert_eq!(problems[2].field, "connection_limits.max_sessions");
+        assert_eq!(problems[3].field, "logging.level");
+        assert_eq!(problems[4].field, "logging.file.max_files");
+        assert_eq!(problems.len(), 5);
+
+    }
+
+    #[test]
+    pub fn test_config_overrides_1(){
+
+        let mut config: ServerConfig = serde_json::from_str(r#"{
+            "command_address":"0.0.0.0:7878","data_dir_path":"./data","serialized_lists_path":"./serialized_lists",
+            "white_list_file_name":"white_list.json","ban_list_file_name":"ban_list.json",
+            "server_num_threads":10,"buffer_size":8192,"first_port":50000,"last_port":50100
+        }"#).unwrap();
+
+        assert!(config.active_list.is_ban_list());
+
+        let overrides = ConfigOverrides {
+            last_port: Some(50010),
+            server_num_threads: Some(2),
+            stdin_console: Some(false),
+            ..ConfigOverrides::default()
+        };
+
+        overrides.apply(&mut config);
+
+        // Only the given settings are replaced
+        assert_eq!((config.first_port, config.last_port), (50000, 50010));
+        assert_eq!(config.server_num_threads, 2);
+        assert!(!config.stdin_console);
+        assert_eq!(config.data_dir_path, PathBuf::from("./data"));
+        assert_eq!(config.sources.get("last_port"), Some(&ConfigSource::CommandLine));
+        assert_eq!(config.sources.get("first_port"), None);
+
+    }
+
+    #[test]
+    pub fn test_default_config_1(){
+
+        // Every setting has a default, which makes a valid config
+        let config: ServerConfig = serde_json::from_str("{}").unwrap();
+
+        assert!(config.validate().is_empty());
+        assert_eq!(config.command_address, constants::DEFAULT_COMMAND_ADDRESS);
+        assert_eq!((config.first_port, config.last_port), (50000, 50100));
+
+        let mut layers = ConfigLayers::new(&ServerConfig::default()).unwrap();
+
+        layers.merge_file(serde_json::json!({"buffer_size": 4096})).unwrap();
+        layers.merge_env(|key| (key == "FTP_FIRST_PORT").then(|| "50050".to_string()));
+
+        let (config, sources) = layers.build::<ServerConfig>().unwrap();
+
+        assert_eq!((config.buffer_size, config.first_port), (4096, 50050));
+        assert_eq!(sources["buffer_size"], ConfigSource::File);
+        assert_eq!(sources["first_port"], ConfigSource::Environment);
+        assert_eq!(sources["last_port"], ConfigSource::Default);
+
+    }
+}
@@ -0,0 +1,267 @@
+use std::collections::BTreeMap;
+use std::net::SocketAddrV4;
+use std::ops::Deref;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+use crate::roles::Role;
+
+/// Request being handled for a session, the transfer rate being measured from its start.
+#[derive(Debug)]
+struct Request {
+    verb: Option<String>,
+    file: Option<String>,
+    started: Instant,
+}
+
+/// Client connection being handled, kept up to date by the request handlers.
+/// Its transfers stop once it is aborted, by a kick or by the shutdown.
+#[derive(Debug)]
+pub struct Session {
+    id: u64,
+    peer: SocketAddrV4,
+    role: Role,
+    started_at: SystemTime,
+    request: Mutex<Request>,
+    bytes_transferred: AtomicU64,
+    abort_reason: OnceLock<&'static str>,
+}
+
+/// State of a session, as shown to the administrators.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionInfo {
+    pub id: u64,
+    pub peer: SocketAddrV4,
+    pub role: Role,
+    pub verb: Option<String>,
+    pub file: Option<String>,
+    pub bytes_transferred: u64,
+    pub bytes_per_sec: u64,
+    /// Start of the session, in seconds since the unix epoch.
+    pub started: u64,
+}
+
+impl Session {
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn peer(&self) -> SocketAddrV4 {
+        self.peer
+    }
+
+    pub fn role(&self) -> Role {
+        self.role
+    }
+
+    /// Returns the bytes transferred for the current request.
+    ///
+    pub fn bytes_transferred(&self) -> u64 {
+        self.bytes_transferred.load(Ordering::Relaxed)
+    }
+
+    /// Returns the time elapsed since the current request was received.
+    ///
+    pub fn request_elapsed(&self) -> Duration {
+        self.request.lock().unwrap().started.elapsed()
+    }
+
+    /// Records the request being handled, the bytes transferred being counted from now on.
+    ///
+    pub fn set_request(&self, verb: &str, file: Option<&str>) {
+        *self.request.lock().unwrap() = Request {
+            verb: Some(verb.to_string()),
+            file: file.map(str::to_string),
+            started: Instant::now(),
+        };
+        self.bytes_transferred.store(0, Ordering::Relaxed);
+    }
+
+    /// Counts bytes sent or received for the current request.
+    ///
+    pub fn add_bytes(&self, bytes: usize) {
+        self.bytes_transferred.fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Asks the handlers to end the session, the reason being sent to the client.
+    /// Returns false if it was already aborted, in which case the first reason is kept.
+    pub fn abort(&self, reason: &'static str) -> bool {
+        self.abort_reason.set(reason).is_ok()
+    }
+
+    pub fn abort_reason(&self) -> Option<&'static str> {
+        self.abort_reason.get().copied()
+    }
+
+    pub fn is_aborted(&self) -> bool {
+        self.abort_reason.get().is_some()
+    }
+
+    /// Returns the state of the session, with its transfer rate at the given time.
+    ///
+    pub fn info(&self, now: Instant) -> SessionInfo {
+
+        let request = self.request.lock().unwrap();
+        let bytes_transferred = self.bytes_transferred.load(Ordering::Relaxed);
+        let elapsed = now.saturating_duration_since(request.started).as_secs_f64();
+
+        SessionInfo {
+            id: self.id,
+            peer: self.peer,
+            role: self.role,
+            verb: request.verb.clone(),
+            file: request.file.clone(),
+            bytes_transferred,
+            bytes_per_sec: match elapsed > 0.0 {
+                true => (bytes_transferred as f64 / elapsed) as u64,
+                false => 0,
+            },
+            started: self.started_at.duration_since(UNIX_EPOCH).unwrap_or_default().as_secs(),
+        }
+    }
+}
+
+/// Sessions being handled, each with an id unique for the lifetime of the server.
+#[derive(Debug, Default)]
+pub struct SessionRegistry {
+    next_id: AtomicU64,
+    sessions: Mutex<BTreeMap<u64, Arc<Session>>>,
+}
+
+/// Session registered while the handle is alive, removed from the registry when dropped.
+#[derive(Debug)]
+pub struct SessionHandle {
+    registry: Arc<SessionRegistry>,
+    session: Arc<Session>,
+}
+
+impl SessionRegistry {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the session of a client which just connected.
+    ///
+    pub fn register(self: &Arc<Self>, peer: SocketAddrV4, role: Role) -> SessionHandle {
+
+        let session = Arc::new(Session {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed) + 1,
+            peer,
+            role,
+            started_at: SystemTime::now(),
+            request: Mutex::new(Request { verb: None, file: None, started: Instant::now() }),
+            bytes_transferred: AtomicU64::new(0),
+            abort_reason: OnceLock::new(),
+        });
+
+        self.sessions.lock().unwrap().insert(session.id, Arc::clone(&session));
+
+        SessionHandle {
+            registry: Arc::clone(self),
+            session,
+        }
+    }
+
+    pub fn get(&self, id: u64) -> Option<Arc<Session>> {
+        self.sessions.lock().unwrap().get(&id).cloned()
+    }
+
+    /// Returns the state of every session, oldest first.
+    ///
+    pub fn list(&self) -> Vec<SessionInfo> {
+        let now = Instant::now();
+        self.sessions.lock().unwrap().values().map(|session| session.info(now)).collect()
+    }
+
+    /// Aborts every session for the same reason.
+    ///
+    pub fn abort_all(&self, reason: &'static str) {
+        for session in self.sessions.lock().unwrap().values() {
+            session.abort(reason);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.sessions.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sessions.lock().unwrap().is_empty()
+    }
+}
+
+impl Deref for SessionHandle {
+    type Target = Session;
+
+    fn deref(&self) -> &Session {
+        &self.session
+    }
+}
+
+impl Drop for SessionHandle {
+    fn drop(&mut self) {
+        self.registry.sessions.lock().unwrap().remove(&self.session.id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    const PEER: SocketAddrV4 = SocketAddrV4::new(Ipv4Addr::new(127, 0, 0, 2), 40000);
+
+    #[test]
+    pub fn test_session_registry_1(){
+
+        let registry = Arc::new(SessionRegistry::new());
+
+        let first = registry.register(PEER, Role::Full);
+        let second = registry.register(PEER, Role::ReadOnly);
+
+        first.set_request("GET", Some("report.pdf"));
+        first.add_bytes(3000);
+
+        let sessions = registry.list();
+
+        assert_eq!(sessions.iter().map(|session| session.id).collect::<Vec<u64>>(), vec![1, 2]);
+        assert_eq!(sessions[0].verb.as_deref(), Some("GET"));
+        assert_eq!(sessions[0].file.as_deref(), Some("report.pdf"));
+        assert_eq!(sessions[0].bytes_transferred, 3000);
+        assert_eq!(sessions[1].verb, None);
+
+        // Ended sessions leave the registry, their ids are not given again
+        drop(second);
+
+        assert_eq!(registry.len(), 1);
+        assert_eq!(registry.register(PEER, Role::Full).id(), 3);
+        assert!(registry.get(2).is_none());
+
+    }
+
+    #[test]
+    pub fn test_session_registry_2(){
+
+        let registry = Arc::new(SessionRegistry::new());
+        let session = registry.register(PEER, Role::Full);
+
+        assert!(!session.is_aborted());
+        assert!(registry.get(session.id()).unwrap().abort("kicked"));
+
+        // The first reason is kept
+        registry.abort_all("shutting down");
+
+        assert_eq!(session.abort_reason(), Some("kicked"));
+
+        session.set_request("CREATE", Some("upload.bin"));
+        session.add_bytes(1000);
+
+        let info = session.info(Instant::now() + Duration::from_secs(2));
+
+        assert!(info.bytes_per_sec > 0 && info.bytes_per_sec <= 500);
+
+    }
+}
@@ -0,0 +1,324 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::io::{ErrorKind, Result};
+use std::net::Ipv4Addr;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use serde::{Deserialize, Serialize};
+use crate::constants::{SHARE_READ, SHARE_WRITE};
+use crate::serialization::{format_ipv4, load, save};
+
+type Grants = HashMap<PathBuf, Vec<Grant>>;
+
+/// Access granted on a file, write access including read access.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+impl Access {
+
+    /// Parses an access level, case-insensitively.
+    ///
+    pub fn parse(access: &str) -> Option<Self> {
+        match access.to_uppercase().as_str() {
+            SHARE_READ => Some(Access::Read),
+            SHARE_WRITE => Some(Access::Write),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Access {
+    fn fmt(&self, formatter: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Access::Read => formatter.write_str(SHARE_READ),
+            Access::Write => formatter.write_str(SHARE_WRITE),
+        }
+    }
+}
+
+/// Client, or group of clients defined in the server configuration, a file is shared with.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Grantee {
+    User(Ipv4Addr),
+    Group(String),
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Grant {
+    pub grantee: Grantee,
+    pub access: Access,
+}
+
+/// File shared with a client by its owner.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SharedFile {
+    pub path: PathBuf,
+    pub access: Access,
+}
+
+/// Access rules of the stored files.
+/// Files are private to the client owning the directory they are stored in,
+/// unless the owner grants read or write access to other clients or groups.
+/// When loaded from a file, the grants are saved back to it on every change.
+#[derive(Debug, Default)]
+pub struct ShareRegistry {
+    grants: RwLock<Grants>,
+    groups: HashMap<String, HashSet<Ipv4Addr>>,
+    file_path: Option<PathBuf>,
+}
+
+impl ShareRegistry {
+
+    /// Creates a registry without any grant, which is not persisted.
+    ///
+    pub fn new(groups: HashMap<String, HashSet<Ipv4Addr>>) -> Self {
+        Self {
+            grants: RwLock::new(HashMap::new()),
+            groups,
+            file_path: None,
+        }
+    }
+
+    /// Loads the grants from a json file, starting without any grant if it does not exist yet.
+    ///
+    pub fn load<P: AsRef<Path>>(file_path: P, groups: HashMap<String, HashSet<Ipv4Addr>>) -> Result<Self> {
+
+        let grants = match load::<Grants, &Path>(file_path.as_ref()) {
+            Ok(grants) => grants,
+            Err(error) if error.kind() == ErrorKind::NotFound => HashMap::new(),
+            Err(error) => return Err(error),
+        };
+
+        Ok(Self {
+            grants: RwLock::new(grants),
+            groups,
+            file_path: Some(file_path.as_ref().to_path_buf()),
+        })
+    }
+
+    /// Parses a grantee, either an ipv4 address or the name of a configured group.
+    ///
+    pub fn parse_grantee(&self, grantee: &str) -> Option<Grantee> {
+
+        if let Ok(ip) = grantee.parse::<Ipv4Addr>() {
+            return Some(Grantee::User(ip));
+        }
+
+        self.groups.contains_key(grantee).then(|| Grantee::Group(grantee.to_string()))
+    }
+
+    /// Checks if a file is stored in the directory of the given client.
+    ///
+    pub fn is_owner(path: &Path, client_ip: Ipv4Addr) -> bool {
+        path.parent() == Some(Path::new(&format_ipv4(client_ip)))
+    }
+
+    /// Returns the access of a client to a file, owners having write access to their own files.
+    ///
+    pub fn access(&self, path: &Path, client_ip: Ipv4Addr) -> Option<Access> {
+
+        if Self::is_owner(path, client_ip) {
+            return Some(Access::Write);
+        }
+
+        self.granted_access(&self.grants.read().unwrap(), path, client_ip)
+    }
+
+    pub fn can_read(&self, path: &Path, client_ip: Ipv4Addr) -> bool {
+        self.access(path, client_ip).is_some()
+    }
+
+    pub fn can_write(&self, path: &Path, client_ip: Ipv4Addr) -> bool {
+        self.access(path, client_ip) == Some(Access::Write)
+    }
+
+    /// Grants access to a file, replacing the access previously granted to the same grantee.
+    ///
+    pub fn share(&self, path: &Path, grantee: Grantee, access: Access) -> Result<()> {
+
+        let mut grants = self.grants.write().unwrap();
+        let file_grants = grants.entry(path.to_path_buf()).or_default();
+
+        file_grants.retain(|grant| grant.grantee != grantee);
+        file_grants.push(Grant { grantee, access });
+
+        self.save(&grants)
+    }
+
+    /// Revokes the access granted to a grantee.
+    /// Returns false if the file was not shared with it.
+    pub fn unshare(&self, path: &Path, grantee: &Grantee) -> Result<bool> {
+
+        let mut grants = self.grants.write().unwrap();
+
+        let file_grants = match grants.get_mut(path) {
+            Some(file_grants) => file_grants,
+            None => return Ok(false),
+        };
+
+        let grants_count = file_grants.len();
+        file_grants.retain(|grant| grant.grantee != *grantee);
+
+        if file_grants.len() == grants_count {
+            return Ok(false);
+        }
+
+        if file_grants.is_empty() {
+            grants.remove(path);
+        }
+
+        self.save(&grants)?;
+        Ok(true)
+    }
+
+    /// Revokes every grant of a file, once it is deleted.
+    ///
+    pub fn remove_file(&self, path: &Path) -> Result<()> {
+
+        let mut grants = self.grants.write().unwrap();
+
+        match grants.remove(path) {
+            Some(_) => self.save(&grants),
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the files of other owners the client has access to, sorted by path.
+    ///
+    pub fn shared_with(&self, client_ip: Ipv4Addr) -> Vec<SharedFile> {
+
+        let grants = self.grants.read().unwrap();
+
+        let mut files = grants.keys()
+            .filter(|path| !Self::is_owner(path, client_ip))
+            .filter_map(|path| Some(SharedFile {
+                path: path.clone(),
+                access: self.granted_access(&grants, path, client_ip)?,
+            }))
+            .collect::<Vec<SharedFile>>();
+
+        files.sort_by(|first, second| first.path.cmp(&second.path));
+
+        files
+    }
+
+    /// Returns the strongest access granted to the client, directly or through its groups.
+    ///
+    fn granted_access(&self, grants: &Grants, path: &Path, client_ip: Ipv4Addr) -> Option<Access> {
+        grants.get(path)?
+            .iter()
+            .filter(|grant| self.is_grantee(&grant.grantee, client_ip))
+            .map(|grant| grant.access)
+            .max()
+    }
+
+    fn is_grantee(&self, grantee: &Grantee, client_ip: Ipv4Addr) -> bool {
+        match grantee {
+            Grantee::User(ip) => *ip == client_ip,
+            Grantee::Group(group) => self.groups.get(group).is_some_and(|members| members.contains(&client_ip)),
+        }
+    }
+
+    fn save(&self, grants: &Grants) -> Result<()> {
+        match &self.file_path {
+            Some(file_path) => save(grants, file_path),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use super::*;
+
+    const OWNER: Ipv4Addr = Ipv4Addr::new(127, 0, 0, 1);
+    const FRIEND: Ipv4Addr = Ipv4Addr::new(127, 0, 0, 2);
+    const STRANGER: Ipv4Addr = Ipv4Addr::new(127, 0, 0, 3);
+
+    fn groups() -> HashMap<String, HashSet<Ipv4Addr>> {
+        HashMap::from([("team".to_string(), HashSet::from([FRIEND, STRANGER]))])
+    }
+
+    #[test]
+    pub fn test_share_registry_1(){
+
+        let registry = ShareRegistry::new(groups());
+        let path = Path::new("127-0-0-1/file.txt");
+
+        // Files are private by default
+        assert_eq!(registry.access(path, OWNER), Some(Access::Write));
+        assert!(!registry.can_read(path, FRIEND));
+
+        registry.share(path, Grantee::User(FRIEND), Access::Read).unwrap();
+
+        assert!(registry.can_read(path, FRIEND));
+        assert!(!registry.can_write(path, FRIEND));
+        assert!(!registry.can_read(path, STRANGER));
+
+        // Sharing again replaces the access
+        registry.share(path, Grantee::User(FRIEND), Access::Write).unwrap();
+        assert!(registry.can_write(path, FRIEND));
+
+        assert!(registry.unshare(path, &Grantee::User(FRIEND)).unwrap());
+        assert!(!registry.unshare(path, &Grantee::User(FRIEND)).unwrap());
+        assert!(!registry.can_read(path, FRIEND));
+
+    }
+
+    #[test]
+    pub fn test_share_registry_2(){
+
+        let registry = ShareRegistry::new(groups());
+        let path = Path::new("127-0-0-1/file.txt");
+
+        registry.share(path, Grantee::Group("team".to_string()), Access::Read).unwrap();
+        registry.share(path, Grantee::User(STRANGER), Access::Write).unwrap();
+        registry.share(Path::new("127-0-0-2/other.txt"), Grantee::User(OWNER), Access::Read).unwrap();
+
+        // The strongest access among the user and group grants wins
+        assert_eq!(registry.access(path, FRIEND), Some(Access::Read));
+        assert_eq!(registry.access(path, STRANGER), Some(Access::Write));
+
+        let shared_files = registry.shared_with(STRANGER);
+
+        assert_eq!(shared_files, vec![SharedFile { path: path.to_path_buf(), access: Access::Write }]);
+        assert_eq!(registry.shared_with(OWNER).len(), 1);
+
+        registry.remove_file(path).unwrap();
+        assert!(registry.shared_with(FRIEND).is_empty());
+
+    }
+
+    #[test]
+    pub fn test_share_registry_3(){
+
+        let file_path = PathBuf::from("./tests/shares/test_shares.json");
+        let _ = fs::remove_file(&file_path);
+
+        let registry = ShareRegistry::load(&file_path, groups()).unwrap();
+        registry.share(Path::new("127-0-0-1/file.txt"), Grantee::Group("team".to_string()), Access::Write).unwrap();
+
+        let registry = ShareRegistry::load(&file_path, groups()).unwrap();
+        assert!(registry.can_write(Path::new("127-0-0-1/file.txt"), FRIEND));
+
+        fs::remove_dir_all("./tests/shares").unwrap();
+
+    }
+
+    #[test]
+    pub fn test_parse_grantee_1(){
+
+        let registry = ShareRegistry::new(groups());
+
+        assert_eq!(registry.parse_grantee("127.0.0.2"), Some(Grantee::User(FRIEND)));
+        assert_eq!(registry.parse_grantee("team"), Some(Grantee::Group("team".to_string())));
+        assert_eq!(registry.parse_grantee("unknown"), None);
+        assert_eq!(Access::parse("write"), Some(Access::Write));
+        assert_eq!(Access::parse("execute"), None);
+
+    }
+}
@@ -0,0 +1,696 @@
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind, Read, Result, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use crate::constants::{BLOBS_DIR, KEYS_DIR, TRASH_DIR, VERSIONS_DIR};
+use crate::storage::storage_backend::{FileStat, StagedWrite, StorageBackend, WriteMode};
+
+/// First line of every pointer file, used to tell pointers apart from plain files.
+const POINTER_HEADER: &str = "blob-pointer-v1";
+
+/// Pointers are tiny, larger files are never read as pointers.
+const POINTER_MAX_SIZE: u64 = 256;
+
+/// Directory of the blob store receiving the uploads whose hash is not known yet.
+const INCOMING_DIR: &str = "incoming";
+
+/// File of the keys directory holding the secret key signing the pointers.
+const POINTER_KEY_FILE: &str = "pointer.key";
+
+/// Length in bytes of the pointer key.
+const POINTER_KEY_LENGTH: usize = 32;
+
+/// Directories holding pointers, the storage root standing for every file not kept by the server itself.
+const POINTER_DIRS: [&str; 3] = ["", VERSIONS_DIR, TRASH_DIR];
+
+/// Counter used to give each incoming upload its own file.
+static INCOMING_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+type BlobReferences = Arc<Mutex<HashMap<String, u64>>>;
+
+/// Content hash and size of the blob a pointer refers to.
+/// Pointers are signed with the key of the storage, so that a plain file cannot pass for a pointer.
+#[derive(Debug, Clone, PartialEq)]
+struct BlobPointer {
+    hash: String,
+    size: u64,
+}
+
+impl BlobPointer {
+
+    fn encode(&self, key: &[u8]) -> String {
+        format!("{}\n{}\n{}\n{}\n", POINTER_HEADER, self.hash, self.size, to_hex(&self.signature(key)))
+    }
+
+    /// Decodes a pointer, or returns None if the content is not a pointer signed with the given key.
+    ///
+    fn decode(content: &[u8], key: &[u8]) -> Option<Self> {
+
+        let mut lines = std::str::from_utf8(content).ok()?.lines();
+
+        if lines.next()? != POINTER_HEADER {
+            return None;
+        }
+
+        let pointer = Self::parse(lines.next()?, lines.next()?)?;
+        let signature = from_hex(lines.next()?)?;
+
+        let mut mac = Self::mac(key);
+        mac.update(pointer.signed_content().as_bytes());
+        mac.verify_slice(&signature).ok()?;
+
+        Some(pointer)
+    }
+
+    fn parse(hash: &str, size: &str) -> Option<Self> {
+
+        let size = size.parse::<u64>().ok()?;
+
+        if hash.len() != 64 || !hash.chars().all(|character| character.is_ascii_hexdigit()) {
+            return None;
+        }
+
+        Some(Self {
+            hash: hash.to_string(),
+            size,
+        })
+    }
+
+    fn signature(&self, key: &[u8]) -> Vec<u8> {
+        let mut mac = Self::mac(key);
+        mac.update(self.signed_content().as_bytes());
+        mac.finalize().into_bytes().to_vec()
+    }
+
+    fn signed_content(&self) -> String {
+        format!("{}\n{}", self.hash, self.size)
+    }
+
+    fn mac(key: &[u8]) -> Hmac<Sha256> {
+        Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length")
+    }
+}
+
+/// Storage that keeps each distinct content only once.
+/// Contents are stored in a blob store addressed by their SHA-256 hash, and every file is a small pointer to its blob.
+/// Blobs are reference counted and released once the last pointer to them is deleted or overwritten.
+/// Files that are not pointers, such as the ones stored before deduplication was enabled, are served as they are.
+/// A file is only taken for a pointer if it is signed with the key of the storage and its blob is stored with the same size.
+#[derive(Debug, Clone)]
+pub struct DedupBackend {
+    inner: Arc<dyn StorageBackend>,
+    references: BlobReferences,
+    key: Arc<Vec<u8>>,
+}
+
+impl DedupBackend {
+
+    /// Counts the references of every blob from the pointers found in the storage.
+    /// Blobs no longer referenced and uploads left unfinished by a previous run are removed.
+    /// The first start creates the pointer key.
+    pub fn new(inner: Arc<dyn StorageBackend>) -> Result<Self> {
+
+        let key_path = Path::new(KEYS_DIR).join(POINTER_KEY_FILE);
+
+        let key = match read_key(inner.as_ref(), &key_path)? {
+            Some(key) => key,
+            None => create_key(inner.as_ref(), &key_path)?,
+        };
+
+        let mut references = HashMap::new();
+
+        for dir in POINTER_DIRS {
+            for file_stat in inner.list(Path::new(dir))? {
+                if let Some(pointer) = read_pointer(inner.as_ref(), &key, &file_stat)? {
+                    *references.entry(pointer.hash).or_insert(0) += 1;
+                }
+            }
+        }
+
+        for file_stat in inner.list(Path::new(BLOBS_DIR))? {
+
+            let referenced = file_stat.path.file_name()
+                .and_then(|file_name| file_name.to_str())
+                .is_some_and(|hash| references.contains_key(hash));
+
+            if !referenced || file_stat.path.starts_with(Path::new(BLOBS_DIR).join(INCOMING_DIR)) {
+                inner.delete(&file_stat.path)?;
+            }
+        }
+
+        Ok(Self {
+            inner,
+            references: Arc::new(Mutex::new(references)),
+            key: Arc::new(key),
+        })
+    }
+
+    /// Returns the number of pointers referring to the blob with the given hash.
+    ///
+    pub fn reference_count(&self, hash: &str) -> u64 {
+        self.references.lock().unwrap().get(hash).copied().unwrap_or(0)
+    }
+
+    /// Returns the pointer stored at the given path, or None if the file is a plain file.
+    ///
+    fn pointer_of(&self, path: &Path) -> Result<Option<BlobPointer>> {
+        let file_stat = self.inner.stat(path)?;
+        read_pointer(self.inner.as_ref(), &self.key, &file_stat)
+    }
+
+    /// Replaces the size of a pointer by the size of its content.
+    ///
+    fn resolve(&self, mut file_stat: FileStat) -> Result<FileStat> {
+
+        if let Some(pointer) = read_pointer(self.inner.as_ref(), &self.key, &file_stat)? {
+            file_stat.size = pointer.size;
+        }
+
+        Ok(file_stat)
+    }
+}
+
+impl StorageBackend for DedupBackend {
+
+    fn open_read(&self, path: &Path) -> Result<Box<dyn Read + Send>> {
+
+        match self.pointer_of(path)? {
+            Some(pointer) => self.inner.open_read(&blob_path(&pointer.hash)),
+            None => self.inner.open_read(path),
+        }
+    }
+
+    fn open_write(&self, path: &Path, mode: WriteMode) -> Result<Box<dyn StagedWrite>> {
+
+        let exists = match self.inner.stat(path) {
+            Ok(_) => true,
+            Err(error) if error.kind() == ErrorKind::NotFound => false,
+            Err(error) => return Err(error),
+        };
+
+        match mode {
+
+            WriteMode::Create if exists => {
+                let error = Error::new(ErrorKind::AlreadyExists, format!("{:?} already exists", path));
+                return Err(error);
+            }
+
+            WriteMode::Overwrite if !exists => {
+                let error = Error::new(ErrorKind::NotFound, format!("{:?} not found", path));
+                return Err(error);
+            }
+
+            _ => (),
+        }
+
+        let id = INCOMING_COUNTER.fetch_add(1, Ordering::Relaxed);
+        let incoming_path = Path::new(BLOBS_DIR).join(INCOMING_DIR).join(format!("{}-{}", process::id(), id));
+
+        let incoming_write = self.inner.open_write(&incoming_path, WriteMode::Create)?;
+
+        Ok(Box::new(DedupStagedWrite {
+            inner: Arc::clone(&self.inner),
+            references: Arc::clone(&self.references),
+            key: Arc::clone(&self.key),
+            path: path.to_path_buf(),
+            mode,
+            incoming_path,
+            incoming_write: Some(incoming_write),
+            hasher: Sha256::new(),
+            size: 0,
+        }))
+    }
+
+    fn delete(&self, path: &Path) -> Result<()> {
+
+        let pointer = self.pointer_of(path)?;
+
+        self.inner.delete(path)?;
+
+        match pointer {
+            Some(pointer) => release(self.inner.as_ref(), &self.references, &pointer.hash),
+            None => Ok(()),
+        }
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+
+        let replaced_pointer = match self.inner.stat(to) {
+            Ok(file_stat) => read_pointer(self.inner.as_ref(), &self.key, &file_stat)?,
+            Err(_) => None,
+        };
+
+        // Moving a pointer keeps its blob referenced, only a replaced pointer releases one
+        self.inner.rename(from, to)?;
+
+        match replaced_pointer {
+            Some(pointer) => release(self.inner.as_ref(), &self.references, &pointer.hash),
+            None => Ok(()),
+        }
+    }
+
+    fn list(&self, dir: &Path) -> Result<Vec<FileStat>> {
+
+        let mut files = Vec::new();
+
+        for file_stat in self.inner.list(dir)? {
+            match self.resolve(file_stat) {
+                Ok(file_stat) => files.push(file_stat),
+                Err(error) if error.kind() == ErrorKind::NotFound => continue,
+                Err(error) => return Err(error),
+            }
+        }
+
+        Ok(files)
+    }
+
+    fn stat(&self, path: &Path) -> Result<FileStat> {
+        self.resolve(self.inner.stat(path)?)
+    }
+
+    fn local_root(&self) -> Option<&Path> {
+        self.inner.local_root()
+    }
+}
+
+/// Staged write that hashes the content while it is uploaded to the incoming directory of the blob store.
+/// On commit the upload becomes the blob of its hash, or is dropped if that blob already exists,
+/// and the pointer is written in place of the file.
+struct DedupStagedWrite {
+    inner: Arc<dyn StorageBackend>,
+    references: BlobReferences,
+    key: Arc<Vec<u8>>,
+    path: PathBuf,
+    mode: WriteMode,
+    incoming_path: PathBuf,
+    incoming_write: Option<Box<dyn StagedWrite>>,
+    hasher: Sha256,
+    size: u64,
+}
+
+impl DedupStagedWrite {
+
+    /// Moves the incoming upload to the blob store, or discards it if the blob is already stored,
+    /// and takes a reference to the blob.
+    fn store_blob(&self, hash: &str) -> Result<()> {
+
+        let mut references = self.references.lock().unwrap();
+
+        match references.get_mut(hash) {
+            Some(count) => {
+                self.inner.delete(&self.incoming_path)?;
+                *count += 1;
+            }
+            None => {
+                if let Err(error) = self.inner.rename(&self.incoming_path, &blob_path(hash)) {
+                    let _ = self.inner.delete(&self.incoming_path);
+                    return Err(error);
+                }
+                references.insert(hash.to_string(), 1);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Writes the pointer in place of the file.
+    ///
+    fn write_pointer(&self, pointer: &BlobPointer) -> Result<()> {
+        write_pointer(self.inner.as_ref(), &self.key, &self.path, self.mode, pointer)
+    }
+}
+
+impl Write for DedupStagedWrite {
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+
+        let bytes_written = match &mut self.incoming_write {
+            Some(incoming_write) => incoming_write.write(buf)?,
+            None => return Err(Error::new(ErrorKind::BrokenPipe, "The write was already committed")),
+        };
+
+        self.hasher.update(&buf[..bytes_written]);
+        self.size += bytes_written as u64;
+
+        Ok(bytes_written)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        match &mut self.incoming_write {
+            Some(incoming_write) => incoming_write.flush(),
+            None => Ok(()),
+        }
+    }
+}
+
+impl StagedWrite for DedupStagedWrite {
+
+    fn commit(mut self: Box<Self>) -> Result<()> {
+
+        if let Some(incoming_write) = self.incoming_write.take() {
+            incoming_write.commit()?;
+        }
+
+        let pointer = BlobPointer {
+            hash: format!("{:x}", self.hasher.clone().finalize()),
+            size: self.size,
+        };
+
+        let replaced_pointer = match self.mode {
+            WriteMode::Overwrite => self.inner.stat(&self.path)
+                .and_then(|file_stat| read_pointer(self.inner.as_ref(), &self.key, &file_stat))
+                .unwrap_or(None),
+            WriteMode::Create => None,
+        };
+
+        self.store_blob(&pointer.hash)?;
+
+        if let Err(error) = self.write_pointer(&pointer) {
+            release(self.inner.as_ref(), &self.references, &pointer.hash)?;
+            return Err(error);
+        }
+
+        match replaced_pointer {
+            Some(replaced_pointer) => release(self.inner.as_ref(), &self.references, &replaced_pointer.hash),
+            None => Ok(()),
+        }
+    }
+
+    fn abort(mut self: Box<Self>) -> Result<()> {
+        match self.incoming_write.take() {
+            Some(incoming_write) => incoming_write.abort(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Returns the path of the blob with the given hash, spread over subdirectories named after the first hash digits.
+///
+fn blob_path(hash: &str) -> PathBuf {
+    Path::new(BLOBS_DIR).join(&hash[..2]).join(hash)
+}
+
+/// Reads the pointer stored in a file, or returns None if the file is a plain file.
+/// A signed pointer whose blob is missing or has another size is also read as a plain file.
+fn read_pointer(storage: &dyn StorageBackend, key: &[u8], file_stat: &FileStat) -> Result<Option<BlobPointer>> {
+
+    let pointer = match read_small_file(storage, file_stat)? {
+        Some(content) => BlobPointer::decode(&content, key),
+        None => None,
+    };
+
+    match pointer {
+        Some(pointer) if is_blob_stored(storage, &pointer)? => Ok(Some(pointer)),
+        _ => Ok(None),
+    }
+}
+
+/// Reads the whole content of a file small enough to be a pointer.
+///
+fn read_small_file(storage: &dyn StorageBackend, file_stat: &FileStat) -> Result<Option<Vec<u8>>> {
+
+    if file_stat.size > POINTER_MAX_SIZE {
+        return Ok(None);
+    }
+
+    let mut content = Vec::new();
+    storage.open_read(&file_stat.path)?.take(POINTER_MAX_SIZE).read_to_end(&mut content)?;
+
+    Ok(Some(content))
+}
+
+/// Checks that the blob of a pointer is stored with the size the pointer gives.
+///
+fn is_blob_stored(storage: &dyn StorageBackend, pointer: &BlobPointer) -> Result<bool> {
+    match storage.stat(&blob_path(&pointer.hash)) {
+        Ok(blob_stat) => Ok(blob_stat.size == pointer.size),
+        Err(error) if error.kind() == ErrorKind::NotFound => Ok(false),
+        Err(error) => Err(error),
+    }
+}
+
+/// Writes a signed pointer at the given path.
+///
+fn write_pointer(storage: &dyn StorageBackend, key: &[u8], path: &Path, mode: WriteMode, pointer: &BlobPointer) -> Result<()> {
+    let mut pointer_write = storage.open_write(path, mode)?;
+    pointer_write.write_all(pointer.encode(key).as_bytes())?;
+    pointer_write.commit()
+}
+
+/// Reads the pointer key, or returns None if it was not created yet.
+///
+fn read_key(storage: &dyn StorageBackend, key_path: &Path) -> Result<Option<Vec<u8>>> {
+
+    let mut key = Vec::new();
+
+    match storage.open_read(key_path) {
+        Ok(mut file) => file.read_to_end(&mut key)?,
+        Err(error) if error.kind() == ErrorKind::NotFound => return Ok(None),
+        Err(error) => return Err(error),
+    };
+
+    if key.len() != POINTER_KEY_LENGTH {
+        return Err(Error::new(ErrorKind::InvalidData, format!("Invalid pointer key {:?}", key_path)));
+    }
+
+    Ok(Some(key))
+}
+
+/// Creates a random pointer key and stores it in the keys directory.
+///
+fn create_key(storage: &dyn StorageBackend, key_path: &Path) -> Result<Vec<u8>> {
+
+    let mut key = vec![0; POINTER_KEY_LENGTH];
+    getrandom::getrandom(&mut key).map_err(|error| Error::other(error.to_string()))?;
+
+    let mut key_write = storage.open_write(key_path, WriteMode::Create)?;
+    key_write.write_all(&key)?;
+    key_write.commit()?;
+
+    Ok(key)
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(hex: &str) -> Option<Vec<u8>> {
+
+    if !hex.len().is_multiple_of(2) || !hex.is_ascii() {
+        return None;
+    }
+
+    (0..hex.len()).step_by(2)
+        .map(|index| u8::from_str_radix(&hex[index..index + 2], 16).ok())
+        .collect()
+}
+
+/// Drops a reference to a blob, deleting the blob once it is no longer referenced.
+///
+fn release(storage: &dyn StorageBackend, references: &BlobReferences, hash: &str) -> Result<()> {
+
+    let mut references = references.lock().unwrap();
+
+    let count = match references.get_mut(hash) {
+        Some(count) => count,
+        None => return Ok(()),
+    };
+
+    *count -= 1;
+
+    if *count == 0 {
+        references.remove(hash);
+        storage.delete(&blob_path(hash))?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::memory_backend::MemoryBackend;
+    use super::*;
+
+    const HELLO_HASH: &str = "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824";
+
+    fn write(backend: &DedupBackend, path: &str, mode: WriteMode, content: &[u8]) -> Result<()> {
+        let mut staged_write = backend.open_write(Path::new(path), mode)?;
+        staged_write.write_all(content)?;
+        staged_write.commit()
+    }
+
+    fn read(backend: &DedupBackend, path: &str) -> Vec<u8> {
+        let mut content = Vec::new();
+        backend.open_read(Path::new(path)).unwrap().read_to_end(&mut content).unwrap();
+        content
+    }
+
+    #[test]
+    pub fn test_dedup_backend_1(){
+
+        let inner = Arc::new(MemoryBackend::new());
+        let backend = DedupBackend::new(inner.clone()).unwrap();
+
+        write(&backend, "127-0-0-1/hello.txt", WriteMode::Create, b"hello").unwrap();
+        write(&backend, "127-0-0-2/copy.txt", WriteMode::Create, b"hello").unwrap();
+
+        // The content is stored once and both files point to it
+        assert_eq!(backend.reference_count(HELLO_HASH), 2);
+        assert_eq!(inner.list(Path::new(BLOBS_DIR)).unwrap().len(), 1);
+        assert_eq!(read(&backend, "127-0-0-2/copy.txt"), b"hello");
+
+        let files = backend.list(Path::new("")).unwrap();
+
+        assert_eq!(files.len(), 2);
+        assert!(files.iter().all(|file| file.size == 5));
+        assert_eq!(backend.stat(Path::new("127-0-0-1/hello.txt")).unwrap().size, 5);
+        assert_eq!(backend.list(Path::new("127-0-0-1")).unwrap().len(), 1);
+
+    }
+
+    #[test]
+    pub fn test_dedup_backend_2(){
+
+        let inner = Arc::new(MemoryBackend::new());
+        let backend = DedupBackend::new(inner.clone()).unwrap();
+
+        write(&backend, "127-0-0-1/hello.txt", WriteMode::Create, b"hello").unwrap();
+        write(&backend, "127-0-0-2/hello.txt", WriteMode::Create, b"hello").unwrap();
+
+        backend.delete(Path::new("127-0-0-1/hello.txt")).unwrap();
+        assert_eq!(backend.reference_count(HELLO_HASH), 1);
+        assert_eq!(read(&backend, "127-0-0-2/hello.txt"), b"hello");
+
+        // The blob is released with its last reference
+        backend.delete(Path::new("127-0-0-2/hello.txt")).unwrap();
+        assert_eq!(backend.reference_count(HELLO_HASH), 0);
+        assert!(inner.list(Path::new(BLOBS_DIR)).unwrap().is_empty());
+        assert_eq!(backend.delete(Path::new("127-0-0-2/hello.txt")).unwrap_err().kind(), ErrorKind::NotFound);
+
+    }
+
+    #[test]
+    pub fn test_dedup_backend_3(){
+
+        let inner = Arc::new(MemoryBackend::new());
+        let backend = DedupBackend::new(inner.clone()).unwrap();
+
+        write(&backend, "127-0-0-1/file.txt", WriteMode::Create, b"hello").unwrap();
+
+        // A failed create gives back the reference it took
+        let result = write(&backend, "127-0-0-1/file.txt", WriteMode::Create, b"hello");
+        assert_eq!(result.unwrap_err().kind(), ErrorKind::AlreadyExists);
+        assert_eq!(backend.reference_count(HELLO_HASH), 1);
+
+        // Overwriting releases the previous content
+        write(&backend, "127-0-0-1/file.txt", WriteMode::Overwrite, b"updated").unwrap();
+        assert_eq!(backend.reference_count(HELLO_HASH), 0);
+        assert_eq!(read(&backend, "127-0-0-1/file.txt"), b"updated");
+        assert_eq!(inner.list(Path::new(BLOBS_DIR)).unwrap().len(), 1);
+
+        let mut staged_write = backend.open_write(Path::new("127-0-0-1/other.txt"), WriteMode::Create).unwrap();
+        staged_write.write_all(b"aborted").unwrap();
+        staged_write.abort().unwrap();
+
+        assert!(backend.stat(Path::new("127-0-0-1/other.txt")).is_err());
+        assert_eq!(inner.list(Path::new(BLOBS_DIR)).unwrap().len(), 1);
+
+    }
+
+    #[test]
+    pub fn test_dedup_backend_4(){
+
+        let inner = Arc::new(MemoryBackend::new());
+        let backend = DedupBackend::new(inner.clone()).unwrap();
+
+        write(&backend, "127-0-0-1/hello.txt", WriteMode::Create, b"hello").unwrap();
+        write(&backend, "127-0-0-2/hello.txt", WriteMode::Create, b"hello").unwrap();
+
+        // Left over by an interrupted run
+        inner.insert(blob_path(&"0".repeat(64)), b"orphan").unwrap();
+        inner.insert(Path::new(BLOBS_DIR).join(INCOMING_DIR).join("1"), b"partial").unwrap();
+
+        // Stored before deduplication was enabled
+        inner.insert("127-0-0-3/plain.txt", b"plain").unwrap();
+
+        let backend = DedupBackend::new(inner.clone()).unwrap();
+
+        assert_eq!(backend.reference_count(HELLO_HASH), 2);
+        assert_eq!(inner.list(Path::new(BLOBS_DIR)).unwrap().len(), 1);
+        assert_eq!(read(&backend, "127-0-0-3/plain.txt"), b"plain");
+
+        backend.delete(Path::new("127-0-0-3/plain.txt")).unwrap();
+        assert_eq!(backend.list(Path::new("")).unwrap().len(), 2);
+
+    }
+
+    #[test]
+    pub fn test_dedup_backend_5(){
+
+        let inner = Arc::new(MemoryBackend::new());
+        let backend = DedupBackend::new(inner.clone()).unwrap();
+
+        write(&backend, "127-0-0-1/hello.txt", WriteMode::Create, b"hello").unwrap();
+        backend.rename(Path::new("127-0-0-1/hello.txt"), &Path::new(VERSIONS_DIR).join("127-0-0-1/1-0-hello.txt")).unwrap();
+
+        // Pointers kept in the history still hold their blob
+        let backend = DedupBackend::new(inner.clone()).unwrap();
+
+        assert_eq!(backend.reference_count(HELLO_HASH), 1);
+        assert_eq!(read(&backend, ".versions/127-0-0-1/1-0-hello.txt"), b"hello");
+
+    }
+
+    #[test]
+    pub fn test_dedup_backend_6(){
+
+        let inner = Arc::new(MemoryBackend::new());
+        let backend = DedupBackend::new(inner.clone()).unwrap();
+
+        write(&backend, "127-0-0-1/hello.txt", WriteMode::Create, b"hello").unwrap();
+
+        // Plain files formatted as pointers to a stored blob, unsigned or signed with another key
+        let forged = BlobPointer { hash: HELLO_HASH.to_string(), size: 5 };
+        inner.insert("127-0-0-2/unsigned.txt", format!("{}\n{}\n5\n", POINTER_HEADER, HELLO_HASH).as_bytes()).unwrap();
+        inner.insert("127-0-0-2/forged.txt", forged.encode(&[0; POINTER_KEY_LENGTH]).as_bytes()).unwrap();
+
+        let backend = DedupBackend::new(inner.clone()).unwrap();
+
+        // They are served as they are and do not hold the blob of another client
+        assert_eq!(backend.reference_count(HELLO_HASH), 1);
+        assert!(read(&backend, "127-0-0-2/forged.txt").starts_with(POINTER_HEADER.as_bytes()));
+
+        backend.delete(Path::new("127-0-0-2/unsigned.txt")).unwrap();
+        backend.delete(Path::new("127-0-0-2/forged.txt")).unwrap();
+
+        assert_eq!(backend.reference_count(HELLO_HASH), 1);
+        assert_eq!(read(&backend, "127-0-0-1/hello.txt"), b"hello");
+
+    }
+
+    #[test]
+    pub fn test_blob_pointer_1(){
+
+        let key = [7; POINTER_KEY_LENGTH];
+
+        let pointer = BlobPointer {
+            hash: HELLO_HASH.to_string(),
+            size: 5,
+        };
+
+        assert_eq!(BlobPointer::decode(pointer.encode(&key).as_bytes(), &key), Some(pointer.clone()));
+        assert_eq!(BlobPointer::decode(pointer.encode(&key).as_bytes(), &[8; POINTER_KEY_LENGTH]), None);
+        assert_eq!(BlobPointer::decode(b"hello", &key), None);
+        assert_eq!(BlobPointer::decode(format!("{}\nnot-a-hash\n5\n", POINTER_HEADER).as_bytes(), &key), None);
+
+        // The signature covers the size
+        let tampered = pointer.encode(&key).replacen("\n5\n", "\n6\n", 1);
+        assert_eq!(BlobPointer::decode(tampered.as_bytes(), &key), None);
+        assert!((pointer.encode(&key).len() as u64) < POINTER_MAX_SIZE);
+
+    }
+}
@@ -0,0 +1,301 @@
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io::{Error, ErrorKind, Read, Result, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+use crate::constants::STAGING_DIR;
+use crate::directory_tree::DirectoryTree;
+use crate::mapped_file::MappedFile;
+use crate::storage::storage_backend::{is_internal_path, validate_path, FileStat, StagedWrite, StorageBackend, WriteMode};
+
+/// Counter used to give each staged write its own file.
+static STAGING_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Storage backed by a directory of the local file system.
+/// Writes go to a file in the staging directory and are moved into place on commit.
+#[derive(Debug, Clone)]
+pub struct LocalBackend {
+    tree: DirectoryTree<PathBuf>,
+}
+
+impl LocalBackend {
+
+    /// Creates the storage root if it is missing and clears the writes left unfinished by a previous run.
+    ///
+    pub fn new<P: AsRef<Path>>(root_dir: P) -> Result<Self> {
+
+        let tree = DirectoryTree::new(root_dir.as_ref().to_path_buf())?;
+
+        let staging_dir = tree.root_dir().join(STAGING_DIR);
+
+        if staging_dir.exists() {
+            fs::remove_dir_all(&staging_dir)?;
+        }
+        tree.create_dir_all(STAGING_DIR)?;
+
+        Ok(Self {
+            tree,
+        })
+    }
+
+    /// Getter for the storage root.
+    pub fn root_dir(&self) -> &Path {
+        self.tree.root_dir()
+    }
+
+    /// Maps a storage path to its location on the file system.
+    ///
+    fn full_path(&self, path: &Path) -> Result<PathBuf> {
+        validate_path(path)?;
+        Ok(self.root_dir().join(path))
+    }
+
+    /// Returns a new file path in the staging directory.
+    ///
+    fn staging_path(&self) -> PathBuf {
+        let id = STAGING_COUNTER.fetch_add(1, Ordering::Relaxed);
+        self.root_dir().join(STAGING_DIR).join(format!("{}-{}", process::id(), id))
+    }
+}
+
+impl StorageBackend for LocalBackend {
+
+    fn open_read(&self, path: &Path) -> Result<Box<dyn Read + Send>> {
+
+        let file = File::open(self.full_path(path)?)?;
+
+        Ok(Box::new(file))
+    }
+
+    fn open_write(&self, path: &Path, mode: WriteMode) -> Result<Box<dyn StagedWrite>> {
+
+        let target_path = self.full_path(path)?;
+
+        match mode {
+
+            WriteMode::Create if target_path.exists() => {
+                let error = Error::new(ErrorKind::AlreadyExists, format!("{:?} already exists", path));
+                return Err(error);
+            }
+
+            WriteMode::Overwrite if !target_path.is_file() => {
+                let error = Error::new(ErrorKind::NotFound, format!("{:?} not found", path));
+                return Err(error);
+            }
+
+            _ => (),
+        }
+
+        let staging_path = self.staging_path();
+
+        let file = OpenOptions::new()
+            .create_new(true)
+            .read(true)
+            .write(true)
+            .open(&staging_path)?;
+
+        Ok(Box::new(LocalStagedWrite {
+            mapped_file: MappedFile::new(file)?,
+            staging_path,
+            target_path,
+            mode,
+        }))
+    }
+
+    fn delete(&self, path: &Path) -> Result<()> {
+        fs::remove_file(self.full_path(path)?)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+
+        let from_path = self.full_path(from)?;
+        let to_path = self.full_path(to)?;
+
+        if let Some(parent) = to_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        fs::rename(from_path, to_path)
+    }
+
+    fn list(&self, dir: &Path) -> Result<Vec<FileStat>> {
+
+        let dir_path = match dir.as_os_str().is_empty() {
+            true => self.root_dir().to_path_buf(),
+            false => self.full_path(dir)?,
+        };
+
+        if !dir_path.is_dir() {
+            return Ok(Vec::new());
+        }
+
+        let dir_tree = DirectoryTree::new_from_existing(&dir_path)?;
+        let mut files = Vec::new();
+
+        for file_path in dir_tree.list_files_in_tree()? {
+
+            // Skip the reserved directories found below the listed one
+            if is_internal_path(file_path.strip_prefix(&dir_path).unwrap()) {
+                continue;
+            }
+
+            let metadata = match fs::metadata(&file_path) {
+                Ok(metadata) => metadata,
+                Err(error) if error.kind() == ErrorKind::NotFound => continue,
+                Err(error) => return Err(error),
+            };
+
+            let relative_path = file_path.strip_prefix(self.root_dir()).unwrap();
+            files.push(FileStat::from_metadata(relative_path, &metadata)?);
+        }
+
+        Ok(files)
+    }
+
+    fn stat(&self, path: &Path) -> Result<FileStat> {
+
+        let metadata = fs::metadata(self.full_path(path)?)?;
+
+        if !metadata.is_file() {
+            let error = Error::new(ErrorKind::NotFound, format!("{:?} is not a file", path));
+            return Err(error);
+        }
+
+        FileStat::from_metadata(path, &metadata)
+    }
+
+    fn local_root(&self) -> Option<&Path> {
+        Some(self.root_dir())
+    }
+}
+
+/// Staged write into a memory mapped file of the staging directory.
+///
+struct LocalStagedWrite {
+    mapped_file: MappedFile,
+    staging_path: PathBuf,
+    target_path: PathBuf,
+    mode: WriteMode,
+}
+
+impl Write for LocalStagedWrite {
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.mapped_file.write_append(buf)?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.mapped_file.flush()
+    }
+}
+
+impl StagedWrite for LocalStagedWrite {
+
+    fn commit(mut self: Box<Self>) -> Result<()> {
+
+        self.flush()?;
+
+        if let Some(parent) = self.target_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        match self.mode {
+            // Linking fails if the target appeared in the meantime, so an existing file is never replaced
+            WriteMode::Create => {
+                fs::hard_link(&self.staging_path, &self.target_path)?;
+                fs::remove_file(&self.staging_path)
+            }
+
+            WriteMode::Overwrite => fs::rename(&self.staging_path, &self.target_path),
+        }
+    }
+
+    fn abort(self: Box<Self>) -> Result<()> {
+        fs::remove_file(&self.staging_path)
+    }
+}
+
+/// The staged file is removed if the write was neither committed nor aborted.
+///
+impl Drop for LocalStagedWrite {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.staging_path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_to_string(backend: &LocalBackend, path: &str) -> String {
+        let mut content = String::new();
+        backend.open_read(Path::new(path)).unwrap().read_to_string(&mut content).unwrap();
+        content
+    }
+
+    #[test]
+    pub fn test_local_backend_1(){
+
+        let backend = LocalBackend::new("./tests/files2").unwrap();
+        let files = backend.list(Path::new("")).unwrap();
+
+        assert_eq!(files.len(), 5);
+        assert!(files.iter().any(|file| file.path == Path::new("files/1")));
+
+        let files = backend.list(Path::new("files")).unwrap();
+        assert_eq!(files.len(), 2);
+
+        assert!(backend.list(Path::new("missing")).unwrap().is_empty());
+
+    }
+
+    #[test]
+    pub fn test_local_backend_2(){
+
+        let backend = LocalBackend::new("./tests/files9").unwrap();
+        let path = Path::new("dir/file.txt");
+
+        let mut staged_write = backend.open_write(path, WriteMode::Create).unwrap();
+        staged_write.write_all(b"first").unwrap();
+
+        // Nothing is visible before the commit
+        assert!(backend.stat(path).is_err());
+        staged_write.commit().unwrap();
+
+        assert_eq!(backend.stat(path).unwrap().size, 5);
+        assert_eq!(read_to_string(&backend, "dir/file.txt"), "first");
+        assert_eq!(backend.open_write(path, WriteMode::Create).err().unwrap().kind(), ErrorKind::AlreadyExists);
+
+        let mut staged_write = backend.open_write(path, WriteMode::Overwrite).unwrap();
+        staged_write.write_all(b"second").unwrap();
+        staged_write.commit().unwrap();
+
+        assert_eq!(read_to_string(&backend, "dir/file.txt"), "second");
+
+        backend.rename(path, Path::new("other/renamed.txt")).unwrap();
+        assert!(backend.stat(path).is_err());
+
+        backend.delete(Path::new("other/renamed.txt")).unwrap();
+        assert!(backend.list(Path::new("")).unwrap().is_empty());
+
+    }
+
+    #[test]
+    pub fn test_local_backend_3(){
+
+        let backend = LocalBackend::new("./tests/files10").unwrap();
+        let path = Path::new("file.txt");
+
+        let mut staged_write = backend.open_write(path, WriteMode::Create).unwrap();
+        staged_write.write_all(b"aborted").unwrap();
+        staged_write.abort().unwrap();
+
+        assert!(backend.stat(path).is_err());
+        assert!(backend.list(Path::new("")).unwrap().is_empty());
+        assert_eq!(backend.open_write(path, WriteMode::Overwrite).err().unwrap().kind(), ErrorKind::NotFound);
+        assert_eq!(backend.open_read(Path::new("../files/1")).err().unwrap().kind(), ErrorKind::InvalidInput);
+
+    }
+}
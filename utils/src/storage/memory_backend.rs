@@ -0,0 +1,256 @@
+use std::collections::HashMap;
+use std::io::{Cursor, Error, ErrorKind, Read, Result, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+use crate::storage::storage_backend::{is_internal_path, validate_path, FileStat, StagedWrite, StorageBackend, WriteMode};
+
+/// Content and modification time of a file kept in memory.
+#[derive(Debug, Clone)]
+struct MemoryFile {
+    content: Arc<Vec<u8>>,
+    modified: SystemTime,
+}
+
+type MemoryFiles = Arc<RwLock<HashMap<PathBuf, MemoryFile>>>;
+
+/// Storage that keeps every file in memory, used to run the server logic without touching the disk.
+#[derive(Debug, Clone, Default)]
+pub struct MemoryBackend {
+    files: MemoryFiles,
+}
+
+impl MemoryBackend {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Stores a file directly, replacing any previous content.
+    ///
+    pub fn insert<P: AsRef<Path>>(&self, path: P, content: &[u8]) -> Result<()> {
+
+        validate_path(path.as_ref())?;
+
+        self.files.write().unwrap().insert(path.as_ref().to_path_buf(), MemoryFile {
+            content: Arc::new(content.to_vec()),
+            modified: SystemTime::now(),
+        });
+
+        Ok(())
+    }
+
+    fn not_found(path: &Path) -> Error {
+        Error::new(ErrorKind::NotFound, format!("{:?} not found", path))
+    }
+}
+
+impl StorageBackend for MemoryBackend {
+
+    fn open_read(&self, path: &Path) -> Result<Box<dyn Read + Send>> {
+
+        validate_path(path)?;
+
+        match self.files.read().unwrap().get(path) {
+            Some(file) => Ok(Box::new(Cursor::new(file.content.as_ref().clone()))),
+            None => Err(Self::not_found(path)),
+        }
+    }
+
+    fn open_write(&self, path: &Path, mode: WriteMode) -> Result<Box<dyn StagedWrite>> {
+
+        validate_path(path)?;
+
+        let exists = self.files.read().unwrap().contains_key(path);
+
+        match mode {
+
+            WriteMode::Create if exists => {
+                let error = Error::new(ErrorKind::AlreadyExists, format!("{:?} already exists", path));
+                return Err(error);
+            }
+
+            WriteMode::Overwrite if !exists => return Err(Self::not_found(path)),
+
+            _ => (),
+        }
+
+        Ok(Box::new(MemoryStagedWrite {
+            files: Arc::clone(&self.files),
+            path: path.to_path_buf(),
+            mode,
+            content: Vec::new(),
+        }))
+    }
+
+    fn delete(&self, path: &Path) -> Result<()> {
+
+        validate_path(path)?;
+
+        match self.files.write().unwrap().remove(path) {
+            Some(_) => Ok(()),
+            None => Err(Self::not_found(path)),
+        }
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+
+        validate_path(from)?;
+        validate_path(to)?;
+
+        let mut files = self.files.write().unwrap();
+
+        match files.remove(from) {
+            Some(file) => {
+                files.insert(to.to_path_buf(), file);
+                Ok(())
+            }
+            None => Err(Self::not_found(from)),
+        }
+    }
+
+    fn list(&self, dir: &Path) -> Result<Vec<FileStat>> {
+
+        if !dir.as_os_str().is_empty() {
+            validate_path(dir)?;
+        }
+
+        let files = self.files.read().unwrap();
+
+        let mut stats = files.iter()
+            .filter(|(path, _)| match path.strip_prefix(dir) {
+                Ok(relative_path) => !is_internal_path(relative_path),
+                Err(_) => false,
+            })
+            .map(|(path, file)| FileStat {
+                path: path.clone(),
+                size: file.content.len() as u64,
+                modified: file.modified,
+            })
+            .collect::<Vec<FileStat>>();
+
+        stats.sort_by(|first, second| first.path.cmp(&second.path));
+
+        Ok(stats)
+    }
+
+    fn stat(&self, path: &Path) -> Result<FileStat> {
+
+        validate_path(path)?;
+
+        match self.files.read().unwrap().get(path) {
+            Some(file) => Ok(FileStat {
+                path: path.to_path_buf(),
+                size: file.content.len() as u64,
+                modified: file.modified,
+            }),
+            None => Err(Self::not_found(path)),
+        }
+    }
+}
+
+/// Staged write buffered in memory until the commit.
+///
+struct MemoryStagedWrite {
+    files: MemoryFiles,
+    path: PathBuf,
+    mode: WriteMode,
+    content: Vec<u8>,
+}
+
+impl Write for MemoryStagedWrite {
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.content.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl StagedWrite for MemoryStagedWrite {
+
+    fn commit(self: Box<Self>) -> Result<()> {
+
+        let mut files = self.files.write().unwrap();
+
+        if self.mode == WriteMode::Create && files.contains_key(&self.path) {
+            let error = Error::new(ErrorKind::AlreadyExists, format!("{:?} already exists", self.path));
+            return Err(error);
+        }
+
+        files.insert(self.path, MemoryFile {
+            content: Arc::new(self.content),
+            modified: SystemTime::now(),
+        });
+
+        Ok(())
+    }
+
+    fn abort(self: Box<Self>) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    pub fn test_memory_backend_1(){
+
+        let backend = MemoryBackend::new();
+        let path = Path::new("127-0-0-1/file.txt");
+
+        let mut staged_write = backend.open_write(path, WriteMode::Create).unwrap();
+        staged_write.write_all(b"content").unwrap();
+
+        assert!(backend.stat(path).is_err());
+        staged_write.commit().unwrap();
+
+        let mut content = String::new();
+        backend.open_read(path).unwrap().read_to_string(&mut content).unwrap();
+
+        assert_eq!(content, "content");
+        assert_eq!(backend.stat(path).unwrap().size, 7);
+
+    }
+
+    #[test]
+    pub fn test_memory_backend_2(){
+
+        let backend = MemoryBackend::new();
+        backend.insert("127-0-0-1/1", b"1").unwrap();
+        backend.insert("127-0-0-2/2", b"2").unwrap();
+        backend.insert(".staging/3", b"3").unwrap();
+
+        assert_eq!(backend.list(Path::new("")).unwrap().len(), 2);
+        assert_eq!(backend.list(Path::new("127-0-0-1")).unwrap().len(), 1);
+        assert_eq!(backend.list(Path::new(".staging")).unwrap().len(), 1);
+
+        backend.rename(Path::new("127-0-0-1/1"), Path::new("127-0-0-2/1")).unwrap();
+        assert!(backend.list(Path::new("127-0-0-1")).unwrap().is_empty());
+
+        backend.delete(Path::new("127-0-0-2/1")).unwrap();
+        assert_eq!(backend.delete(Path::new("127-0-0-2/1")).unwrap_err().kind(), ErrorKind::NotFound);
+
+    }
+
+    #[test]
+    pub fn test_memory_backend_3(){
+
+        let backend = MemoryBackend::new();
+        let path = Path::new("file.txt");
+
+        assert_eq!(backend.open_write(path, WriteMode::Overwrite).err().unwrap().kind(), ErrorKind::NotFound);
+
+        let staged_write = backend.open_write(path, WriteMode::Create).unwrap();
+        backend.insert(path, b"first").unwrap();
+
+        // The file appeared while the write was staged
+        assert_eq!(staged_write.commit().unwrap_err().kind(), ErrorKind::AlreadyExists);
+
+    }
+}
@@ -0,0 +1,467 @@
+use std::cmp;
+use std::io::{Cursor, Error, ErrorKind, Read, Result, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use chrono::DateTime;
+use s3::bucket::Bucket;
+use s3::creds::Credentials;
+use s3::region::Region;
+use s3::serde_types::Part;
+use crate::constants::S3_MIN_PART_SIZE;
+use crate::server_utils::server_config::S3Config;
+use crate::storage::storage_backend::{is_internal_path, validate_path, FileStat, StagedWrite, StorageBackend, WriteMode};
+
+const CONTENT_TYPE: &str = "application/octet-stream";
+
+/// Storage that maps the namespace onto the objects of an S3 compatible bucket.
+/// Every file is an object whose key is its path, optionally prefixed by a fixed key prefix.
+/// Writes larger than a part are sent as multipart uploads and reads are served by ranged requests of one part each.
+#[derive(Debug, Clone)]
+pub struct S3Backend {
+    bucket: Box<Bucket>,
+    key_prefix: String,
+    part_size: usize,
+}
+
+impl S3Backend {
+
+    /// Connects to the configured bucket.
+    /// The part size is raised to the minimum accepted by S3 if needed.
+    pub fn new(config: &S3Config) -> Result<Self> {
+
+        let region = match &config.endpoint {
+            Some(endpoint) => Region::Custom {
+                region: config.region.clone(),
+                endpoint: endpoint.clone(),
+            },
+            None => config.region.parse::<Region>().map_err(Error::other)?,
+        };
+
+        let credentials = match (&config.access_key, &config.secret_key) {
+            (Some(access_key), Some(secret_key)) => Credentials::new(Some(access_key), Some(secret_key), None, None, None),
+            _ => Credentials::default(),
+        }.map_err(Error::other)?;
+
+        let mut bucket = Bucket::new(&config.bucket, region, credentials).map_err(Error::other)?;
+
+        if config.path_style {
+            bucket.set_path_style();
+        }
+
+        let key_prefix = match config.key_prefix.trim_matches('/') {
+            "" => String::new(),
+            key_prefix => format!("{}/", key_prefix),
+        };
+
+        Ok(Self {
+            bucket,
+            key_prefix,
+            part_size: cmp::max(config.part_size, S3_MIN_PART_SIZE),
+        })
+    }
+
+    /// Maps a storage path to the key of its object.
+    ///
+    fn key(&self, path: &Path) -> Result<String> {
+
+        validate_path(path)?;
+
+        let components = path.components()
+            .filter_map(|component| component.as_os_str().to_str())
+            .filter(|component| *component != ".")
+            .collect::<Vec<&str>>();
+
+        Ok(format!("{}{}", self.key_prefix, components.join("/")))
+    }
+
+    /// Maps the key of an object to its storage path, if the object belongs to the storage.
+    ///
+    fn path_of(&self, key: &str) -> Option<PathBuf> {
+
+        match key.strip_prefix(&self.key_prefix) {
+            Some(relative_key) if !relative_key.is_empty() && !relative_key.ends_with('/') => Some(PathBuf::from(relative_key)),
+            _ => None,
+        }
+    }
+
+    /// Reads the metadata of an object, failing with NotFound if it does not exist.
+    ///
+    fn head(&self, path: &Path, key: &str) -> Result<FileStat> {
+
+        let (head, status) = self.bucket.head_object(key).map_err(Error::other)?;
+        check_status(status, key)?;
+
+        Ok(FileStat {
+            path: path.to_path_buf(),
+            size: head.content_length.unwrap_or(0).max(0) as u64,
+            modified: parse_time(head.last_modified.as_deref()),
+        })
+    }
+
+    /// Checks if an object exists.
+    ///
+    fn exists(&self, path: &Path, key: &str) -> Result<bool> {
+
+        match self.head(path, key) {
+            Ok(_) => Ok(true),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(false),
+            Err(error) => Err(error),
+        }
+    }
+}
+
+impl StorageBackend for S3Backend {
+
+    fn open_read(&self, path: &Path) -> Result<Box<dyn Read + Send>> {
+
+        let key = self.key(path)?;
+        let file_stat = self.head(path, &key)?;
+
+        Ok(Box::new(S3Reader {
+            bucket: self.bucket.clone(),
+            key,
+            size: file_stat.size,
+            position: 0,
+            range_size: self.part_size as u64,
+            chunk: Cursor::new(Vec::new()),
+        }))
+    }
+
+    fn open_write(&self, path: &Path, mode: WriteMode) -> Result<Box<dyn StagedWrite>> {
+
+        let key = self.key(path)?;
+        let exists = self.exists(path, &key)?;
+
+        match mode {
+
+            WriteMode::Create if exists => {
+                let error = Error::new(ErrorKind::AlreadyExists, format!("{:?} already exists", path));
+                return Err(error);
+            }
+
+            WriteMode::Overwrite if !exists => {
+                let error = Error::new(ErrorKind::NotFound, format!("{:?} not found", path));
+                return Err(error);
+            }
+
+            _ => (),
+        }
+
+        Ok(Box::new(S3StagedWrite {
+            bucket: self.bucket.clone(),
+            path: path.to_path_buf(),
+            key,
+            mode,
+            part_size: self.part_size,
+            buffer: Vec::with_capacity(self.part_size),
+            upload_id: None,
+            parts: Vec::new(),
+        }))
+    }
+
+    fn delete(&self, path: &Path) -> Result<()> {
+
+        let key = self.key(path)?;
+
+        // Deleting a missing object succeeds on S3, so the object is looked up first
+        self.head(path, &key)?;
+
+        let response = self.bucket.delete_object(&key).map_err(Error::other)?;
+        check_status(response.status_code(), &key)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> Result<()> {
+
+        let from_key = self.key(from)?;
+        let to_key = self.key(to)?;
+
+        self.head(from, &from_key)?;
+
+        let status = self.bucket.copy_object_internal(&from_key, &to_key).map_err(Error::other)?;
+        check_status(status, &to_key)?;
+
+        let response = self.bucket.delete_object(&from_key).map_err(Error::other)?;
+        check_status(response.status_code(), &from_key)
+    }
+
+    fn list(&self, dir: &Path) -> Result<Vec<FileStat>> {
+
+        let prefix = match dir.as_os_str().is_empty() {
+            true => self.key_prefix.clone(),
+            false => format!("{}/", self.key(dir)?),
+        };
+
+        let pages = self.bucket.list(prefix, None).map_err(Error::other)?;
+        let mut files = Vec::new();
+
+        for object in pages.into_iter().flat_map(|page| page.contents) {
+
+            let path = match self.path_of(&object.key) {
+                Some(path) => path,
+                None => continue,
+            };
+
+            // Skip the reserved directories found below the listed one
+            if path.strip_prefix(dir).map_or(true, is_internal_path) {
+                continue;
+            }
+
+            files.push(FileStat {
+                path,
+                size: object.size,
+                modified: parse_time(Some(&object.last_modified)),
+            });
+        }
+
+        files.sort_by(|first, second| first.path.cmp(&second.path));
+
+        Ok(files)
+    }
+
+    fn stat(&self, path: &Path) -> Result<FileStat> {
+        let key = self.key(path)?;
+        self.head(path, &key)
+    }
+}
+
+/// Reader that downloads an object one range at a time.
+///
+struct S3Reader {
+    bucket: Box<Bucket>,
+    key: String,
+    size: u64,
+    position: u64,
+    range_size: u64,
+    chunk: Cursor<Vec<u8>>,
+}
+
+impl Read for S3Reader {
+
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+
+        let bytes_read = self.chunk.read(buf)?;
+
+        if bytes_read > 0 || buf.is_empty() || self.position >= self.size {
+            return Ok(bytes_read);
+        }
+
+        let end = cmp::min(self.position + self.range_size, self.size) - 1;
+
+        let response = self.bucket.get_object_range(&self.key, self.position, Some(end)).map_err(Error::other)?;
+        check_status(response.status_code(), &self.key)?;
+
+        let chunk = response.bytes().to_vec();
+
+        if chunk.is_empty() {
+            let error = Error::new(ErrorKind::UnexpectedEof, format!("{} ended before its expected size", self.key));
+            return Err(error);
+        }
+
+        self.position += chunk.len() as u64;
+        self.chunk = Cursor::new(chunk);
+
+        self.chunk.read(buf)
+    }
+}
+
+/// Staged write that is buffered up to one part.
+/// Larger contents are uploaded part by part as a multipart upload, which only becomes visible once completed.
+struct S3StagedWrite {
+    bucket: Box<Bucket>,
+    path: PathBuf,
+    key: String,
+    mode: WriteMode,
+    part_size: usize,
+    buffer: Vec<u8>,
+    upload_id: Option<String>,
+    parts: Vec<Part>,
+}
+
+impl S3StagedWrite {
+
+    /// Uploads the buffered content as the next part, starting the multipart upload if needed.
+    ///
+    fn upload_part(&mut self) -> Result<()> {
+
+        let upload_id = match &self.upload_id {
+            Some(upload_id) => upload_id.clone(),
+            None => {
+                let upload = self.bucket.initiate_multipart_upload(&self.key, CONTENT_TYPE).map_err(Error::other)?;
+                self.upload_id = Some(upload.upload_id.clone());
+                upload.upload_id
+            }
+        };
+
+        let part_number = self.parts.len() as u32 + 1;
+        let part = self.bucket.put_multipart_chunk(&self.buffer, &self.key, part_number, &upload_id, CONTENT_TYPE)
+            .map_err(Error::other)?;
+
+        self.parts.push(part);
+        self.buffer.clear();
+
+        Ok(())
+    }
+
+    /// Checks if the target object exists.
+    ///
+    fn exists(&self) -> Result<bool> {
+
+        let (_, status) = self.bucket.head_object(&self.key).map_err(Error::other)?;
+
+        match status {
+            404 => Ok(false),
+            status => check_status(status, &self.key).map(|_| true),
+        }
+    }
+}
+
+impl Write for S3StagedWrite {
+
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+
+        let mut remaining = buf;
+
+        while !remaining.is_empty() {
+
+            let free_space = self.part_size - self.buffer.len();
+            let (head, tail) = remaining.split_at(cmp::min(free_space, remaining.len()));
+
+            self.buffer.extend_from_slice(head);
+            remaining = tail;
+
+            if self.buffer.len() == self.part_size {
+                self.upload_part()?;
+            }
+        }
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+impl StagedWrite for S3StagedWrite {
+
+    fn commit(mut self: Box<Self>) -> Result<()> {
+
+        if self.mode == WriteMode::Create && self.exists()? {
+            let error = Error::new(ErrorKind::AlreadyExists, format!("{:?} already exists", self.path));
+            return Err(error);
+        }
+
+        if self.upload_id.is_none() {
+            let response = self.bucket.put_object(&self.key, &self.buffer).map_err(Error::other)?;
+            return check_status(response.status_code(), &self.key);
+        }
+
+        if !self.buffer.is_empty() {
+            self.upload_part()?;
+        }
+
+        let upload_id = self.upload_id.clone().unwrap();
+        let parts = std::mem::take(&mut self.parts);
+
+        let response = self.bucket.complete_multipart_upload(&self.key, &upload_id, parts).map_err(Error::other)?;
+        check_status(response.status_code(), &self.key)?;
+        self.upload_id = None;
+
+        Ok(())
+    }
+
+    fn abort(mut self: Box<Self>) -> Result<()> {
+
+        match self.upload_id.take() {
+            Some(upload_id) => self.bucket.abort_upload(&self.key, &upload_id).map_err(Error::other),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The multipart upload is aborted if the write was neither committed nor aborted,
+/// so that the uploaded parts do not keep taking space in the bucket.
+impl Drop for S3StagedWrite {
+    fn drop(&mut self) {
+        if let Some(upload_id) = self.upload_id.take() {
+            let _ = self.bucket.abort_upload(&self.key, &upload_id);
+        }
+    }
+}
+
+/// Maps the status of an S3 response to an error.
+///
+fn check_status(status: u16, key: &str) -> Result<()> {
+
+    let kind = match status {
+        200..=299 => return Ok(()),
+        403 => ErrorKind::PermissionDenied,
+        404 => ErrorKind::NotFound,
+        _ => ErrorKind::Other,
+    };
+
+    Err(Error::new(kind, format!("S3 request for {} failed with status {}", key, status)))
+}
+
+/// Parses the modification time of an object.
+/// HEAD requests report it in the RFC 2822 format while listings use RFC 3339.
+fn parse_time(value: Option<&str>) -> SystemTime {
+
+    let value = match value {
+        Some(value) => value,
+        None => return UNIX_EPOCH,
+    };
+
+    DateTime::parse_from_rfc2822(value)
+        .or_else(|_| DateTime::parse_from_rfc3339(value))
+        .map(SystemTime::from)
+        .unwrap_or(UNIX_EPOCH)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+    use crate::constants::MEGABYTE;
+    use super::*;
+
+    fn config(key_prefix: &str, part_size: usize) -> S3Config {
+        S3Config {
+            bucket: "files".to_string(),
+            region: "us-east-1".to_string(),
+            endpoint: Some("http://127.0.0.1:9000".to_string()),
+            access_key: Some("access".to_string()),
+            secret_key: Some("secret".to_string()),
+            key_prefix: key_prefix.to_string(),
+            path_style: true,
+            part_size,
+        }
+    }
+
+    #[test]
+    pub fn test_s3_backend_1(){
+
+        let backend = S3Backend::new(&config("/served/", MEGABYTE)).unwrap();
+
+        assert_eq!(backend.part_size, S3_MIN_PART_SIZE);
+        assert_eq!(backend.key(Path::new("127-0-0-1/./file.txt")).unwrap(), "served/127-0-0-1/file.txt");
+        assert_eq!(backend.key(Path::new("../file.txt")).unwrap_err().kind(), ErrorKind::InvalidInput);
+
+        assert_eq!(backend.path_of("served/127-0-0-1/file.txt"), Some(PathBuf::from("127-0-0-1/file.txt")));
+        assert_eq!(backend.path_of("served/127-0-0-1/"), None);
+        assert_eq!(backend.path_of("other/file.txt"), None);
+
+    }
+
+    #[test]
+    pub fn test_s3_backend_2(){
+
+        assert_eq!(parse_time(Some("Thu, 01 Jan 1970 00:01:00 GMT")), UNIX_EPOCH + Duration::from_secs(60));
+        assert_eq!(parse_time(Some("1970-01-01T00:01:00.000Z")), UNIX_EPOCH + Duration::from_secs(60));
+        assert_eq!(parse_time(Some("yesterday")), UNIX_EPOCH);
+
+        assert!(check_status(206, "key").is_ok());
+        assert_eq!(check_status(404, "key").unwrap_err().kind(), ErrorKind::NotFound);
+        assert_eq!(check_status(500, "key").unwrap_err().kind(), ErrorKind::Other);
+
+    }
+}
@@ -0,0 +1,169 @@
+use std::fmt::Debug;
+use std::fs;
+use std::io;
+use std::io::{Error, ErrorKind, Read, Result, Write};
+use std::path::{Component, Path, PathBuf};
+use std::time::SystemTime;
+
+/// Metadata of a stored file.
+/// The path is relative to the root of the storage.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileStat {
+    pub path: PathBuf,
+    pub size: u64,
+    pub modified: SystemTime,
+}
+
+impl FileStat {
+    /// Reads the metadata of a file from the local file system and stores it under the given path.
+    ///
+    pub fn from_metadata<P: AsRef<Path>>(path: P, metadata: &fs::Metadata) -> Result<Self> {
+        Ok(Self {
+            path: path.as_ref().to_path_buf(),
+            size: metadata.len(),
+            modified: metadata.modified()?,
+        })
+    }
+}
+
+/// How a staged write treats the file it replaces on commit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WriteMode {
+    /// The file must not exist yet.
+    Create,
+    /// The file must already exist and its content is replaced.
+    Overwrite,
+}
+
+/// Write in progress to a stored file.
+/// The written content only becomes visible after a successful commit.
+pub trait StagedWrite: Write + Send {
+    /// Publishes the written content under the target path.
+    fn commit(self: Box<Self>) -> Result<()>;
+
+    /// Drops the written content, leaving the target untouched.
+    fn abort(self: Box<Self>) -> Result<()>;
+}
+
+/// Place where the server keeps its files.
+/// Every path is relative to the storage root and uses `/` separated components.
+/// Directories whose name starts with a dot are reserved for the server and hidden from listings.
+pub trait StorageBackend: Send + Sync + Debug {
+
+    /// Opens a file for reading.
+    fn open_read(&self, path: &Path) -> Result<Box<dyn Read + Send>>;
+
+    /// Starts a staged write of a file.
+    /// Fails with AlreadyExists or NotFound if the file does not fit the given mode.
+    fn open_write(&self, path: &Path, mode: WriteMode) -> Result<Box<dyn StagedWrite>>;
+
+    /// Deletes a file.
+    fn delete(&self, path: &Path) -> Result<()>;
+
+    /// Moves a file, replacing the destination if it exists.
+    fn rename(&self, from: &Path, to: &Path) -> Result<()>;
+
+    /// Lists every file in the hierarchy of the given directory, an empty path meaning the whole storage.
+    /// Listing a missing directory returns no files.
+    fn list(&self, dir: &Path) -> Result<Vec<FileStat>>;
+
+    /// Returns the metadata of a file.
+    fn stat(&self, path: &Path) -> Result<FileStat>;
+
+    /// Directory on the local file system backing this storage, if any.
+    fn local_root(&self) -> Option<&Path> {
+        None
+    }
+}
+
+/// Checks that a path stays inside the storage root.
+///
+pub fn validate_path(path: &Path) -> Result<()> {
+
+    let is_valid = path.components().all(|component| matches!(component, Component::Normal(_) | Component::CurDir));
+
+    if !is_valid || path.as_os_str().is_empty() {
+        let error = Error::new(ErrorKind::InvalidInput, format!("Invalid path {:?}", path));
+        return Err(error);
+    }
+
+    Ok(())
+}
+
+/// Checks if any directory of the path is reserved for the server.
+///
+pub fn is_internal_path(path: &Path) -> bool {
+
+    let mut components = path.components().collect::<Vec<Component>>();
+    components.pop();
+
+    components.iter().any(|component| match component {
+        Component::Normal(name) => name.to_string_lossy().starts_with('.'),
+        _ => false,
+    })
+}
+
+/// Copies the content of a file to another path of the same storage through a staged write.
+///
+pub fn copy_file(storage: &dyn StorageBackend, from: &Path, to: &Path, mode: WriteMode) -> Result<()> {
+
+    let mut reader = storage.open_read(from)?;
+    let mut staged_write = storage.open_write(to, mode)?;
+
+    if let Err(error) = io::copy(&mut reader, &mut staged_write) {
+        staged_write.abort()?;
+        return Err(error);
+    }
+
+    staged_write.commit()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::memory_backend::MemoryBackend;
+
+    #[test]
+    pub fn test_validate_path_1(){
+
+        assert!(validate_path(Path::new("127-0-0-1/file.txt")).is_ok());
+        assert!(validate_path(Path::new("./file.txt")).is_ok());
+
+    }
+
+    #[test]
+    pub fn test_validate_path_2(){
+
+        assert!(validate_path(Path::new("")).is_err());
+        assert!(validate_path(Path::new("/etc/passwd")).is_err());
+        assert!(validate_path(Path::new("127-0-0-1/../../file.txt")).is_err());
+
+    }
+
+    #[test]
+    pub fn test_is_internal_path_1(){
+
+        assert!(is_internal_path(Path::new(".staging/file.txt")));
+        assert!(is_internal_path(Path::new("127-0-0-1/.hidden/file.txt")));
+        assert!(!is_internal_path(Path::new("127-0-0-1/.file.txt")));
+        assert!(!is_internal_path(Path::new("127-0-0-1/file.txt")));
+
+    }
+
+    #[test]
+    pub fn test_copy_file_1(){
+
+        let storage = MemoryBackend::new();
+        storage.insert("1/file.txt", b"content").unwrap();
+
+        copy_file(&storage, Path::new("1/file.txt"), Path::new("2/file.txt"), WriteMode::Create).unwrap();
+
+        let mut content = String::new();
+        storage.open_read(Path::new("2/file.txt")).unwrap().read_to_string(&mut content).unwrap();
+
+        assert_eq!(content, "content");
+        assert!(storage.stat(Path::new("1/file.txt")).is_ok());
+        assert_eq!(copy_file(&storage, Path::new("1/file.txt"), Path::new("2/file.txt"), WriteMode::Create).unwrap_err().kind(), ErrorKind::AlreadyExists);
+
+    }
+}
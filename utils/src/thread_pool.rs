@@ -2,12 +2,22 @@ use std::sync::mpsc::{channel, Receiver, Sender};
 use std::sync::{Arc, Mutex};
 use std::thread;
 use std::thread::JoinHandle;
+use tracing::debug;
 
 type Job = Box<dyn FnOnce() + Send + 'static>;
 
+/// Message received by the workers, either a job or a request for one of them to stop.
+enum Message {
+    Job(Job),
+    Stop,
+}
+
 pub struct ThreadPool {
     workers: Vec<Worker>,
-    sender: Option<Sender<Job>>,
+    sender: Option<Sender<Message>>,
+    receiver: Arc<Mutex<Receiver<Message>>>,
+    size: usize,
+    next_label: usize,
 }
 
 impl ThreadPool {
@@ -27,19 +37,50 @@ impl ThreadPool {
         Self{
             workers,
             sender: Some(sender),
+            receiver,
+            size,
+            next_label: size,
         }
     }
 
+    /// Changes the number of workers.
+    /// Workers in excess stop once they are done with the jobs they already took.
+    pub fn resize(&mut self, size: usize){
+        assert!(size > 0);
+
+        // Forget the workers which already stopped
+        self.workers.retain(|worker| worker.thread.as_ref().is_some_and(|thread| !thread.is_finished()));
+
+        for _ in size..self.size{
+            self.send(Message::Stop);
+        }
+
+        for _ in self.size..size{
+            self.workers.push(Worker::new(self.next_label,Arc::clone(&self.receiver)));
+            self.next_label += 1;
+        }
+
+        self.size = size;
+    }
+
+    pub fn size(&self) -> usize{
+        self.size
+    }
+
     pub fn execute<F>(&self,f: F)
     where
         F: FnOnce() + Send + 'static,
     {
         let job = Box::new(f);
 
+        self.send(Message::Job(job));
+    }
+
+    fn send(&self, message: Message){
         self.sender
             .as_ref()
             .unwrap()
-            .send(job)
+            .send(message)
             .unwrap();
     }
 
@@ -54,7 +95,7 @@ impl Drop for ThreadPool
 
         for worker in &mut self.workers{
 
-            println!("Shutting down worker {}", worker.label);
+            debug!("Shutting down worker {}", worker.label);
 
            if let Some(thread) = worker.thread.take() {
                thread.join().unwrap();
@@ -72,7 +113,7 @@ struct Worker
 }
 
 impl Worker {
-    pub fn new(label: usize,receiver: Arc<Mutex<Receiver<Job>>>) -> Self{
+    pub fn new(label: usize,receiver: Arc<Mutex<Receiver<Message>>>) -> Self{
 
         let thread = thread::spawn(move || {
 
@@ -84,13 +125,18 @@ impl Worker {
                     .recv();
 
                 match message{
-                    Ok(job) => {
+                    Ok(Message::Job(job)) => {
                         // println!("Worker labeled with {label} got a job.");
                         job()
                     }
 
+                    Ok(Message::Stop) => {
+                        debug!("Worker labeled with {label} stopped.");
+                        break;
+                    }
+
                     Err(_) => {
-                        println!("Worker labeled with {label} disconnected.");
+                        debug!("Worker labeled with {label} disconnected.");
                         break;
                     }
 
@@ -104,4 +150,31 @@ impl Worker {
             thread: Some(thread),
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Barrier;
+
+    #[test]
+    pub fn test_thread_pool_1(){
+
+        let mut pool = ThreadPool::new(1);
+        pool.resize(3);
+
+        // The jobs only finish once the three of them run at once
+        let barrier = Arc::new(Barrier::new(4));
+
+        for _ in 0..3{
+            let barrier = Arc::clone(&barrier);
+            pool.execute(move || { barrier.wait(); });
+        }
+
+        barrier.wait();
+
+        pool.resize(2);
+
+        assert_eq!(pool.size(),2);
+
+    }
+}
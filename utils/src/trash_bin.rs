@@ -0,0 +1,287 @@
+use std::io::{Error, ErrorKind, Result};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::constants::{SECONDS_PER_DAY, TRASH_DIR};
+use crate::server_utils::server_config::TrashConfig;
+use crate::storage::storage_backend::{FileStat, StorageBackend};
+
+/// File moved to the trash by a delete.
+/// The path is the location of the trashed content in the storage.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TrashedFile {
+    pub file_name: String,
+    pub deleted: SystemTime,
+    pub size: u64,
+    pub path: PathBuf,
+}
+
+impl TrashedFile {
+
+    /// Parses a trashed file, named <deleted unix milliseconds>-<file name>.
+    ///
+    fn parse(file_stat: &FileStat) -> Option<Self> {
+
+        let name = file_stat.path.file_name()?.to_str()?;
+        let (deleted, file_name) = name.split_once('-')?;
+        let deleted = deleted.parse::<u64>().ok()?;
+
+        Some(Self {
+            file_name: file_name.to_string(),
+            deleted: UNIX_EPOCH + Duration::from_millis(deleted),
+            size: file_stat.size,
+            path: file_stat.path.clone(),
+        })
+    }
+
+    /// Checks if the file has been in the trash for longer than the configured max age.
+    /// A max age too long to count in seconds never expires.
+    pub fn is_expired(&self, config: &TrashConfig, now: SystemTime) -> bool {
+        config.max_age_days.and_then(|days| days.checked_mul(SECONDS_PER_DAY)).is_some_and(|max_age| {
+            now.duration_since(self.deleted).unwrap_or_default() > Duration::from_secs(max_age)
+        })
+    }
+}
+
+/// Per owner trash area of a storage.
+/// The deleted files of an owner directory are kept in the trash directory under the same owner directory,
+/// which the listings of the storage do not show.
+#[derive(Debug)]
+pub struct TrashBin {
+    storage: Arc<dyn StorageBackend>,
+    config: TrashConfig,
+    lock: Mutex<()>,
+}
+
+impl TrashBin {
+
+    pub fn new(storage: Arc<dyn StorageBackend>, config: TrashConfig) -> Self {
+        Self {
+            storage,
+            config,
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Checks if deleted files are moved to the trash.
+    ///
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// Moves a file to the trash of the directory holding it.
+    ///
+    pub fn trash(&self, path: &Path) -> Result<()> {
+
+        let file_name = path.file_name()
+            .and_then(|file_name| file_name.to_str())
+            .ok_or_else(|| Error::new(ErrorKind::InvalidInput, format!("Invalid file path {:?}", path)))?;
+
+        let _guard = self.lock.lock().unwrap();
+
+        // Files deleted within the same millisecond are told apart by bumping the time
+        let mut deleted = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+        let mut trash_path = trash_dir(path).join(format!("{}-{}", deleted, file_name));
+
+        while self.storage.stat(&trash_path).is_ok() {
+            deleted += 1;
+            trash_path = trash_dir(path).join(format!("{}-{}", deleted, file_name));
+        }
+
+        self.storage.rename(path, &trash_path)
+    }
+
+    /// Returns the trashed files of an owner directory, oldest deletion first.
+    ///
+    pub fn files<P: AsRef<Path>>(&self, owner_dir: P) -> Result<Vec<TrashedFile>> {
+
+        let mut files = self.storage.list(&Path::new(TRASH_DIR).join(owner_dir))?
+            .iter()
+            .filter_map(TrashedFile::parse)
+            .collect::<Vec<TrashedFile>>();
+
+        files.sort_by_key(|file| file.deleted);
+
+        Ok(files)
+    }
+
+    /// Moves the most recently trashed file with the name of the given path back to that path.
+    /// Fails with NotFound if no such file is in the trash, and with AlreadyExists if the path is taken.
+    pub fn restore(&self, path: &Path) -> Result<()> {
+
+        let _guard = self.lock.lock().unwrap();
+
+        let owner_dir = path.parent().unwrap_or(Path::new(""));
+        let file_name = path.file_name().and_then(|file_name| file_name.to_str());
+
+        let trashed_file = self.files(owner_dir)?
+            .into_iter()
+            .rev()
+            .find(|file| Some(file.file_name.as_str()) == file_name)
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("{:?} is not in the trash", path)))?;
+
+        if self.storage.stat(path).is_ok() {
+            return Err(Error::new(ErrorKind::AlreadyExists, format!("{:?} already exists", path)));
+        }
+
+        self.storage.rename(&trashed_file.path, path)
+    }
+
+    /// Deletes every trashed file of an owner directory.
+    /// Returns the number of deleted files.
+    pub fn empty<P: AsRef<Path>>(&self, owner_dir: P) -> Result<usize> {
+        let _guard = self.lock.lock().unwrap();
+        self.delete_files(self.files(owner_dir)?.iter())
+    }
+
+    /// Deletes the trashed files of every owner which have been in the trash for longer than the configured max age.
+    /// Returns the number of deleted files.
+    pub fn purge_expired(&self) -> Result<usize> {
+
+        let _guard = self.lock.lock().unwrap();
+        let now = SystemTime::now();
+
+        let files = self.storage.list(Path::new(TRASH_DIR))?
+            .iter()
+            .filter_map(TrashedFile::parse)
+            .collect::<Vec<TrashedFile>>();
+
+        self.delete_files(files.iter().filter(|file| file.is_expired(&self.config, now)))
+    }
+
+    fn delete_files<'a>(&self, files: impl Iterator<Item = &'a TrashedFile>) -> Result<usize> {
+
+        let mut deleted = 0;
+
+        for file in files {
+            match self.storage.delete(&file.path) {
+                Ok(_) => deleted += 1,
+                Err(error) if error.kind() == ErrorKind::NotFound => (),
+                Err(error) => return Err(error),
+            }
+        }
+
+        Ok(deleted)
+    }
+}
+
+/// Directory holding the trashed files of the same directory as the given file.
+///
+fn trash_dir(path: &Path) -> PathBuf {
+    Path::new(TRASH_DIR).join(path.parent().unwrap_or(Path::new("")))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use crate::storage::memory_backend::MemoryBackend;
+    use super::*;
+
+    fn trash_bin(max_age_days: Option<u64>) -> (Arc<MemoryBackend>, TrashBin) {
+
+        let storage = Arc::new(MemoryBackend::new());
+        let config = TrashConfig {
+            enabled: true,
+            max_age_days,
+        };
+
+        (storage.clone(), TrashBin::new(storage, config))
+    }
+
+    fn read(storage: &MemoryBackend, path: &Path) -> String {
+        let mut content = String::new();
+        storage.open_read(path).unwrap().read_to_string(&mut content).unwrap();
+        content
+    }
+
+    #[test]
+    pub fn test_trash_bin_1(){
+
+        let (storage, trash) = trash_bin(None);
+        let path = Path::new("127-0-0-1/file.txt");
+
+        storage.insert(path, b"first").unwrap();
+        trash.trash(path).unwrap();
+        storage.insert(path, b"second").unwrap();
+        trash.trash(path).unwrap();
+
+        // Trashed files are neither listed nor visible to the other owners
+        assert!(storage.list(Path::new("")).unwrap().is_empty());
+        assert!(trash.files("127-0-0-2").unwrap().is_empty());
+
+        let files = trash.files("127-0-0-1").unwrap();
+
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].file_name, "file.txt");
+        assert_eq!(files[1].size, 6);
+        assert_eq!(trash.trash(path).unwrap_err().kind(), ErrorKind::NotFound);
+
+    }
+
+    #[test]
+    pub fn test_trash_bin_2(){
+
+        let (storage, trash) = trash_bin(None);
+        let path = Path::new("127-0-0-1/file.txt");
+
+        storage.insert(path, b"first").unwrap();
+        trash.trash(path).unwrap();
+        storage.insert(path, b"second").unwrap();
+        trash.trash(path).unwrap();
+
+        // The latest deletion comes back first
+        trash.restore(path).unwrap();
+        assert_eq!(read(&storage, path), "second");
+        assert_eq!(trash.restore(path).unwrap_err().kind(), ErrorKind::AlreadyExists);
+
+        storage.delete(path).unwrap();
+        trash.restore(path).unwrap();
+        assert_eq!(read(&storage, path), "first");
+
+        assert_eq!(trash.restore(path).unwrap_err().kind(), ErrorKind::NotFound);
+        assert_eq!(trash.restore(Path::new("127-0-0-1/other.txt")).unwrap_err().kind(), ErrorKind::NotFound);
+
+    }
+
+    #[test]
+    pub fn test_trash_bin_3(){
+
+        let (storage, trash) = trash_bin(Some(30));
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+        let day = SECONDS_PER_DAY * 1000;
+
+        storage.insert(format!(".trash/127-0-0-1/{}-old.txt", now - 31 * day), b"").unwrap();
+        storage.insert(format!(".trash/127-0-0-2/{}-old.txt", now - 40 * day), b"").unwrap();
+        storage.insert(format!(".trash/127-0-0-2/{}-recent.txt", now - 29 * day), b"").unwrap();
+        storage.insert("127-0-0-1/kept.txt", b"").unwrap();
+
+        assert_eq!(trash.purge_expired().unwrap(), 2);
+        assert!(trash.files("127-0-0-1").unwrap().is_empty());
+        assert_eq!(trash.files("127-0-0-2").unwrap()[0].file_name, "recent.txt");
+
+        assert_eq!(trash.empty("127-0-0-2").unwrap(), 1);
+        assert!(trash.files("127-0-0-2").unwrap().is_empty());
+        assert_eq!(storage.list(Path::new("")).unwrap().len(), 1);
+
+    }
+
+    #[test]
+    pub fn test_trashed_file_1(){
+
+        let now = UNIX_EPOCH + Duration::from_secs(100 * SECONDS_PER_DAY);
+        let file = TrashedFile {
+            file_name: "file.txt".to_string(),
+            deleted: now - Duration::from_secs(8 * SECONDS_PER_DAY),
+            size: 0,
+            path: PathBuf::from(".trash/1/0-file.txt"),
+        };
+
+        let config = |max_age_days| TrashConfig { enabled: true, max_age_days };
+
+        assert!(file.is_expired(&config(Some(7)), now));
+        assert!(!file.is_expired(&config(Some(8)), now));
+        assert!(!file.is_expired(&config(None), now));
+        assert!(!file.is_expired(&config(Some(u64::MAX)), now));
+
+    }
+}
@@ -0,0 +1,445 @@
+use std::collections::BTreeMap;
+use std::io::{Error, ErrorKind, Read, Result};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::constants::{SECONDS_PER_DAY, VERSIONS_DIR};
+use crate::server_utils::server_config::VersioningConfig;
+use crate::storage::storage_backend::{copy_file, FileStat, StorageBackend, WriteMode};
+
+/// Previous content of a file, archived when the file was updated or deleted.
+/// The path is the location of the archived content in the storage.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileVersion {
+    pub id: u64,
+    pub archived: SystemTime,
+    pub size: u64,
+    pub path: PathBuf,
+}
+
+impl FileVersion {
+
+    /// Parses a stored version, named <id>-<archived unix seconds>-<file name>.
+    /// Returns the name of the versioned file along with the version.
+    fn parse(file_stat: &FileStat) -> Option<(String, Self)> {
+
+        let name = file_stat.path.file_name()?.to_str()?;
+        let mut parts = name.splitn(3, '-');
+
+        let id = parts.next()?.parse::<u64>().ok()?;
+        let archived = parts.next()?.parse::<u64>().ok()?;
+        let file_name = parts.next()?.to_string();
+
+        Some((file_name, Self {
+            id,
+            archived: UNIX_EPOCH + Duration::from_secs(archived),
+            size: file_stat.size,
+            path: file_stat.path.clone(),
+        }))
+    }
+}
+
+/// History of the files of a storage.
+/// The versions of a file are kept in the versions directory, under the same owner directory as the file.
+/// Archiving and pruning are serialized so that two requests never pick the same version id.
+#[derive(Debug)]
+pub struct VersionStore {
+    storage: Arc<dyn StorageBackend>,
+    config: VersioningConfig,
+    lock: Mutex<()>,
+}
+
+impl VersionStore {
+
+    pub fn new(storage: Arc<dyn StorageBackend>, config: VersioningConfig) -> Self {
+        Self {
+            storage,
+            config,
+            lock: Mutex::new(()),
+        }
+    }
+
+    /// Checks if files are archived when they are updated or deleted.
+    ///
+    pub fn is_enabled(&self) -> bool {
+        self.config.enabled
+    }
+
+    /// Returns the versions of a file, oldest first.
+    ///
+    pub fn versions(&self, path: &Path) -> Result<Vec<FileVersion>> {
+
+        let file_name = file_name_of(path)?;
+
+        let mut versions = self.storage.list(&history_dir(path))?
+            .iter()
+            .filter_map(FileVersion::parse)
+            .filter(|(version_file_name, _)| *version_file_name == file_name)
+            .map(|(_, version)| version)
+            .collect::<Vec<FileVersion>>();
+
+        versions.sort_by_key(|version| version.id);
+
+        Ok(versions)
+    }
+
+    /// Returns a single version of a file.
+    /// Fails with NotFound if the file has no such version.
+    pub fn version(&self, path: &Path, id: u64) -> Result<FileVersion> {
+
+        self.versions(path)?
+            .into_iter()
+            .find(|version| version.id == id)
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("Version {} of {:?} not found", id, path)))
+    }
+
+    /// Opens a version of a file for reading.
+    ///
+    pub fn open_version(&self, path: &Path, id: u64) -> Result<Box<dyn Read + Send>> {
+        let version = self.version(path, id)?;
+        self.storage.open_read(&version.path)
+    }
+
+    /// Keeps a copy of the current content of a file before it is replaced.
+    /// Does nothing when versioning is disabled.
+    pub fn archive(&self, path: &Path) -> Result<()> {
+
+        if !self.is_enabled() {
+            return Ok(());
+        }
+
+        let _guard = self.lock.lock().unwrap();
+
+        let version_path = self.next_version_path(path)?;
+        copy_file(self.storage.as_ref(), path, &version_path, WriteMode::Create)?;
+
+        self.prune_file(path).map(|_| ())
+    }
+
+    /// Deletes a file, moving its content into its history when versioning is enabled.
+    ///
+    pub fn delete(&self, path: &Path) -> Result<()> {
+
+        if !self.is_enabled() {
+            return self.storage.delete(path);
+        }
+
+        let _guard = self.lock.lock().unwrap();
+
+        let version_path = self.next_version_path(path)?;
+        self.storage.rename(path, &version_path)?;
+
+        self.prune_file(path).map(|_| ())
+    }
+
+    /// Replaces the content of a file by one of its versions, recreating the file if it was deleted.
+    /// The replaced content is archived first, and the restored version stays in the history.
+    pub fn restore(&self, path: &Path, id: u64) -> Result<()> {
+
+        let _guard = self.lock.lock().unwrap();
+        let version = self.version(path, id)?;
+
+        let mode = match self.storage.stat(path) {
+            Ok(_) => WriteMode::Overwrite,
+            Err(error) if error.kind() == ErrorKind::NotFound => WriteMode::Create,
+            Err(error) => return Err(error),
+        };
+
+        if mode == WriteMode::Overwrite && self.is_enabled() {
+            let version_path = self.next_version_path(path)?;
+            copy_file(self.storage.as_ref(), path, &version_path, WriteMode::Create)?;
+        }
+
+        copy_file(self.storage.as_ref(), &version.path, path, mode)?;
+
+        // Pruned only now so that the restored version cannot be dropped before it is copied
+        self.prune_file(path).map(|_| ())
+    }
+
+    /// Deletes the versions of a file which are no longer retained.
+    /// Returns the number of deleted versions.
+    pub fn prune(&self, path: &Path) -> Result<usize> {
+        let _guard = self.lock.lock().unwrap();
+        self.prune_file(path)
+    }
+
+    /// Deletes the versions of every file which are no longer retained.
+    /// Returns the number of deleted versions.
+    pub fn prune_all(&self) -> Result<usize> {
+
+        let _guard = self.lock.lock().unwrap();
+        let mut histories: BTreeMap<(PathBuf, String), Vec<FileVersion>> = BTreeMap::new();
+
+        for file_stat in self.storage.list(Path::new(VERSIONS_DIR))? {
+
+            if let Some((file_name, version)) = FileVersion::parse(&file_stat) {
+                let history_dir = file_stat.path.parent().unwrap_or(Path::new("")).to_path_buf();
+                histories.entry((history_dir, file_name)).or_default().push(version);
+            }
+        }
+
+        let mut pruned = 0;
+
+        for mut versions in histories.into_values() {
+            versions.sort_by_key(|version| version.id);
+            pruned += self.delete_versions(expired_versions(&versions, &self.config, SystemTime::now()))?;
+        }
+
+        Ok(pruned)
+    }
+
+    /// Prunes the versions of a file, the lock must already be held.
+    ///
+    fn prune_file(&self, path: &Path) -> Result<usize> {
+        let versions = self.versions(path)?;
+        self.delete_versions(expired_versions(&versions, &self.config, SystemTime::now()))
+    }
+
+    fn delete_versions(&self, versions: Vec<&FileVersion>) -> Result<usize> {
+
+        for version in &versions {
+            match self.storage.delete(&version.path) {
+                Err(error) if error.kind() != ErrorKind::NotFound => return Err(error),
+                _ => (),
+            }
+        }
+
+        Ok(versions.len())
+    }
+
+    /// Picks the path of the next version of a file, the lock must already be held.
+    ///
+    fn next_version_path(&self, path: &Path) -> Result<PathBuf> {
+
+        let file_name = file_name_of(path)?;
+        let id = self.versions(path)?.last().map_or(1, |version| version.id + 1);
+        let archived = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs();
+
+        Ok(history_dir(path).join(format!("{}-{}-{}", id, archived, file_name)))
+    }
+}
+
+/// Selects the versions falling outside the retention policy, given versions sorted oldest first.
+///
+pub fn expired_versions<'a>(versions: &'a [FileVersion], config: &VersioningConfig, now: SystemTime) -> Vec<&'a FileVersion> {
+
+    let excess = match config.max_versions {
+        Some(max_versions) => versions.len().saturating_sub(max_versions),
+        None => 0,
+    };
+
+    // A max age too long to count in seconds never expires
+    let max_age = config.max_age_days.and_then(|days| days.checked_mul(SECONDS_PER_DAY)).map(Duration::from_secs);
+
+    versions.iter()
+        .enumerate()
+        .filter(|(position, version)| {
+            let too_old = max_age.is_some_and(|max_age| now.duration_since(version.archived).unwrap_or_default() > max_age);
+            *position < excess || too_old
+        })
+        .map(|(_, version)| version)
+        .collect()
+}
+
+/// Directory holding the versions of the files of the same directory as the given file.
+///
+fn history_dir(path: &Path) -> PathBuf {
+    Path::new(VERSIONS_DIR).join(path.parent().unwrap_or(Path::new("")))
+}
+
+fn file_name_of(path: &Path) -> Result<String> {
+    path.file_name()
+        .and_then(|file_name| file_name.to_str())
+        .map(|file_name| file_name.to_string())
+        .ok_or_else(|| Error::new(ErrorKind::InvalidInput, format!("Invalid file path {:?}", path)))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::storage::memory_backend::MemoryBackend;
+    use super::*;
+
+    fn store(enabled: bool, max_versions: Option<usize>) -> (Arc<MemoryBackend>, VersionStore) {
+
+        let storage = Arc::new(MemoryBackend::new());
+        let config = VersioningConfig {
+            enabled,
+            max_versions,
+            max_age_days: None,
+        };
+
+        (storage.clone(), VersionStore::new(storage, config))
+    }
+
+    fn read(reader: Result<Box<dyn Read + Send>>) -> String {
+        let mut content = String::new();
+        reader.unwrap().read_to_string(&mut content).unwrap();
+        content
+    }
+
+    fn version(id: u64, archived: SystemTime) -> FileVersion {
+        FileVersion {
+            id,
+            archived,
+            size: 0,
+            path: PathBuf::from(format!(".versions/1/{}-0-file.txt", id)),
+        }
+    }
+
+    #[test]
+    pub fn test_version_store_1(){
+
+        let (storage, versions) = store(true, None);
+        let path = Path::new("127-0-0-1/file.txt");
+
+        storage.insert(path, b"first").unwrap();
+        versions.archive(path).unwrap();
+        storage.insert(path, b"second").unwrap();
+        versions.archive(path).unwrap();
+
+        // Versions of another file of the same owner are kept apart
+        storage.insert("127-0-0-1/other.txt", b"other").unwrap();
+        versions.archive(Path::new("127-0-0-1/other.txt")).unwrap();
+
+        let history = versions.versions(path).unwrap();
+
+        assert_eq!(history.iter().map(|version| version.id).collect::<Vec<u64>>(), vec![1, 2]);
+        assert_eq!(history[1].size, 6);
+        assert_eq!(read(versions.open_version(path, 1)), "first");
+        assert_eq!(versions.version(path, 3).unwrap_err().kind(), ErrorKind::NotFound);
+
+        // The history is not visible in listings
+        assert_eq!(storage.list(Path::new("")).unwrap().len(), 2);
+
+    }
+
+    #[test]
+    pub fn test_version_store_2(){
+
+        let (storage, versions) = store(true, None);
+        let path = Path::new("127-0-0-1/file.txt");
+
+        storage.insert(path, b"first").unwrap();
+        versions.delete(path).unwrap();
+
+        assert!(storage.stat(path).is_err());
+        assert_eq!(versions.versions(path).unwrap().len(), 1);
+
+        // Restoring a deleted file recreates it
+        versions.restore(path, 1).unwrap();
+        assert_eq!(read(storage.open_read(path)), "first");
+
+        // Restoring over an existing file archives it first
+        storage.insert(path, b"second").unwrap();
+        versions.restore(path, 1).unwrap();
+
+        assert_eq!(read(storage.open_read(path)), "first");
+        assert_eq!(read(versions.open_version(path, 2)), "second");
+        assert_eq!(versions.restore(path, 5).unwrap_err().kind(), ErrorKind::NotFound);
+
+    }
+
+    #[test]
+    pub fn test_version_store_3(){
+
+        let (storage, versions) = store(false, None);
+        let path = Path::new("127-0-0-1/file.txt");
+
+        storage.insert(path, b"first").unwrap();
+        versions.archive(path).unwrap();
+        versions.delete(path).unwrap();
+
+        assert!(storage.stat(path).is_err());
+        assert!(versions.versions(path).unwrap().is_empty());
+        assert_eq!(versions.delete(path).unwrap_err().kind(), ErrorKind::NotFound);
+
+    }
+
+    #[test]
+    pub fn test_version_store_4(){
+
+        let (storage, versions) = store(true, Some(2));
+        let path = Path::new("127-0-0-1/file.txt");
+
+        for content in ["1", "2", "3"] {
+            storage.insert(path, content.as_bytes()).unwrap();
+            versions.archive(path).unwrap();
+        }
+
+        let history = versions.versions(path).unwrap();
+
+        assert_eq!(history.iter().map(|version| version.id).collect::<Vec<u64>>(), vec![2, 3]);
+        assert_eq!(read(versions.open_version(path, 2)), "2");
+
+        // The limit still holds when restoring the oldest version
+        versions.restore(path, 2).unwrap();
+
+        assert_eq!(read(storage.open_read(path)), "2");
+        assert_eq!(versions.versions(path).unwrap().iter().map(|version| version.id).collect::<Vec<u64>>(), vec![3, 4]);
+
+    }
+
+    #[test]
+    pub fn test_version_store_5(){
+
+        let (storage, _) = store(true, None);
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+
+        storage.insert(format!(".versions/127-0-0-1/1-{}-file.txt", now - 40 * SECONDS_PER_DAY), b"").unwrap();
+        storage.insert(format!(".versions/127-0-0-1/2-{}-file.txt", now - 31 * SECONDS_PER_DAY), b"").unwrap();
+        storage.insert(format!(".versions/127-0-0-2/1-{}-file.txt", now - 29 * SECONDS_PER_DAY), b"").unwrap();
+        storage.insert(".versions/127-0-0-2/not-a-version", b"").unwrap();
+
+        let config = VersioningConfig {
+            enabled: true,
+            max_versions: None,
+            max_age_days: Some(30),
+        };
+
+        let versions = VersionStore::new(storage.clone(), config);
+
+        assert_eq!(versions.prune_all().unwrap(), 2);
+        assert_eq!(versions.versions(Path::new("127-0-0-2/file.txt")).unwrap().len(), 1);
+        assert!(versions.versions(Path::new("127-0-0-1/file.txt")).unwrap().is_empty());
+
+    }
+
+    #[test]
+    pub fn test_expired_versions_1(){
+
+        let day = Duration::from_secs(SECONDS_PER_DAY);
+        let now = UNIX_EPOCH + 100 * day;
+        let versions = vec![version(1, now - 10 * day), version(2, now - 5 * day), version(3, now)];
+
+        let config = VersioningConfig {
+            enabled: true,
+            max_versions: Some(2),
+            max_age_days: None,
+        };
+        assert_eq!(expired_versions(&versions, &config, now), vec![&versions[0]]);
+
+        let config = VersioningConfig {
+            enabled: true,
+            max_versions: None,
+            max_age_days: Some(7),
+        };
+        assert_eq!(expired_versions(&versions, &config, now), vec![&versions[0]]);
+
+        let config = VersioningConfig {
+            enabled: true,
+            max_versions: Some(1),
+            max_age_days: Some(1),
+        };
+        assert_eq!(expired_versions(&versions, &config, now), vec![&versions[0], &versions[1]]);
+
+        assert!(expired_versions(&versions, &VersioningConfig::default(), now).is_empty());
+
+        let config = VersioningConfig {
+            enabled: true,
+            max_versions: None,
+            max_age_days: Some(u64::MAX),
+        };
+        assert!(expired_versions(&versions, &config, now).is_empty());
+
+    }
+}
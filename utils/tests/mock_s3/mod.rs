@@ -0,0 +1,370 @@
+//! Minimal stand-in for an S3 compatible service, implementing only the requests issued by the S3 storage backend.
+//! Objects are kept in memory, requests are not authenticated and every connection serves a single request.
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::{BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use chrono::{SecondsFormat, Utc};
+
+#[derive(Default)]
+struct State {
+    objects: BTreeMap<String, Vec<u8>>,
+    uploads: HashMap<String, BTreeMap<u32, Vec<u8>>>,
+    next_upload_id: u64,
+    completed_uploads: usize,
+    ranged_gets: usize,
+}
+
+struct Request {
+    method: String,
+    key: String,
+    query: HashMap<String, String>,
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+}
+
+struct Response {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Response {
+
+    fn new(status: u16, body: &[u8]) -> Self {
+        Self {
+            status,
+            headers: Vec::new(),
+            body: body.to_vec(),
+        }
+    }
+
+    fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    fn not_found(code: &str) -> Self {
+        Self::new(404, format!("<Error><Code>{}</Code><Message>Not found</Message></Error>", code).as_bytes())
+    }
+}
+
+/// Handle to a running mock service, which stops with the test process.
+pub struct MockS3 {
+    address: SocketAddr,
+    bucket: String,
+    state: Arc<Mutex<State>>,
+}
+
+impl MockS3 {
+
+    pub fn start(bucket: &str) -> Self {
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let state = Arc::new(Mutex::new(State::default()));
+
+        let server_state = Arc::clone(&state);
+        let server_bucket = bucket.to_string();
+
+        thread::spawn(move || {
+            for stream in listener.incoming().flatten() {
+                let state = Arc::clone(&server_state);
+                let bucket = server_bucket.clone();
+                thread::spawn(move || {
+                    let _ = serve(stream, &bucket, &state);
+                });
+            }
+        });
+
+        Self {
+            address,
+            bucket: bucket.to_string(),
+            state,
+        }
+    }
+
+    pub fn endpoint(&self) -> String {
+        format!("http://{}", self.address)
+    }
+
+    pub fn bucket(&self) -> &str {
+        &self.bucket
+    }
+
+    /// Number of multipart uploads started and neither completed nor aborted.
+    pub fn pending_uploads(&self) -> usize {
+        self.state.lock().unwrap().uploads.len()
+    }
+
+    pub fn completed_uploads(&self) -> usize {
+        self.state.lock().unwrap().completed_uploads
+    }
+
+    pub fn ranged_gets(&self) -> usize {
+        self.state.lock().unwrap().ranged_gets
+    }
+
+    pub fn keys(&self) -> Vec<String> {
+        self.state.lock().unwrap().objects.keys().cloned().collect()
+    }
+}
+
+fn serve(stream: TcpStream, bucket: &str, state: &Mutex<State>) -> std::io::Result<()> {
+
+    let mut reader = BufReader::new(stream.try_clone()?);
+    let request = match read_request(&mut reader, bucket)? {
+        Some(request) => request,
+        None => return Ok(()),
+    };
+
+    let response = handle(&request, bucket, &mut state.lock().unwrap());
+    write_response(stream, &request, response)
+}
+
+fn read_request(reader: &mut impl BufRead, bucket: &str) -> std::io::Result<Option<Request>> {
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    let mut parts = request_line.split_whitespace();
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method.to_string(), target.to_string()),
+        _ => return Ok(None),
+    };
+
+    let mut headers = HashMap::new();
+
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+        }
+    }
+
+    let content_length = headers.get("content-length").and_then(|length| length.parse::<usize>().ok()).unwrap_or(0);
+    let mut body = vec![0; content_length];
+    reader.read_exact(&mut body)?;
+
+    let (path, query) = target.split_once('?').unwrap_or((&target, ""));
+
+    let key = path.trim_start_matches('/')
+        .strip_prefix(bucket)
+        .unwrap_or("")
+        .trim_start_matches('/');
+
+    let query = query.split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (decode(name), decode(value))
+        })
+        .collect();
+
+    Ok(Some(Request {
+        method,
+        key: decode(key),
+        query,
+        headers,
+        body,
+    }))
+}
+
+fn handle(request: &Request, bucket: &str, state: &mut State) -> Response {
+
+    let query = &request.query;
+
+    match request.method.as_str() {
+
+        "GET" if query.contains_key("list-type") => {
+            let prefix = query.get("prefix").cloned().unwrap_or_default();
+            list(bucket, &prefix, state)
+        }
+
+        "GET" => match state.objects.get(&request.key) {
+            Some(content) => match request.headers.get("range").and_then(|range| parse_range(range, content.len())) {
+                Some((start, end)) => {
+                    state.ranged_gets += 1;
+                    Response::new(206, &content[start..end])
+                }
+                None => Response::new(200, content),
+            },
+            None => Response::not_found("NoSuchKey"),
+        },
+
+        "HEAD" => match state.objects.get(&request.key) {
+            Some(content) => Response::new(200, &[])
+                .header("Content-Length", &content.len().to_string())
+                .header("Last-Modified", &Utc::now().to_rfc2822()),
+            None => Response::new(404, &[]),
+        },
+
+        "PUT" if query.contains_key("uploadId") => {
+            let part_number = query.get("partNumber").and_then(|number| number.parse::<u32>().ok()).unwrap_or(0);
+
+            match state.uploads.get_mut(&query["uploadId"]) {
+                Some(parts) => {
+                    parts.insert(part_number, request.body.clone());
+                    Response::new(200, &[]).header("ETag", &format!("\"part-{}\"", part_number))
+                }
+                None => Response::not_found("NoSuchUpload"),
+            }
+        }
+
+        "PUT" => match request.headers.get("x-amz-copy-source") {
+            Some(source) => {
+                let source = decode(source);
+                let source_key = source.trim_start_matches('/').strip_prefix(bucket).unwrap_or("").trim_start_matches('/');
+
+                match state.objects.get(source_key).cloned() {
+                    Some(content) => {
+                        state.objects.insert(request.key.clone(), content);
+                        let body = format!("<CopyObjectResult><LastModified>{}</LastModified><ETag>\"copy\"</ETag></CopyObjectResult>", now());
+                        Response::new(200, body.as_bytes())
+                    }
+                    None => Response::not_found("NoSuchKey"),
+                }
+            }
+            None => {
+                state.objects.insert(request.key.clone(), request.body.clone());
+                Response::new(200, &[]).header("ETag", "\"object\"")
+            }
+        },
+
+        "POST" if query.contains_key("uploads") => {
+            state.next_upload_id += 1;
+            let upload_id = format!("upload-{}", state.next_upload_id);
+            state.uploads.insert(upload_id.clone(), BTreeMap::new());
+
+            let body = format!(
+                "<InitiateMultipartUploadResult><Bucket>{}</Bucket><Key>{}</Key><UploadId>{}</UploadId></InitiateMultipartUploadResult>",
+                bucket, escape(&request.key), upload_id
+            );
+            Response::new(200, body.as_bytes())
+        }
+
+        "POST" if query.contains_key("uploadId") => match state.uploads.remove(&query["uploadId"]) {
+            Some(parts) => {
+                let content = parts.into_values().flatten().collect::<Vec<u8>>();
+                state.objects.insert(request.key.clone(), content);
+                state.completed_uploads += 1;
+
+                let body = format!(
+                    "<CompleteMultipartUploadResult><Bucket>{}</Bucket><Key>{}</Key><ETag>\"upload\"</ETag></CompleteMultipartUploadResult>",
+                    bucket, escape(&request.key)
+                );
+                Response::new(200, body.as_bytes())
+            }
+            None => Response::not_found("NoSuchUpload"),
+        },
+
+        "DELETE" if query.contains_key("uploadId") => {
+            state.uploads.remove(&query["uploadId"]);
+            Response::new(204, &[])
+        }
+
+        "DELETE" => {
+            state.objects.remove(&request.key);
+            Response::new(204, &[])
+        }
+
+        _ => Response::new(405, &[]),
+    }
+}
+
+fn list(bucket: &str, prefix: &str, state: &State) -> Response {
+
+    let contents = state.objects.iter()
+        .filter(|(key, _)| key.starts_with(prefix))
+        .map(|(key, content)| format!(
+            "<Contents><Key>{}</Key><LastModified>{}</LastModified><ETag>\"object\"</ETag><Size>{}</Size><StorageClass>STANDARD</StorageClass></Contents>",
+            escape(key), now(), content.len()
+        ))
+        .collect::<String>();
+
+    let body = format!(
+        "<ListBucketResult><Name>{}</Name><Prefix>{}</Prefix><IsTruncated>false</IsTruncated>{}</ListBucketResult>",
+        bucket, escape(prefix), contents
+    );
+
+    Response::new(200, body.as_bytes())
+}
+
+fn write_response(mut stream: TcpStream, request: &Request, response: Response) -> std::io::Result<()> {
+
+    let mut head = format!("HTTP/1.1 {} Mock\r\nConnection: close\r\n", response.status);
+
+    for (name, value) in &response.headers {
+        head.push_str(&format!("{}: {}\r\n", name, value));
+    }
+
+    // HEAD responses carry the size of the object without its content
+    if request.method != "HEAD" {
+        head.push_str(&format!("Content-Length: {}\r\n", response.body.len()));
+    }
+    head.push_str("\r\n");
+
+    stream.write_all(head.as_bytes())?;
+    stream.write_all(&response.body)?;
+    stream.flush()
+}
+
+/// Parses a bytes=start-end range into a half open interval.
+fn parse_range(range: &str, size: usize) -> Option<(usize, usize)> {
+
+    let (start, end) = range.strip_prefix("bytes=")?.split_once('-')?;
+    let start = start.parse::<usize>().ok()?;
+
+    let end = match end {
+        "" => size,
+        end => end.parse::<usize>().ok()?.saturating_add(1).min(size),
+    };
+
+    (start < end).then_some((start, end))
+}
+
+fn decode(value: &str) -> String {
+
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+
+    while index < bytes.len() {
+        match bytes[index] {
+            b'%' if index + 2 < bytes.len() => {
+                match u8::from_str_radix(&value[index + 1..index + 3], 16) {
+                    Ok(byte) => decoded.push(byte),
+                    Err(_) => decoded.extend_from_slice(&bytes[index..index + 3]),
+                }
+                index += 3;
+            }
+            b'+' => {
+                decoded.push(b' ');
+                index += 1;
+            }
+            byte => {
+                decoded.push(byte);
+                index += 1;
+            }
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn escape(value: &str) -> String {
+    value.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+fn now() -> String {
+    Utc::now().to_rfc3339_opts(SecondsFormat::Millis, true)
+}
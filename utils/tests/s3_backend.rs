@@ -0,0 +1,230 @@
+//! Integration tests of the S3 storage backend.
+//! They run against an in-process mock of the S3 API, or against a real service such as a local MinIO
+//! when S3_TEST_ENDPOINT is set. The bucket given by S3_TEST_BUCKET must already exist in that case,
+//! and the credentials are read from S3_TEST_ACCESS_KEY and S3_TEST_SECRET_KEY.
+#![cfg(feature = "s3")]
+
+mod mock_s3;
+
+use std::env;
+use std::io::{ErrorKind, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::Arc;
+use mock_s3::MockS3;
+use utils::constants::S3_MIN_PART_SIZE;
+use utils::file_index::FileIndex;
+use utils::search::SearchQuery;
+use utils::server_utils::server_config::S3Config;
+use utils::storage::s3_backend::S3Backend;
+use utils::storage::storage_backend::{StorageBackend, WriteMode};
+
+const TEST_ENDPOINT_ENV: &str = "S3_TEST_ENDPOINT";
+const TEST_BUCKET_ENV: &str = "S3_TEST_BUCKET";
+const TEST_ACCESS_KEY_ENV: &str = "S3_TEST_ACCESS_KEY";
+const TEST_SECRET_KEY_ENV: &str = "S3_TEST_SECRET_KEY";
+
+/// Backend under test, with the mock it talks to when no real service is configured.
+struct TestStorage {
+    mock: Option<MockS3>,
+    backend: S3Backend,
+}
+
+/// Creates a backend whose keys are isolated from the other tests by a prefix.
+fn test_storage(test_name: &str) -> TestStorage {
+
+    let prefix = format!("{}-{}", test_name, process::id());
+
+    match env::var(TEST_ENDPOINT_ENV) {
+
+        Ok(endpoint) => {
+            let bucket = env::var(TEST_BUCKET_ENV).unwrap_or("file-transfer-tests".to_string());
+            TestStorage {
+                mock: None,
+                backend: S3Backend::new(&config(&endpoint, &bucket, &prefix)).unwrap(),
+            }
+        }
+
+        Err(_) => {
+            let mock = MockS3::start("file-transfer-tests");
+            let backend = S3Backend::new(&config(&mock.endpoint(), mock.bucket(), &prefix)).unwrap();
+            TestStorage {
+                mock: Some(mock),
+                backend,
+            }
+        }
+    }
+}
+
+fn config(endpoint: &str, bucket: &str, key_prefix: &str) -> S3Config {
+    S3Config {
+        bucket: bucket.to_string(),
+        region: "us-east-1".to_string(),
+        endpoint: Some(endpoint.to_string()),
+        access_key: Some(env::var(TEST_ACCESS_KEY_ENV).unwrap_or("minioadmin".to_string())),
+        secret_key: Some(env::var(TEST_SECRET_KEY_ENV).unwrap_or("minioadmin".to_string())),
+        key_prefix: key_prefix.to_string(),
+        path_style: true,
+        part_size: S3_MIN_PART_SIZE,
+    }
+}
+
+fn write(backend: &S3Backend, path: &str, mode: WriteMode, content: &[u8]) {
+    let mut staged_write = backend.open_write(Path::new(path), mode).unwrap();
+    staged_write.write_all(content).unwrap();
+    staged_write.commit().unwrap();
+}
+
+fn read(backend: &S3Backend, path: &str) -> Vec<u8> {
+    let mut content = Vec::new();
+    backend.open_read(Path::new(path)).unwrap().read_to_end(&mut content).unwrap();
+    content
+}
+
+/// Content spanning several parts, which does not repeat with the part size.
+fn large_content() -> Vec<u8> {
+    (0..2 * S3_MIN_PART_SIZE + 12345).map(|index| (index % 251) as u8).collect()
+}
+
+#[test]
+pub fn test_s3_backend_1(){
+
+    let storage = test_storage("test-s3-backend-1");
+    let backend = &storage.backend;
+    let path = Path::new("127-0-0-1/file.txt");
+
+    let mut staged_write = backend.open_write(path, WriteMode::Create).unwrap();
+    staged_write.write_all(b"first").unwrap();
+
+    // Nothing is visible before the commit
+    assert_eq!(backend.stat(path).unwrap_err().kind(), ErrorKind::NotFound);
+    staged_write.commit().unwrap();
+
+    assert_eq!(backend.stat(path).unwrap().size, 5);
+    assert_eq!(read(backend, "127-0-0-1/file.txt"), b"first");
+    assert_eq!(backend.open_write(path, WriteMode::Create).err().unwrap().kind(), ErrorKind::AlreadyExists);
+
+    write(backend, "127-0-0-1/file.txt", WriteMode::Overwrite, b"second");
+    assert_eq!(read(backend, "127-0-0-1/file.txt"), b"second");
+
+    assert_eq!(backend.open_write(Path::new("missing.txt"), WriteMode::Overwrite).err().unwrap().kind(), ErrorKind::NotFound);
+    assert_eq!(backend.open_read(Path::new("missing.txt")).err().unwrap().kind(), ErrorKind::NotFound);
+
+}
+
+#[test]
+pub fn test_s3_backend_2(){
+
+    let storage = test_storage("test-s3-backend-2");
+    let backend = &storage.backend;
+    let path = Path::new("127-0-0-1/large.bin");
+    let content = large_content();
+
+    let mut staged_write = backend.open_write(path, WriteMode::Create).unwrap();
+
+    for chunk in content.chunks(8192) {
+        staged_write.write_all(chunk).unwrap();
+    }
+
+    // The parts already uploaded are not visible until the upload is completed
+    assert_eq!(backend.stat(path).unwrap_err().kind(), ErrorKind::NotFound);
+    staged_write.commit().unwrap();
+
+    assert_eq!(backend.stat(path).unwrap().size, content.len() as u64);
+    assert!(read(backend, "127-0-0-1/large.bin") == content);
+
+    if let Some(mock) = &storage.mock {
+        assert_eq!(mock.completed_uploads(), 1);
+        assert_eq!(mock.pending_uploads(), 0);
+        assert_eq!(mock.ranged_gets(), 3);
+    }
+
+}
+
+#[test]
+pub fn test_s3_backend_3(){
+
+    let storage = test_storage("test-s3-backend-3");
+    let backend = &storage.backend;
+    let path = Path::new("127-0-0-1/aborted.bin");
+
+    let mut staged_write = backend.open_write(path, WriteMode::Create).unwrap();
+    staged_write.write_all(&large_content()).unwrap();
+    staged_write.abort().unwrap();
+
+    // Dropping an unfinished write also aborts its upload
+    let mut staged_write = backend.open_write(path, WriteMode::Create).unwrap();
+    staged_write.write_all(&large_content()).unwrap();
+    drop(staged_write);
+
+    assert_eq!(backend.stat(path).unwrap_err().kind(), ErrorKind::NotFound);
+    assert!(backend.list(Path::new("")).unwrap().is_empty());
+
+    if let Some(mock) = &storage.mock {
+        assert_eq!(mock.pending_uploads(), 0);
+        assert_eq!(mock.completed_uploads(), 0);
+    }
+
+}
+
+#[test]
+pub fn test_s3_backend_4(){
+
+    let storage = test_storage("test-s3-backend-4");
+    let backend = &storage.backend;
+
+    write(backend, "127-0-0-1/1", WriteMode::Create, b"1");
+    write(backend, "127-0-0-1/dir/2", WriteMode::Create, b"22");
+    write(backend, "127-0-0-2/3", WriteMode::Create, b"333");
+    write(backend, ".staging/4", WriteMode::Create, b"4444");
+
+    let files = backend.list(Path::new("")).unwrap();
+    let paths = files.iter().map(|file| file.path.clone()).collect::<Vec<PathBuf>>();
+
+    assert_eq!(paths, vec![PathBuf::from("127-0-0-1/1"), PathBuf::from("127-0-0-1/dir/2"), PathBuf::from("127-0-0-2/3")]);
+    assert_eq!(files[2].size, 3);
+    assert_eq!(backend.list(Path::new("127-0-0-1")).unwrap().len(), 2);
+    assert_eq!(backend.list(Path::new(".staging")).unwrap().len(), 1);
+    assert!(backend.list(Path::new("missing")).unwrap().is_empty());
+
+    backend.rename(Path::new("127-0-0-1/1"), Path::new("127-0-0-2/1")).unwrap();
+    assert_eq!(backend.stat(Path::new("127-0-0-1/1")).unwrap_err().kind(), ErrorKind::NotFound);
+    assert_eq!(read(backend, "127-0-0-2/1"), b"1");
+
+    backend.delete(Path::new("127-0-0-2/1")).unwrap();
+    assert_eq!(backend.delete(Path::new("127-0-0-2/1")).unwrap_err().kind(), ErrorKind::NotFound);
+    assert_eq!(backend.rename(Path::new("127-0-0-2/1"), Path::new("1")).unwrap_err().kind(), ErrorKind::NotFound);
+
+}
+
+#[test]
+pub fn test_s3_backend_5(){
+
+    let storage = test_storage("test-s3-backend-5");
+    let backend = &storage.backend;
+
+    write(backend, "127-0-0-1/notes.txt", WriteMode::Create, b"find the needle\n");
+    write(backend, "127-0-0-1/other.txt", WriteMode::Create, b"nothing here\n");
+
+    // Objects stored under another prefix of the same bucket are not part of the storage
+    let other_backend = match &storage.mock {
+        Some(mock) => S3Backend::new(&config(&mock.endpoint(), mock.bucket(), "other-prefix")).unwrap(),
+        None => test_storage("test-s3-backend-5-other").backend,
+    };
+    write(&other_backend, "127-0-0-1/needle.txt", WriteMode::Create, b"");
+
+    let index = FileIndex::build(Arc::new(backend.clone())).unwrap();
+
+    assert_eq!(index.len(), 2);
+    assert!(!index.contains("needle.txt"));
+
+    let results = SearchQuery::parse(&["needle", "CONTENT"]).unwrap().search(&index, &|_| true);
+
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].path, PathBuf::from("127-0-0-1/notes.txt"));
+
+    if let Some(mock) = &storage.mock {
+        assert!(mock.keys().iter().any(|key| key.starts_with("other-prefix/")));
+    }
+
+}